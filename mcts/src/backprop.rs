@@ -1,10 +1,12 @@
 //! Interface and implementations for backpropagation of game payoffs through a
 //! search graph.
 
+use std::collections::HashSet;
 use std::iter::Iterator;
 
 use crate::game::{Game, Statistics};
 use crate::graph::{EdgeData, VertexData};
+use crate::simulation::Playout;
 use crate::ucb;
 use crate::SearchSettings;
 use log::trace;
@@ -20,7 +22,7 @@ pub trait BackpropSelector<'id>: for<'a> From<&'a SearchSettings> {
   /// search graph.
   fn select<G: Game, R: Rng>(
     &self,
-    graph: &search_graph::view::View<'_, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
     node: search_graph::view::NodeRef<'id>,
     payoff: &G::Payoff,
     rng: &mut R,
@@ -47,7 +49,7 @@ impl<'id> BackpropSelector<'id> for FirstParentSelector {
 
   fn select<G: Game, R: Rng>(
     &self,
-    graph: &search_graph::view::View<'_, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
     node: search_graph::view::NodeRef<'id>,
     _payoff: &G::Payoff,
     _rng: &mut R,
@@ -75,7 +77,7 @@ impl<'id> BackpropSelector<'id> for RandomParentSelector {
 
   fn select<G: Game, R: Rng>(
     &self,
-    graph: &search_graph::view::View<'_, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
     node: search_graph::view::NodeRef<'id>,
     _payoff: &G::Payoff,
     rng: &mut R,
@@ -87,12 +89,15 @@ impl<'id> BackpropSelector<'id> for RandomParentSelector {
 /// Returns an iterator that traverses the game graph upwards from `node` up to
 /// the root vertices of the search graph.
 ///
+/// Each vertex has its parents selected at most once, so that traversal ends
+/// even if the search graph has cycles.
+///
 /// The caller may update the statistics of each edge produced by this
 /// iterator. Keep in mind that the iterator is lazy, and the edges that it
 /// yields may be affected by statistics updates if they are applied during
 /// iteration.
 pub fn backprop_iter<'a, 'b, 'id, G, S, R>(
-  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   node: search_graph::view::NodeRef<'id>,
   payoff: &'b G::Payoff,
   selector: &'b S,
@@ -114,9 +119,11 @@ where
   S: BackpropSelector<'id> + 'b,
   R: Rng,
 {
-  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   /// Nodes whose parent edges to traverse.
   stack: Vec<search_graph::view::NodeRef<'id>>,
+  /// Nodes whose parent edges have been or will be traversed.
+  visited: HashSet<search_graph::view::NodeRef<'id>>,
   /// Edges from most recently examined node.
   parent_edges: S::Items,
   payoff: &'b G::Payoff,
@@ -131,22 +138,34 @@ where
   R: Rng,
 {
   fn new(
-    graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
     node: search_graph::view::NodeRef<'id>,
     payoff: &'b G::Payoff,
     selector: &'b S,
     rng: &'b mut R,
   ) -> Self {
     let parent_edges = selector.select(graph, node, payoff, rng);
+    let mut visited = HashSet::new();
+    visited.insert(node);
     BackpropIter {
       graph,
       stack: vec![],
+      visited,
       parent_edges,
       payoff,
       selector,
       rng,
     }
   }
+
+  /// Queues the source of `parent` to have its own parents traversed, unless
+  /// it has already been.
+  fn push_source(&mut self, parent: search_graph::view::EdgeRef<'id>) {
+    let source = self.graph.edge_source(parent);
+    if self.visited.insert(source) {
+      self.stack.push(source);
+    }
+  }
 }
 
 impl<'a, 'b, 'id, G, S, R> Iterator for BackpropIter<'a, 'b, 'id, G, S, R>
@@ -160,7 +179,7 @@ where
   fn next(&mut self) -> Option<Self::Item> {
     while let Some(parent) = self.parent_edges.next() {
       if !self.graph.edge_data(parent).mark_backprop_traversal() {
-        self.stack.push(self.graph.edge_source(parent));
+        self.push_source(parent);
         return Some(parent);
       }
     }
@@ -171,7 +190,7 @@ where
           .select(self.graph, node, self.payoff, self.rng);
       while let Some(parent) = self.parent_edges.next() {
         if !self.graph.edge_data(parent).mark_backprop_traversal() {
-          self.stack.push(self.graph.edge_source(parent));
+          self.push_source(parent);
           return Some(parent);
         }
       }
@@ -191,7 +210,7 @@ where
   G: Game,
   I: Iterator<Item = search_graph::view::EdgeRef<'id>>,
 {
-  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   parents: I,
   explore_bias: f64,
 }
@@ -202,7 +221,7 @@ where
   I: Iterator<Item = search_graph::view::EdgeRef<'id>>,
 {
   pub fn new(
-    graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
     parents: I,
     explore_bias: f64,
   ) -> Self {
@@ -255,10 +274,15 @@ where
 /// updates the local statistics at each ancestor of `node` that is selected by
 /// `selector`. Ancestors are selected by recursively applying `selector` to the
 /// parent that it selects.
+///
+/// `payoff` must be the sum of the payoffs of `playouts`. Each playout is
+/// recorded separately, so that statistics count one visit per playout and
+/// see the spread of their scores.
 pub fn backprop<'a, 'id, G, S, R>(
-  graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   node: search_graph::view::NodeRef<'id>,
   payoff: &G::Payoff,
+  playouts: &[Playout<G>],
   selector: &S,
   rng: &mut R,
 ) where
@@ -273,7 +297,9 @@ pub fn backprop<'a, 'id, G, S, R>(
     .map(|edge| graph.edge_data(edge))
     .collect();
   for s in statistics {
-    s.statistics.increment(payoff);
+    for playout in playouts.iter() {
+      s.statistics.increment(&playout.payoff);
+    }
     s.mark_backprop_traversal();
   }
 }
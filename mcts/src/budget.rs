@@ -0,0 +1,185 @@
+//! Limits on the amount of work done by a round of search.
+
+use std::sync::atomic;
+use std::time::{Duration, Instant};
+
+/// Limits on a round of search. Search stops as soon as any of the limits that
+/// are set is reached, except that the first iteration is always started so
+/// that every round of search gathers some statistics.
+///
+/// A budget with no limits set is unbounded, and search with it never stops
+/// on its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchBudget {
+  /// The maximum number of iterations of rollout, scoring, backprop, and
+  /// expansion to start.
+  pub iterations: Option<u32>,
+  /// The maximum wall-clock time after which no new iterations are started.
+  /// Iterations that are already in progress when this time elapses are
+  /// allowed to finish.
+  pub duration: Option<Duration>,
+  /// The number of vertices in the search graph at or above which no new
+  /// iterations are started. Because expansion adds all the children of a
+  /// vertex at once, the graph may grow somewhat past this size.
+  ///
+  /// This counts every vertex in the graph, including those kept from earlier
+  /// rounds of search. If a graph that is kept between rounds has already
+  /// reached this size, each later round runs only its first iteration, so
+  /// this limit is not suited to graphs that are kept whole from one round to
+  /// the next.
+  pub node_count: Option<usize>,
+}
+
+impl SearchBudget {
+  /// Returns a budget that is limited to `iterations` iterations.
+  pub fn iterations(iterations: u32) -> Self {
+    SearchBudget {
+      iterations: Some(iterations),
+      ..Default::default()
+    }
+  }
+
+  /// Returns a budget that is limited to the given wall-clock time.
+  pub fn duration(duration: Duration) -> Self {
+    SearchBudget {
+      duration: Some(duration),
+      ..Default::default()
+    }
+  }
+
+  /// Returns a budget that is limited to a search graph of `node_count`
+  /// vertices.
+  pub fn node_count(node_count: usize) -> Self {
+    SearchBudget {
+      node_count: Some(node_count),
+      ..Default::default()
+    }
+  }
+
+  /// Returns `true` iff at least one limit is set.
+  pub fn is_bounded(&self) -> bool {
+    self.iterations.is_some() || self.duration.is_some() || self.node_count.is_some()
+  }
+
+  /// Starts the clock on this budget. The returned tracker is used to decide
+  /// whether each new iteration of search may begin.
+  pub fn start(&self) -> BudgetTracker {
+    BudgetTracker {
+      iterations: self.iterations,
+      deadline: self.duration.map(|d| Instant::now() + d),
+      node_count: self.node_count,
+      started: atomic::AtomicU32::new(0),
+    }
+  }
+}
+
+/// Tracks the consumption of a [SearchBudget](struct.SearchBudget.html) during
+/// a round of search.
+///
+/// A tracker may be shared by several threads that search the same graph.
+#[derive(Debug)]
+pub struct BudgetTracker {
+  iterations: Option<u32>,
+  deadline: Option<Instant>,
+  node_count: Option<usize>,
+  started: atomic::AtomicU32,
+}
+
+impl BudgetTracker {
+  /// Returns `true` and counts a new iteration as started if the budget allows
+  /// it, given that the search graph currently has `node_count` vertices.
+  /// Returns `false` if any limit has been reached and an iteration has
+  /// already been started.
+  pub fn start_iteration(&self, node_count: usize) -> bool {
+    let mut exhausted = false;
+    if let Some(limit) = self.node_count {
+      exhausted |= node_count >= limit;
+    }
+    if let Some(deadline) = self.deadline {
+      exhausted |= Instant::now() >= deadline;
+    }
+    let limit = self.iterations;
+    self
+      .started
+      .fetch_update(
+        atomic::Ordering::SeqCst,
+        atomic::Ordering::SeqCst,
+        |started| match limit {
+          _ if started == 0 => Some(1),
+          _ if exhausted => None,
+          Some(limit) if started >= limit => None,
+          _ => Some(started.saturating_add(1)),
+        },
+      )
+      .is_ok()
+  }
+
+  /// Returns the number of iterations that have been started.
+  pub fn iterations_started(&self) -> u32 {
+    self.started.load(atomic::Ordering::SeqCst)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::SearchBudget;
+  use std::time::Duration;
+
+  #[test]
+  fn iteration_limit() {
+    let tracker = SearchBudget::iterations(3).start();
+    assert!(tracker.start_iteration(0));
+    assert!(tracker.start_iteration(0));
+    assert!(tracker.start_iteration(0));
+    assert!(!tracker.start_iteration(0));
+    assert_eq!(3, tracker.iterations_started());
+  }
+
+  #[test]
+  fn node_count_limit() {
+    let tracker = SearchBudget::node_count(10).start();
+    assert!(tracker.start_iteration(8));
+    assert!(tracker.start_iteration(9));
+    assert!(!tracker.start_iteration(10));
+    assert_eq!(2, tracker.iterations_started());
+  }
+
+  #[test]
+  fn first_iteration_always_starts() {
+    let tracker = SearchBudget::iterations(0).start();
+    assert!(tracker.start_iteration(0));
+    assert!(!tracker.start_iteration(0));
+    let tracker = SearchBudget::node_count(0).start();
+    assert!(tracker.start_iteration(50));
+    assert!(!tracker.start_iteration(50));
+  }
+
+  #[test]
+  fn duration_limit() {
+    let tracker = SearchBudget::duration(Duration::from_millis(0)).start();
+    assert!(tracker.start_iteration(0));
+    assert!(!tracker.start_iteration(0));
+    let tracker = SearchBudget::duration(Duration::from_secs(3600)).start();
+    assert!(tracker.start_iteration(0));
+    assert!(tracker.start_iteration(0));
+  }
+
+  #[test]
+  fn first_limit_reached_stops_search() {
+    let budget = SearchBudget {
+      iterations: Some(5),
+      node_count: Some(100),
+      ..Default::default()
+    };
+    assert!(budget.is_bounded());
+    assert!(!SearchBudget::default().is_bounded());
+    let tracker = budget.start();
+    assert!(tracker.start_iteration(100));
+    assert!(!tracker.start_iteration(100));
+    let tracker = budget.start();
+    for _ in 0..5 {
+      assert!(tracker.start_iteration(0));
+    }
+    assert!(!tracker.start_iteration(0));
+  }
+}
@@ -0,0 +1,269 @@
+//! Bounding the size of a search graph by evicting its least-visited parts.
+//!
+//! A long search grows its search graph without limit. [evict](fn.evict.html)
+//! copies the most-visited part of a search graph into a new graph with at
+//! most a given number of vertices. A vertex whose children do not fit is
+//! copied without them and marked as unexpanded, so that search expands it
+//! again if it is reached later. The statistics of the edge that leads to such
+//! a vertex and its proven payoff, if any, are kept.
+
+use crate::game::{Game, Statistics};
+use crate::graph::{EdgeData, VertexData};
+
+use std::cmp;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::{BinaryHeap, HashSet};
+use std::mem;
+
+type SearchGraph<G> = search_graph::Graph<<G as Game>::State, VertexData<G>, EdgeData<G>>;
+
+/// A limit on the size of a search graph.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphLimit {
+  /// At most this many vertices.
+  Vertices(usize),
+  /// At most about this many bytes, as estimated by
+  /// [vertex_size](fn.vertex_size.html).
+  Bytes(usize),
+}
+
+impl GraphLimit {
+  /// Returns the number of vertices that a search graph for `G` may have
+  /// under this limit. This is at least 1.
+  pub fn vertex_count<G: Game>(&self) -> usize {
+    let count = match *self {
+      GraphLimit::Vertices(count) => count,
+      GraphLimit::Bytes(bytes) => bytes / vertex_size::<G>(),
+    };
+    cmp::max(count, 1)
+  }
+}
+
+/// Returns an estimate of the number of bytes used by each vertex of a search
+/// graph for `G`, including an edge that leads to it. Memory that is owned
+/// indirectly by game states, actions, or statistics is not counted.
+pub fn vertex_size<G: Game>() -> usize {
+  // Each state is counted twice, for the vertex and for the index that finds
+  // vertices by state. Each vertex and edge also appears in a few lists of
+  // IDs.
+  2 * mem::size_of::<G::State>()
+    + mem::size_of::<VertexData<G>>()
+    + mem::size_of::<EdgeData<G>>()
+    + 4 * mem::size_of::<usize>()
+}
+
+/// Returns a copy of the part of `graph` that can be reached from `root` with
+/// at most `vertex_limit` vertices.
+///
+/// Vertices are considered in order of the visits of the edge by which they
+/// were first reached, starting from `root`. A vertex keeps its children if
+/// they fit within `vertex_limit` along with the vertices that have already
+/// been kept, and otherwise is kept without them. So if `root` has more than
+/// `vertex_limit - 1` children, the result has only a vertex for `root`. If
+/// `root` is not in `graph`, the result is empty.
+pub fn evict<G: Game>(
+  graph: &SearchGraph<G>,
+  root: &G::State,
+  vertex_limit: usize,
+) -> SearchGraph<G> {
+  let mut evicted = crate::new_search_graph::<G>();
+  let root = match graph.find_node(root) {
+    Some(n) => n,
+    None => return evicted,
+  };
+  // Kept vertices by position in the new graph, and the positions of their
+  // IDs in `graph`.
+  let mut positions = HashMap::new();
+  positions.insert(root.get_id(), 0);
+  let mut vertices = vec![root];
+  let mut expanded = vec![false];
+  // Kept vertices whose children may yet be kept, by the visits of the edge
+  // that reached them. Ties go to the vertex that was reached first.
+  let mut frontier = BinaryHeap::new();
+  frontier.push((u32::MAX, cmp::Reverse(0)));
+  while let Some((_, cmp::Reverse(position))) = frontier.pop() {
+    if !vertices[position].get_data().expanded() {
+      continue;
+    }
+    let children = vertices[position].get_child_list();
+    let added: HashSet<usize> = children
+      .iter()
+      .map(|edge| edge.get_target().get_id())
+      .filter(|id| !positions.contains_key(id))
+      .collect();
+    if vertices.len() + added.len() > vertex_limit {
+      continue;
+    }
+    expanded[position] = true;
+    for edge in children.iter() {
+      let target = edge.get_target();
+      if let Entry::Vacant(entry) = positions.entry(target.get_id()) {
+        entry.insert(vertices.len());
+        let visits = Statistics::<G::State, G::Payoff>::visits(&edge.get_data().statistics);
+        frontier.push((visits, cmp::Reverse(vertices.len())));
+        vertices.push(target);
+        expanded.push(false);
+      }
+    }
+  }
+
+  search_graph::view::of_graph(&mut evicted, |mut view| {
+    let nodes: Vec<_> = vertices
+      .iter()
+      .zip(expanded.iter())
+      .map(|(vertex, &expanded)| {
+        let data = VertexData::default();
+        if expanded {
+          data.mark_expanded();
+          if let Some(count) = vertex.get_data().action_count() {
+            data.set_action_count(count);
+          }
+        }
+        if let Some(payoff) = vertex.get_data().solution() {
+          data.mark_solved(payoff);
+        }
+        view.append_node(vertex.get_label().clone(), data)
+      })
+      .collect();
+    for (position, vertex) in vertices.iter().enumerate() {
+      if !expanded[position] {
+        continue;
+      }
+      for edge in vertex.get_child_list().iter() {
+        let target = positions[&edge.get_target().get_id()];
+        view.append_edge(nodes[position], nodes[target], edge.get_data().clone());
+      }
+    }
+  });
+  evicted
+}
+
+#[cfg(test)]
+mod test {
+  use super::GraphLimit;
+  use crate::game::Statistics;
+  use crate::graph::{EdgeData, VertexData};
+  use crate::{backprop, simulation, tictactoe, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    VertexData<tictactoe::ScoredGame>,
+    EdgeData<tictactoe::ScoredGame>,
+  >;
+
+  fn settings() -> SearchSettings {
+    SearchSettings::default()
+  }
+
+  fn search(graph: &mut Graph, iterations: usize) {
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings(), tictactoe::State::default(), view);
+      for _ in 0..iterations {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+  }
+
+  fn evict(graph: &Graph, vertex_limit: usize) -> Graph {
+    super::evict::<tictactoe::ScoredGame>(graph, &tictactoe::State::default(), vertex_limit)
+  }
+
+  #[test]
+  fn evicted_graph_keeps_root_statistics() {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 500);
+    assert!(graph.vertex_count() > 100);
+    let evicted = evict(&graph, 100);
+    assert!(evicted.vertex_count() <= 100);
+    assert!(evicted.vertex_count() > 10);
+
+    let original = graph.find_node(&tictactoe::State::default()).unwrap();
+    let root = evicted.find_node(&tictactoe::State::default()).unwrap();
+    assert!(root.get_data().expanded());
+    assert_eq!(9, root.get_child_list().len());
+    for (a, b) in original
+      .get_child_list()
+      .iter()
+      .zip(root.get_child_list().iter())
+    {
+      assert_eq!(a.get_data().action(), b.get_data().action());
+      assert_eq!(
+        Statistics::<tictactoe::State, _>::payoff(&a.get_data().statistics),
+        Statistics::<tictactoe::State, _>::payoff(&b.get_data().statistics)
+      );
+    }
+  }
+
+  #[test]
+  fn evicted_vertices_are_unexpanded_leaves() {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 500);
+    let evicted = evict(&graph, 50);
+    let root = evicted.find_node(&tictactoe::State::default()).unwrap();
+    let mut frontier = vec![root];
+    let mut leaves = 0;
+    while let Some(vertex) = frontier.pop() {
+      if vertex.get_data().expanded() {
+        for edge in vertex.get_child_list().iter() {
+          frontier.push(edge.get_target());
+        }
+      } else {
+        assert_eq!(0, vertex.get_child_list().len());
+        let original = graph.find_node(vertex.get_label()).unwrap();
+        if original.get_data().expanded() {
+          leaves += 1;
+        }
+      }
+    }
+    // Some vertices that were expanded in the original graph have lost their
+    // children.
+    assert!(leaves > 0);
+  }
+
+  #[test]
+  fn evicted_graph_can_be_searched() {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 300);
+    let mut evicted = evict(&graph, 40);
+    let count = evicted.vertex_count();
+    search(&mut evicted, 100);
+    assert!(evicted.vertex_count() > count);
+  }
+
+  #[test]
+  fn root_that_does_not_fit_keeps_no_children() {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 20);
+    let evicted = evict(&graph, 5);
+    assert_eq!(1, evicted.vertex_count());
+    let root = evicted.find_node(&tictactoe::State::default()).unwrap();
+    assert!(!root.get_data().expanded());
+    assert_eq!(0, evict(&Graph::new(), 5).vertex_count());
+  }
+
+  #[test]
+  fn byte_limit_allows_whole_vertices() {
+    let size = super::vertex_size::<tictactoe::ScoredGame>();
+    assert_eq!(
+      10,
+      GraphLimit::Bytes(10 * size + size / 2).vertex_count::<tictactoe::ScoredGame>()
+    );
+    assert_eq!(
+      1,
+      GraphLimit::Bytes(0).vertex_count::<tictactoe::ScoredGame>()
+    );
+    assert_eq!(
+      7,
+      GraphLimit::Vertices(7).vertex_count::<tictactoe::ScoredGame>()
+    );
+  }
+}
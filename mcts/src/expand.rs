@@ -0,0 +1,164 @@
+//! Expansion of search graph vertices.
+
+use crate::game::{Game, State};
+use crate::graph::{EdgeData, VertexData};
+use crate::widening::{self, ProgressiveWidening};
+
+use log::trace;
+
+/// Expands `node` in `graph`, if it has not already been expanded.
+///
+/// This is done by playing out each of the legal moves at the node's game
+/// state, adding them to the graph if they don't already exist, and then
+/// creating an edge from the original node to the node for the resulting game
+/// state. Each edge is given the normalized prior probability of its action
+/// from `G::action_priors`.
+///
+/// `actions` is a scratch buffer that the caller reuses for every expansion,
+/// so that listing the actions of a node does not allocate.
+pub fn expand<'a, 'id, G: Game>(
+  graph: &mut search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+  actions: &mut Vec<G::Action>,
+) {
+  if graph.node_data(node).mark_expanded() {
+    trace!("rollout node was already marked as expanded; ExpandPhase does nothing");
+  } else {
+    let mut state = graph.node_state(node).clone();
+    state.fill_actions(actions);
+    graph.node_data(node).set_action_count(actions.len());
+    let priors = normalize_priors(G::action_priors(&state, actions));
+    debug_assert_eq!(actions.len(), priors.len());
+    for (action, prior) in actions.drain(..).zip(priors) {
+      append_child(graph, node, &mut state, action, prior);
+    }
+  }
+}
+
+/// Expands `node` in `graph` with [progressive
+/// widening](../widening/index.html), and widens each vertex at the source of
+/// an edge in `path`, the rollout path that led to `node`.
+///
+/// The first time that `node` is expanded, only as many children are added as
+/// `widening` allows for a vertex with no visits. Each vertex on `path` is
+/// given further children, in the order of `G::order_actions`, until it has as
+/// many as `widening` allows for its current number of visits. Priors are
+/// normalized over all the actions of a vertex, including those that have not
+/// yet been added. `actions` is a scratch buffer, as for
+/// [expand](fn.expand.html).
+pub fn expand_progressively<'a, 'id, G: Game>(
+  graph: &mut search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+  path: &[search_graph::view::EdgeRef<'id>],
+  widening: &ProgressiveWidening,
+  actions: &mut Vec<G::Action>,
+) {
+  for &edge in path.iter() {
+    let parent = graph.edge_source(edge);
+    widen(graph, parent, widening, actions);
+  }
+  if graph.node_data(node).mark_expanded() {
+    trace!("rollout node was already marked as expanded; ExpandPhase does nothing");
+  } else {
+    widen(graph, node, widening, actions);
+  }
+}
+
+/// Adds children to `node` until it has as many as `widening` allows for its
+/// current number of visits.
+fn widen<'a, 'id, G: Game>(
+  graph: &mut search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+  widening: &ProgressiveWidening,
+  actions: &mut Vec<G::Action>,
+) {
+  let child_count = graph.child_count(node);
+  let visits = widening::visits(graph, node);
+  if child_count >= widening.child_limit(visits) {
+    return;
+  }
+  let mut state = graph.node_state(node).clone();
+  state.fill_actions(actions);
+  graph.node_data(node).set_action_count(actions.len());
+  let target = widening::target_child_count(widening, visits, actions.len());
+  if child_count >= target {
+    return;
+  }
+  G::order_actions(&state, actions);
+  let priors = normalize_priors(G::action_priors(&state, actions));
+  debug_assert_eq!(actions.len(), priors.len());
+  trace!(
+    "widening vertex with {} visits from {} to {} children",
+    visits,
+    child_count,
+    target
+  );
+  for (action, prior) in actions
+    .drain(..)
+    .zip(priors)
+    .skip(child_count)
+    .take(target - child_count)
+  {
+    append_child(graph, node, &mut state, action, prior);
+  }
+}
+
+/// Adds an edge for `action` from `node`, whose game state is `state`, to the
+/// vertex for the resulting game state, which is added to `graph` if it does
+/// not already exist.
+///
+/// The action is done and then undone on `state` in place, so that `state` is
+/// only cloned if a vertex must be added for the resulting game state.
+fn append_child<'a, 'id, G: Game>(
+  graph: &mut search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+  state: &mut G::State,
+  action: G::Action,
+  prior: f32,
+) {
+  trace!("ExpandPhase adds edge for action {:?}", action);
+  trace!("ExpandState old state: {:?}", state);
+  let undo = state.do_action(&action);
+  trace!("ExpandState new state: {:?}", state);
+  let child = match graph.find_node(state) {
+    Some(n) => {
+      trace!("ExpandState expanded to existing game state");
+      n
+    }
+    None => {
+      trace!("ExpandState expanded to new game state");
+      graph.append_node(state.clone(), Default::default())
+    }
+  };
+  state.undo_action(&action, undo);
+  graph.append_edge(node, child, EdgeData::with_prior(action, prior));
+}
+
+/// Scales `weights` so that they sum to 1. If they sum to 0, they are all
+/// replaced with equal values.
+fn normalize_priors(mut weights: Vec<f32>) -> Vec<f32> {
+  let total: f32 = weights.iter().sum();
+  if total > 0.0 {
+    for w in weights.iter_mut() {
+      *w /= total;
+    }
+  } else {
+    let uniform = 1.0 / weights.len() as f32;
+    for w in weights.iter_mut() {
+      *w = uniform;
+    }
+  }
+  weights
+}
+
+#[cfg(test)]
+mod test {
+  use super::normalize_priors;
+
+  #[test]
+  fn normalize_weights() {
+    assert_eq!(vec![0.25, 0.75], normalize_priors(vec![1.0, 3.0]));
+    assert_eq!(vec![0.5, 0.5], normalize_priors(vec![0.0, 0.0]));
+    assert!(normalize_priors(vec![]).is_empty());
+  }
+}
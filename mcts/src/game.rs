@@ -1,23 +1,60 @@
 //! Base interfaces defining a game whose state space can be searched with MCTS.
 
-use std::cmp::Eq;
+use crate::normalize::Normalization;
+
+use rand::Rng;
+use std::cmp::{Eq, Ordering};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::ops::AddAssign;
 
 pub trait State: Debug + Hash + Eq + Clone + Send {
-  type Action: Clone + Debug;
-  type PlayerId: Debug;
+  type Action: Clone + Debug + Hash + Eq + Send;
+  type PlayerId: Clone + Debug + Hash + Eq + Send;
+  /// What `undo_action` needs to reverse an action, beyond the action itself.
+  type Undo;
 
   fn active_player(&self) -> &Self::PlayerId;
-  fn actions<'s>(&'s self) -> Box<dyn Iterator<Item=Self::Action> + 's>;
-  fn do_action(&mut self, action: &<Self as State>::Action);
+  /// Replaces the contents of `actions` with the actions available at this
+  /// state, always in the same order for the same state.
+  ///
+  /// Search reuses one buffer across calls in its inner loops, so actions can
+  /// be listed without allocating.
+  fn fill_actions(&self, actions: &mut Vec<Self::Action>);
+  /// Does `action`, and returns what is needed to undo it.
+  fn do_action(&mut self, action: &<Self as State>::Action) -> Self::Undo;
+  /// Reverses `action`, which must be the last action that was done on this
+  /// state, given what `do_action` returned for it. This lets search look
+  /// ahead from a state in place instead of cloning it.
+  fn undo_action(&mut self, action: &<Self as State>::Action, undo: Self::Undo);
 }
 
 pub trait Statistics<S: State, P>: Clone + Debug + Default {
+  /// Records the outcomes summed in `payoff`, counting one visit for each
+  /// game that it sums.
   fn increment(&self, payoff: &P);
   fn visits(&self) -> u32;
   fn score(&self, player: &S::PlayerId) -> f32;
+  /// Returns the accumulated payoff of all outcomes recorded so far.
+  fn payoff(&self) -> P;
+  /// Adds the outcomes recorded in `other` to these statistics.
+  fn merge(&self, other: &Self);
+  /// Returns the variance of the score per visit for `player`, or `None` if
+  /// these statistics do not track it. This is used by [variance-aware
+  /// rollout selectors](../variance/index.html).
+  ///
+  /// The default implementation returns `None`.
+  fn score_variance(&self, _player: &S::PlayerId) -> Option<f32> {
+    None
+  }
+  /// Returns `true` iff any counter in these statistics has reached the limit
+  /// of its range, so that further outcomes are not fully recorded. Tooling
+  /// may use this to warn that statistics are no longer accurate.
+  ///
+  /// The default implementation returns `false`.
+  fn saturated(&self) -> bool {
+    false
+  }
 }
 
 pub trait PayoffFn<S, P>: Debug {
@@ -25,11 +62,118 @@ pub trait PayoffFn<S, P>: Debug {
 }
 
 pub trait Game: Debug {
-  type Action: Clone + Debug;
-  type PlayerId: Debug;
+  type Action: Clone + Debug + Hash + Eq + Send;
+  type PlayerId: Clone + Debug + Hash + Eq + Send;
   type Payoff: Debug + Default + for<'a> AddAssign<&'a Self::Payoff> + Send;
   type State: State<Action = Self::Action, PlayerId = Self::PlayerId>;
   type Statistics: Statistics<Self::State, Self::Payoff>;
 
   fn payoff_of(state: &Self::State) -> Option<Self::Payoff>;
+
+  /// Returns a prior weight for each of `actions`, which are the actions
+  /// available at `state`, in the same order. Weights must be non-negative but
+  /// need not sum to 1; they are normalized when the edges for `actions` are
+  /// added to the search graph.
+  ///
+  /// The default implementation weights all actions equally.
+  fn action_priors(_state: &Self::State, actions: &[Self::Action]) -> Vec<f32> {
+    vec![1.0; actions.len()]
+  }
+
+  /// Reorders `actions`, which are the actions available at `state`, so that
+  /// the most promising come first. With [progressive
+  /// widening](../widening/index.html), children are added to a vertex in this
+  /// order.
+  ///
+  /// The default implementation sorts actions by descending prior weight from
+  /// `action_priors`, keeping actions of equal weight in their original order.
+  fn order_actions(state: &Self::State, actions: &mut Vec<Self::Action>) {
+    let priors = Self::action_priors(state, actions);
+    let mut weighted: Vec<(f32, Self::Action)> =
+      priors.into_iter().zip(actions.drain(..)).collect();
+    weighted.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    actions.extend(weighted.into_iter().map(|(_, action)| action));
+  }
+
+  /// Returns `true` iff no outcome of the game could be better for the player
+  /// to move at `state` than `payoff`. When the [solver](../solver/index.html)
+  /// proves that an action at `state` leads to such a payoff, it can mark
+  /// `state` as solved without proving the values of its other actions.
+  ///
+  /// The default implementation returns `false`, so that a state is only
+  /// solved once all of its actions have been.
+  fn is_optimal_payoff(_state: &Self::State, _payoff: &Self::Payoff) -> bool {
+    false
+  }
+
+  /// Returns the payoff of a single drawn game, or `None` if the game cannot
+  /// be drawn. With
+  /// [CyclePolicy::Draw](../rollout/enum.CyclePolicy.html#variant.Draw), a
+  /// rollout that returns to a state already on its path is scored with this
+  /// payoff.
+  ///
+  /// The default implementation returns `None`.
+  fn draw_payoff() -> Option<Self::Payoff> {
+    None
+  }
+
+  /// Returns `true` iff doing `action` at `state` only returns play to a
+  /// position that could already be played from, such as by declining a
+  /// proposal to end the game. If the game has a
+  /// [draw_payoff](#method.draw_payoff), the [solver](../solver/index.html)
+  /// counts such an action as leading to a proven draw instead of waiting for
+  /// the state that it reaches to be proven.
+  ///
+  /// The default implementation returns `false`.
+  fn repeats_position(_state: &Self::State, _action: &Self::Action) -> bool {
+    false
+  }
+
+  /// Returns the score that `Self::Statistics` records for a single game
+  /// lost by a player, from that player's point of view, when simulated
+  /// payoffs are normalised by `normalization` (or not normalised, if it is
+  /// `None`). This is the least score that one visit can add.
+  ///
+  /// During tree-parallel search, each rollout in flight through an edge is
+  /// counted as a visit with this score (see
+  /// [EdgeData](../graph/struct.EdgeData.html)), so that other rollouts are
+  /// steered away from it.
+  ///
+  /// The default implementation returns -1, the net score of a lost game when
+  /// a win scores 1 or payoffs are normalised.
+  fn loss_score(_normalization: Option<&Normalization>) -> f32 {
+    -1.0
+  }
+
+  /// Returns the score that `Self::Statistics` records for a single game won
+  /// by a player, from that player's point of view, when simulated payoffs
+  /// are normalised by `normalization` (or not normalised, if it is `None`).
+  /// This is the greatest score that one visit can add.
+  ///
+  /// Together with [loss_score](#method.loss_score), this gives the range of
+  /// scores that the [variance-aware bounds](../variance/index.html) rescale
+  /// to `[0, 1]`.
+  ///
+  /// The default implementation returns 1.
+  fn win_score(_normalization: Option<&Normalization>) -> f32 {
+    1.0
+  }
+
+  /// Returns `payoff` with its outcomes [normalised](../normalize/index.html)
+  /// by `normalization`, so that the mean score of the result for each player
+  /// lies in the same range whatever the scale of the game's payoffs. This is
+  /// applied to simulated payoffs if `SearchSettings::payoff_normalization` is
+  /// set.
+  ///
+  /// The default implementation returns `payoff` unchanged, for games whose
+  /// payoffs have no score margin to normalise.
+  fn normalize_payoff<R: Rng>(
+    payoff: &Self::Payoff,
+    _normalization: &Normalization,
+    _rng: &mut R,
+  ) -> Self::Payoff {
+    let mut copy = Self::Payoff::default();
+    copy += payoff;
+    copy
+  }
 }
@@ -1,10 +1,11 @@
 //! Graph component definitions for MCTS.
 
-use crate::game::Game;
+use crate::game::{Game, Statistics};
 
 use std::clone::Clone;
 use std::default::Default;
 use std::sync::atomic;
+use std::sync::Mutex;
 
 /// Data associated with search tree edges.
 #[derive(Debug)]
@@ -16,6 +17,12 @@ where
   action: G::Action,
   /// Statistics for payoffs that resulted from taking this edge's action.
   pub statistics: G::Statistics,
+  /// "All moves as first" (AMAF) statistics for payoffs of playouts through
+  /// this edge's source vertex in which this edge's action was taken later on
+  /// by the same player. See the [rave](../rave/index.html) module.
+  pub amaf: G::Statistics,
+  /// The prior probability of this edge's action among its siblings.
+  prior: f32,
   /// Tracks:
   ///
   /// * Whether an edge has ever been traversed. Default false. Set to true when
@@ -27,6 +34,17 @@ where
   ///   true when visited during backprop. Set to false when visited during
   ///   rollout.
   fields: atomic::AtomicUsize,
+  /// The number of rollouts currently in flight through this edge whose
+  /// payoffs have not yet been backpropagated. Each is counted as a visit that
+  /// lost the game for the player to move at this edge's source, so that
+  /// concurrent rollouts tend to spread out across the search graph.
+  virtual_loss: atomic::AtomicU32,
+  /// The bits of the `f32` score given to each pending visit from virtual
+  /// loss. See `Game::loss_score`.
+  virtual_loss_score: atomic::AtomicU32,
+  /// True iff rollout has followed this edge back to a vertex already on its
+  /// path and scored it as a draw. See `CyclePolicy::Draw`.
+  repetition: atomic::AtomicBool,
 }
 
 impl<G> Clone for EdgeData<G>
@@ -37,7 +55,14 @@ where
     EdgeData {
       action: self.action.clone(),
       statistics: self.statistics.clone(),
+      amaf: self.amaf.clone(),
+      prior: self.prior,
       fields: atomic::AtomicUsize::new(self.fields.load(atomic::Ordering::SeqCst)),
+      virtual_loss: atomic::AtomicU32::new(self.virtual_loss.load(atomic::Ordering::SeqCst)),
+      virtual_loss_score: atomic::AtomicU32::new(
+        self.virtual_loss_score.load(atomic::Ordering::SeqCst),
+      ),
+      repetition: atomic::AtomicBool::new(self.repetition()),
     }
   }
 }
@@ -46,12 +71,24 @@ impl<G> EdgeData<G>
 where
   G: Game,
 {
-  /// Creates a new edge data item that corresponds to a given game action.
+  /// Creates a new edge data item that corresponds to a given game action,
+  /// with a prior probability of 1.
   pub fn new(action: G::Action) -> Self {
+    EdgeData::with_prior(action, 1.0)
+  }
+
+  /// Creates a new edge data item that corresponds to a given game action,
+  /// which has the given prior probability of being taken.
+  pub fn with_prior(action: G::Action, prior: f32) -> Self {
     EdgeData {
-      action: action,
+      action,
       statistics: Default::default(),
+      amaf: Default::default(),
+      prior,
       fields: atomic::AtomicUsize::new(0),
+      virtual_loss: atomic::AtomicU32::new(0),
+      virtual_loss_score: atomic::AtomicU32::new(0),
+      repetition: atomic::AtomicBool::new(false),
     }
   }
 
@@ -60,6 +97,12 @@ where
     &self.action
   }
 
+  /// Returns the prior probability of this edge's action among the actions of
+  /// its source vertex.
+  pub fn prior(&self) -> f32 {
+    self.prior
+  }
+
   /// Marks the edge as having been traversed at least once (and attached to a
   /// known game state). Returns the prior value of this field.
   pub fn mark_traversal(&self) -> bool {
@@ -77,17 +120,87 @@ where
   pub fn mark_backprop_traversal(&self) -> bool {
     (self.fields.fetch_and(0b101, atomic::Ordering::SeqCst) & 0b100) != 0
   }
+
+  /// Returns `true` iff rollout has closed a cycle through this edge that was
+  /// scored as a draw.
+  pub fn repetition(&self) -> bool {
+    self.repetition.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Marks this edge as closing a cycle that was scored as a draw, so that
+  /// the [solver](../solver/index.html) counts it as leading to a proven draw.
+  pub fn mark_repetition(&self) {
+    self.repetition.store(true, atomic::Ordering::SeqCst);
+  }
+
+  /// Returns the number of pending visits that have been added to this edge
+  /// with `add_virtual_loss` and not yet removed.
+  pub fn virtual_loss(&self) -> u32 {
+    self.virtual_loss.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Adds `count` pending visits to this edge. Until they are removed, each
+  /// is treated as a visit with a score of `loss_score` for the player to move
+  /// at this edge's source, which should be the score of a lost game (see
+  /// `Game::loss_score`).
+  pub fn add_virtual_loss(&self, count: u32, loss_score: f32) {
+    self
+      .virtual_loss_score
+      .store(loss_score.to_bits(), atomic::Ordering::SeqCst);
+    self.virtual_loss.fetch_add(count, atomic::Ordering::SeqCst);
+  }
+
+  /// Removes `count` pending visits that were previously added with
+  /// `add_virtual_loss`.
+  pub fn remove_virtual_loss(&self, count: u32) {
+    self.virtual_loss.fetch_sub(count, atomic::Ordering::SeqCst);
+  }
+
+  /// Returns the number of visits recorded in this edge's statistics plus its
+  /// pending visits from virtual loss.
+  pub fn visits_with_virtual_loss(&self) -> u32 {
+    self.statistics.visits() + self.virtual_loss()
+  }
+
+  /// Returns the score recorded in this edge's statistics for `player`, who
+  /// is the player to move at this edge's source, plus the score of a lost
+  /// game for each of its pending visits from virtual loss.
+  pub fn score_with_virtual_loss(&self, player: &G::PlayerId) -> f32 {
+    let virtual_loss = self.virtual_loss();
+    let score = self.statistics.score(player);
+    if virtual_loss == 0 {
+      score
+    } else {
+      let loss_score = f32::from_bits(self.virtual_loss_score.load(atomic::Ordering::SeqCst));
+      score + virtual_loss as f32 * loss_score
+    }
+  }
 }
 
 /// Data associated with search tree vertices.
 #[derive(Debug)]
-pub struct VertexData {
+pub struct VertexData<G>
+where
+  G: Game,
+{
   /// True iff the children of this vertex have been added to the playout
   /// graph. Vertices are added in an unexpanded state.
   expanded: atomic::AtomicBool,
+  /// The number of actions available at this vertex's game state, once
+  /// expansion has listed them, or `UNKNOWN_ACTION_COUNT` until then.
+  action_count: atomic::AtomicUsize,
+  /// True iff `solution` has been set. This is checked during rollout, so it
+  /// is kept separately from `solution` to avoid taking a lock.
+  solved: atomic::AtomicBool,
+  /// The proven game-theoretic payoff of this vertex's game state under
+  /// optimal play, if it has been found by the [solver](../solver/index.html).
+  solution: Mutex<Option<G::Payoff>>,
 }
 
-impl VertexData {
+impl<G> VertexData<G>
+where
+  G: Game,
+{
   pub fn expanded(&self) -> bool {
     self.expanded.load(atomic::Ordering::SeqCst)
   }
@@ -95,20 +208,87 @@ impl VertexData {
   pub fn mark_expanded(&self) -> bool {
     self.expanded.swap(true, atomic::Ordering::SeqCst)
   }
+
+  /// Returns the number of actions available at this vertex's game state, if
+  /// expansion has listed them. The [solver](../solver/index.html) compares
+  /// this with the number of children to tell whether every action has been
+  /// added.
+  pub fn action_count(&self) -> Option<usize> {
+    match self.action_count.load(atomic::Ordering::SeqCst) {
+      UNKNOWN_ACTION_COUNT => None,
+      count => Some(count),
+    }
+  }
+
+  /// Records the number of actions available at this vertex's game state.
+  pub fn set_action_count(&self, count: usize) {
+    self.action_count.store(count, atomic::Ordering::SeqCst);
+  }
+
+  /// Returns `true` iff this vertex has a proven payoff.
+  pub fn solved(&self) -> bool {
+    self.solved.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Returns a copy of this vertex's proven payoff, if it has one.
+  pub fn solution(&self) -> Option<G::Payoff> {
+    if !self.solved() {
+      return None;
+    }
+    let solution = self.solution.lock().expect("vertex solution lock poisoned");
+    solution.as_ref().map(copy_payoff::<G>)
+  }
+
+  /// Sets this vertex's proven payoff to `payoff` if it does not already have
+  /// one. Returns `true` iff the vertex was already solved, in which case its
+  /// proven payoff is left unchanged.
+  pub fn mark_solved(&self, payoff: G::Payoff) -> bool {
+    let mut solution = self.solution.lock().expect("vertex solution lock poisoned");
+    if solution.is_some() {
+      true
+    } else {
+      *solution = Some(payoff);
+      self.solved.store(true, atomic::Ordering::SeqCst);
+      false
+    }
+  }
+}
+
+/// Stored as the action count of a vertex whose actions have not been listed.
+const UNKNOWN_ACTION_COUNT: usize = usize::MAX;
+
+/// Returns a copy of `payoff`. Payoffs need not implement `Clone`, but they can
+/// be added to the default (zero) payoff.
+pub fn copy_payoff<G: Game>(payoff: &G::Payoff) -> G::Payoff {
+  let mut copy = G::Payoff::default();
+  copy += payoff;
+  copy
 }
 
-impl Clone for VertexData {
+impl<G> Clone for VertexData<G>
+where
+  G: Game,
+{
   fn clone(&self) -> Self {
     VertexData {
       expanded: atomic::AtomicBool::new(self.expanded.load(atomic::Ordering::SeqCst)),
+      action_count: atomic::AtomicUsize::new(self.action_count.load(atomic::Ordering::SeqCst)),
+      solved: atomic::AtomicBool::new(self.solved()),
+      solution: Mutex::new(self.solution()),
     }
   }
 }
 
-impl Default for VertexData {
+impl<G> Default for VertexData<G>
+where
+  G: Game,
+{
   fn default() -> Self {
     VertexData {
       expanded: atomic::AtomicBool::new(false),
+      action_count: atomic::AtomicUsize::new(UNKNOWN_ACTION_COUNT),
+      solved: atomic::AtomicBool::new(false),
+      solution: Mutex::new(None),
     }
   }
 }
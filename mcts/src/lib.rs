@@ -1,21 +1,35 @@
-//! Single-threaded Monte Carlo tree search on directed acyclic graphs.
+//! Monte Carlo tree search on directed acyclic graphs.
 
 pub mod backprop;
+pub mod budget;
+pub mod evict;
+pub mod expand;
 pub mod game;
 pub mod graph;
+pub mod normalize;
+pub mod observe;
+pub mod parallel;
+pub mod persist;
+pub mod playout;
+pub mod puct;
+pub mod rave;
+pub mod report;
 pub mod rollout;
 pub mod simulation;
+pub mod solver;
 pub mod statistics;
 pub mod ucb;
+pub mod variance;
+pub mod widening;
 
 #[cfg(test)]
 pub(crate) mod tictactoe;
 
 use crate::backprop::BackpropSelector;
-use crate::game::{Game, State};
+use crate::game::{Game, Statistics};
 use crate::graph::{EdgeData, VertexData};
 use crate::rollout::RolloutSelector;
-use crate::simulation::Simulator;
+use crate::simulation::{Playout, Simulation, Simulator};
 
 use std::convert::From;
 use std::result::Result;
@@ -56,17 +70,69 @@ pub struct ActionStatistics<G: Game> {
   pub action: G::Action,
   /// The action's expected payoff.
   pub payoff: G::Payoff,
+  /// The number of times the action was visited.
+  pub visits: u32,
   /// The result of UCB rollout for that action (used for debugging MCTS with
   /// a UCB rollout policy).
   pub ucb: Result<UcbValue, ucb::UcbError>,
+  /// The action's proven payoff under optimal play, if the
+  /// [solver](solver/index.html) has found one.
+  pub proven: Option<G::Payoff>,
+  /// Whether the action's statistics have saturated, so that `payoff` no
+  /// longer reflects every outcome that was recorded for it.
+  pub saturated: bool,
+}
+
+impl<G: Game> ActionStatistics<G> {
+  /// Summarizes `statistics` for `action`, which is taken by `player` from a
+  /// state whose actions have been visited `parent_visits` times in total.
+  ///
+  /// This is useful for reporting on statistics that have been gathered
+  /// outside of a single search graph, such as by merging the results of
+  /// several independent searches.
+  pub fn new(
+    action: G::Action,
+    statistics: &G::Statistics,
+    player: &G::PlayerId,
+    parent_visits: u32,
+    explore_bias: f64,
+  ) -> Self {
+    let ucb = if statistics.visits() == 0 {
+      Ok(UcbValue::Select)
+    } else {
+      let value = ucb::ucb_value(
+        f64::ln(parent_visits as f64),
+        explore_bias,
+        statistics.visits() as f64,
+        statistics.score(player) as f64,
+      );
+      if value.is_nan() {
+        Err(ucb::UcbError::InvalidComputation)
+      } else {
+        Ok(UcbValue::Value(value))
+      }
+    };
+    ActionStatistics {
+      action,
+      payoff: statistics.payoff(),
+      visits: statistics.visits(),
+      ucb,
+      proven: None,
+      saturated: statistics.saturated(),
+    }
+  }
 }
 
 /// Creates a new search graph suitable for Monte Carlo tree search through the
 /// state space of the game `G`.
-pub fn new_search_graph<G: Game>() -> search_graph::Graph<G::State, VertexData, EdgeData<G>> {
-  search_graph::Graph::<G::State, VertexData, EdgeData<G>>::new()
+pub fn new_search_graph<G: Game>() -> search_graph::Graph<G::State, VertexData<G>, EdgeData<G>> {
+  search_graph::Graph::<G::State, VertexData<G>, EdgeData<G>>::new()
 }
 
+/// A view of a search graph for the game `G`.
+type SearchView<'a, 'id, G> =
+  search_graph::view::View<'a, 'id, <G as Game>::State, VertexData<G>, EdgeData<G>>;
+
 /// Settings for a round of Monte Carlo tree search.
 #[derive(Clone, Copy, Debug)]
 pub struct SearchSettings {
@@ -76,6 +142,61 @@ pub struct SearchSettings {
   pub simulation_thread_limit: u32,
   /// The exploration bias term to use for the UCB policy.
   pub explore_bias: f64,
+  /// The number of threads that concurrently run rollout, scoring, backprop,
+  /// and expansion on a shared search graph during [tree-parallel
+  /// search](parallel/fn.search.html).
+  pub search_thread_count: u32,
+  /// The number of pending visits to add to each edge traversed during
+  /// rollout in tree-parallel search, which are removed again after backprop.
+  pub virtual_loss: u32,
+  /// The schedule for blending AMAF statistics into rollout decisions made by
+  /// the [RAVE rollout selector](rave/struct.Rollout.html). If this is `None`,
+  /// AMAF statistics are not gathered.
+  pub rave_schedule: Option<rave::RaveSchedule>,
+  /// The schedule for adding children to vertices during expansion. If this
+  /// is `None`, vertices are expanded with all of their children at once.
+  pub progressive_widening: Option<widening::ProgressiveWidening>,
+  /// Parameters for the [playout policy](playout/index.html) used by the
+  /// simulator.
+  pub playout: playout::PlayoutSettings,
+  /// The maximum number of actions in a simulated playout. A playout that
+  /// reaches this depth without ending the game is scored with the
+  /// simulator's [evaluator](simulation/trait.Evaluator.html). If this is
+  /// `None`, playouts continue until the game ends.
+  pub max_playout_depth: Option<u32>,
+  /// Whether to prove the game-theoretic values of vertices with the
+  /// [solver](solver/index.html) during backprop. Rollout stops at vertices
+  /// that have been solved.
+  pub solver: bool,
+  /// The [normalisation](normalize/index.html) to apply to simulated payoffs
+  /// before they are recorded in statistics, so that `explore_bias` has the
+  /// same meaning whatever the scale of the game's payoffs. If this is
+  /// `None`, payoffs are recorded as they are.
+  pub payoff_normalization: Option<normalize::Normalization>,
+  /// How rollout handles an edge that leads back to a vertex already on the
+  /// rollout path, which can happen when game states repeat.
+  pub cycle_policy: rollout::CyclePolicy,
+}
+
+/// Settings for a small, single-threaded search: one simulation per new
+/// vertex, an exploration bias of 1, and none of the optional enhancements.
+impl Default for SearchSettings {
+  fn default() -> Self {
+    SearchSettings {
+      simulation_count: 1,
+      simulation_thread_limit: 1,
+      explore_bias: 1.0,
+      search_thread_count: 1,
+      virtual_loss: 1,
+      rave_schedule: None,
+      progressive_widening: None,
+      playout: Default::default(),
+      max_playout_depth: None,
+      solver: false,
+      payoff_normalization: None,
+      cycle_policy: Default::default(),
+    }
+  }
 }
 
 /// Recursively traverses the search graph to find a game state from which to
@@ -83,8 +204,11 @@ pub struct SearchSettings {
 pub struct RolloutPhase<'a, 'id, R: Rng, G: Game> {
   rng: R,
   settings: SearchSettings,
-  graph: search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   root_node: search_graph::view::NodeRef<'id>,
+  /// Scratch buffer for listing actions during expansion, which is handed
+  /// from phase to phase so that it is reused by every iteration.
+  actions: Vec<G::Action>,
 }
 
 impl<'a, 'id, R: Rng, G: Game> RolloutPhase<'a, 'id, R, G> {
@@ -92,7 +216,7 @@ impl<'a, 'id, R: Rng, G: Game> RolloutPhase<'a, 'id, R, G> {
     rng: R,
     settings: SearchSettings,
     root_state: G::State,
-    mut graph: search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+    mut graph: search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   ) -> Self {
     trace!("initializing rollout phase to state: {:?}", root_state);
     let root_node = match graph.find_node(&root_state) {
@@ -104,20 +228,25 @@ impl<'a, 'id, R: Rng, G: Game> RolloutPhase<'a, 'id, R, G> {
       settings,
       graph,
       root_node,
+      actions: Vec::new(),
     }
   }
 
   pub fn rollout<S: RolloutSelector>(
     mut self,
   ) -> Result<ScoringPhase<'a, 'id, R, G>, rollout::RolloutError<G, S::Error>> {
-    let result = rollout::rollout(
+    let result = rollout::rollout_path(
       &self.graph,
       self.root_node,
-      S::from(&self.settings),
+      &S::from(&self.settings),
       &mut self.rng,
+      self.settings.cycle_policy,
+    );
+    trace!(
+      "rollout finds result {:?}",
+      result.as_ref().map(|(node, _, _)| node)
     );
-    trace!("rollout finds result {:?}", result);
-    result.map(|node| {
+    result.map(|(node, path, cycle)| {
       trace!("rollout result has target node: {:?}", node);
       trace!(
         "rollout result has state: {:?}",
@@ -129,6 +258,9 @@ impl<'a, 'id, R: Rng, G: Game> RolloutPhase<'a, 'id, R, G> {
         graph: self.graph,
         root_node: self.root_node,
         rollout_node: node,
+        rollout_path: path,
+        rollout_cycle: cycle,
+        actions: self.actions,
       }
     })
   }
@@ -137,12 +269,11 @@ impl<'a, 'id, R: Rng, G: Game> RolloutPhase<'a, 'id, R, G> {
     self.root_node
   }
 
-  pub fn recover_components(
-    self,
-  ) -> (
-    R,
-    search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
-  ) {
+  pub fn graph(&self) -> &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>> {
+    &self.graph
+  }
+
+  pub fn recover_components(self) -> (R, SearchView<'a, 'id, G>) {
     (self.rng, self.graph)
   }
 }
@@ -151,9 +282,14 @@ impl<'a, 'id, R: Rng, G: Game> RolloutPhase<'a, 'id, R, G> {
 pub struct ScoringPhase<'a, 'id, R: Rng, G: Game> {
   rng: R,
   settings: SearchSettings,
-  graph: search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   root_node: search_graph::view::NodeRef<'id>,
   rollout_node: search_graph::view::NodeRef<'id>,
+  rollout_path: Vec<search_graph::view::EdgeRef<'id>>,
+  /// Whether the rollout path ends by closing a cycle, so that the rollout
+  /// node is scored as a draw.
+  rollout_cycle: bool,
+  actions: Vec<G::Action>,
 }
 
 impl<'a, 'id, R: Rng, G: Game> ScoringPhase<'a, 'id, R, G> {
@@ -165,26 +301,66 @@ impl<'a, 'id, R: Rng, G: Game> ScoringPhase<'a, 'id, R, G> {
     self.rollout_node
   }
 
-  pub fn score<S: Simulator>(mut self) -> Result<BackpropPhase<'a, 'id, R, G>, S::Error> {
-    let payoff = match G::payoff_of(self.graph.node_state(self.rollout_node())) {
+  /// Returns the number of edges that rollout followed from the root node to
+  /// the rollout node.
+  pub fn rollout_depth(&self) -> usize {
+    self.rollout_path.len()
+  }
+
+  /// Scores the rollout node with a new simulator built from the search
+  /// settings. Building a simulator may be expensive, such as for a
+  /// [PlayoutSimulator](simulation/struct.PlayoutSimulator.html), which starts
+  /// a thread pool, so search loops should build one simulator and call
+  /// `score_with` instead.
+  pub fn score<S: Simulator<G>>(self) -> Result<BackpropPhase<'a, 'id, R, G>, S::Error> {
+    let simulator = S::from(&self.settings);
+    self.score_with(&simulator)
+  }
+
+  /// Scores the rollout node, simulating playouts with `simulator` if its
+  /// payoff is not already known.
+  pub fn score_with<S: Simulator<G>>(
+    mut self,
+    simulator: &S,
+  ) -> Result<BackpropPhase<'a, 'id, R, G>, S::Error> {
+    let payoff = if self.rollout_cycle {
+      G::draw_payoff()
+    } else {
+      G::payoff_of(self.graph.node_state(self.rollout_node()))
+        .or_else(|| self.graph.node_data(self.rollout_node()).solution())
+    };
+    let (simulation, simulations) = match payoff {
       Some(p) => {
         trace!("direct payoff found: {:?}", p);
-        p
+        (Simulation::of_payoff(&p, 1), 0)
       }
       None => {
         trace!("simulating to find payoff");
-        let simulator = S::from(&self.settings);
-        simulator.simulate::<G, R>(self.graph.node_state(self.rollout_node()), &mut self.rng)?
+        let simulation =
+          simulator.simulate(self.graph.node_state(self.rollout_node()), &mut self.rng)?;
+        let simulations = simulation.playouts.len();
+        (simulation, simulations)
+      }
+    };
+    let simulation = match self.settings.payoff_normalization {
+      Some(ref normalization) => {
+        normalize::normalize_simulation(simulation, normalization, &mut self.rng)
       }
+      None => simulation,
     };
-    trace!("scoring phase finds payoff {:?}", payoff);
+    trace!("scoring phase finds payoff {:?}", simulation.payoff);
     Ok(BackpropPhase {
       rng: self.rng,
       settings: self.settings,
       graph: self.graph,
       root_node: self.root_node,
       rollout_node: self.rollout_node,
-      payoff,
+      rollout_path: self.rollout_path,
+      rollout_cycle: self.rollout_cycle,
+      payoff: simulation.payoff,
+      playouts: simulation.playouts,
+      simulations,
+      actions: self.actions,
     })
   }
 }
@@ -196,27 +372,52 @@ impl<'a, 'id, R: Rng, G: Game> ScoringPhase<'a, 'id, R, G> {
 pub struct BackpropPhase<'a, 'id, R: Rng, G: Game> {
   rng: R,
   settings: SearchSettings,
-  graph: search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   root_node: search_graph::view::NodeRef<'id>,
   rollout_node: search_graph::view::NodeRef<'id>,
+  rollout_path: Vec<search_graph::view::EdgeRef<'id>>,
+  /// Whether the rollout path ends by closing a cycle.
+  rollout_cycle: bool,
   payoff: G::Payoff,
+  playouts: Vec<Playout<G>>,
+  /// The number of playouts that were simulated to find `payoff`.
+  simulations: usize,
+  actions: Vec<G::Action>,
 }
 
 impl<'a, 'id, R: Rng, G: Game> BackpropPhase<'a, 'id, R, G> {
+  /// Returns the number of playouts that were simulated during scoring, which
+  /// is 0 if the payoff of the rollout node was known without simulation.
+  pub fn simulation_count(&self) -> usize {
+    self.simulations
+  }
+
   pub fn backprop<S: BackpropSelector<'id>>(mut self) -> ExpandPhase<'a, 'id, R, G> {
     backprop::backprop(
       &self.graph,
       self.rollout_node,
       &self.payoff,
+      &self.playouts,
       &S::from(&self.settings),
       &mut self.rng,
     );
+    if self.settings.rave_schedule.is_some() {
+      rave::update_amaf(&self.graph, &self.rollout_path, &self.playouts);
+    }
+    if self.settings.solver {
+      match self.rollout_path.last() {
+        Some(&edge) if self.rollout_cycle => solver::update_repetition(&self.graph, edge),
+        _ => solver::update(&self.graph, self.rollout_node),
+      }
+    }
     ExpandPhase {
       rng: self.rng,
       settings: self.settings,
       graph: self.graph,
       root_node: self.root_node,
       rollout_node: self.rollout_node,
+      rollout_path: self.rollout_path,
+      actions: self.actions,
     }
   }
 }
@@ -230,43 +431,31 @@ impl<'a, 'id, R: Rng, G: Game> BackpropPhase<'a, 'id, R, G> {
 pub struct ExpandPhase<'a, 'id, R: Rng, G: Game> {
   rng: R,
   settings: SearchSettings,
-  graph: search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   root_node: search_graph::view::NodeRef<'id>,
   rollout_node: search_graph::view::NodeRef<'id>,
+  rollout_path: Vec<search_graph::view::EdgeRef<'id>>,
+  actions: Vec<G::Action>,
 }
 
 impl<'a, 'id, R: Rng, G: Game> ExpandPhase<'a, 'id, R, G> {
   pub fn expand(mut self) -> RolloutPhase<'a, 'id, R, G> {
-    if self.graph.node_data(self.rollout_node).mark_expanded() {
-      trace!("rollout node was already marked as expanded; ExpandPhase does nothing");
-    } else {
-      let parent_state = self.graph.node_state(self.rollout_node).clone();
-      for action in parent_state.actions() {
-        trace!("ExpandPhase adds edge for action {:?}", action);
-        let mut child_state = parent_state.clone();
-        trace!("ExpandState old state: {:?}", child_state);
-        child_state.do_action(&action);
-        trace!("ExpandState new state: {:?}", child_state);
-        let child = match self.graph.find_node(&child_state) {
-          Some(n) => {
-            trace!("ExpandState expanded to existing game state");
-            n
-          }
-          None => {
-            trace!("ExpandState expanded to new game state");
-            self.graph.append_node(child_state, Default::default())
-          }
-        };
-        self
-          .graph
-          .append_edge(self.rollout_node, child, EdgeData::new(action));
-      }
+    match self.settings.progressive_widening {
+      Some(ref widening) => expand::expand_progressively(
+        &mut self.graph,
+        self.rollout_node,
+        &self.rollout_path,
+        widening,
+        &mut self.actions,
+      ),
+      None => expand::expand(&mut self.graph, self.rollout_node, &mut self.actions),
     }
     RolloutPhase {
       rng: self.rng,
       settings: self.settings,
       graph: self.graph,
       root_node: self.root_node,
+      actions: self.actions,
     }
   }
 }
@@ -291,14 +480,14 @@ mod test {
   }
 
   fn default_settings() -> SearchSettings {
-    SearchSettings {
-      simulation_count: 1,
-      simulation_thread_limit: 1,
-      explore_bias: 1.0,
-    }
+    SearchSettings::default()
   }
 
-  type Graph = search_graph::Graph<tictactoe::State, VertexData, EdgeData<tictactoe::ScoredGame>>;
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    VertexData<tictactoe::ScoredGame>,
+    EdgeData<tictactoe::ScoredGame>,
+  >;
 
   #[test]
   fn rollout_init() {
@@ -338,6 +527,7 @@ mod test {
         rollout_phase.root_node,
         ucb::Rollout::from(&default_settings()),
         &mut default_rng(),
+        default_settings().cycle_policy,
       )
       .unwrap();
       assert_eq!(rollout_phase.root_node(), rollout_target);
@@ -640,4 +830,44 @@ mod test {
     assert_eq!(104, child_statistics[8].score(crate::statistics::two_player::Player::One));
     assert_eq!(41, child_statistics[8].score(crate::statistics::two_player::Player::Two));
   }
+
+  /// Runs `iterations` iterations of search, reusing one simulator if `reuse`
+  /// is set, and returns the visits of the root's children.
+  fn root_visits(iterations: usize, reuse: bool) -> Vec<u32> {
+    let mut graph = Graph::new();
+    let simulator = simulation::RandomSimulator::from(&default_settings());
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout = RolloutPhase::initialize(
+        default_rng(),
+        default_settings(),
+        default_game_state(),
+        view,
+      );
+      for _ in 0..iterations {
+        let score = rollout.rollout::<ucb::Rollout>().unwrap();
+        let backprop = if reuse {
+          score.score_with(&simulator).unwrap()
+        } else {
+          score.score::<simulation::RandomSimulator>().unwrap()
+        };
+        rollout = backprop
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+    graph
+      .find_node(&default_game_state())
+      .unwrap()
+      .get_child_list()
+      .iter()
+      .map(|c| c.get_data().statistics.visits())
+      .collect()
+  }
+
+  #[test]
+  fn reused_simulator_matches_new_simulators() {
+    let visits = root_visits(300, true);
+    assert_eq!(9, visits.len());
+    assert_eq!(visits, root_visits(300, false));
+  }
 }
@@ -0,0 +1,121 @@
+//! Normalisation of payoffs before they are recorded in statistics.
+//!
+//! Rollout selectors compare the mean score of an action, which is in the
+//! units of the game's payoffs, to an exploration term scaled by
+//! `SearchSettings::explore_bias`. When payoffs are raw score margins, the
+//! right bias depends on how large those margins can get, which varies with
+//! the material on the board. A [Normalization](enum.Normalization.html) maps
+//! the margin of each payoff to a value in [0, 1] before it is recorded, so
+//! that mean scores lie in the same range in every game state.
+//!
+//! Normalisation is applied to each playout of a simulation by
+//! [Game::normalize_payoff](../game/trait.Game.html#method.normalize_payoff).
+//! Proven payoffs kept by the [solver](../solver/index.html) are not
+//! normalised.
+
+use crate::game::Game;
+use crate::simulation::{Playout, Simulation};
+
+use rand::Rng;
+
+/// Maps the score margin of a payoff, which is player one's score less player
+/// two's score, to a value in [0, 1] that is player one's share of the
+/// outcome.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+  /// Rescales margins linearly from [-max_margin, max_margin] to [0, 1].
+  /// Margins outside of that range are clamped to it.
+  Linear { max_margin: f64 },
+  /// Maps positive margins to 1, negative margins to 0, and a margin of 0 to
+  /// 1/2, so that only the winner of a game matters.
+  WinDrawLoss,
+  /// Maps margins to `1 / (1 + exp(-margin / scale))`, so that small margins
+  /// are distinguished and large ones approach a win or loss.
+  Sigmoid { scale: f64 },
+}
+
+impl Normalization {
+  /// Returns player one's share of an outcome in which its score exceeds
+  /// player two's by `margin`.
+  pub fn value(&self, margin: f64) -> f64 {
+    match *self {
+      Normalization::Linear { max_margin } => {
+        if max_margin > 0.0 {
+          (0.5 + margin / (2.0 * max_margin)).clamp(0.0, 1.0)
+        } else {
+          Normalization::WinDrawLoss.value(margin)
+        }
+      }
+      Normalization::WinDrawLoss => {
+        if margin > 0.0 {
+          1.0
+        } else if margin < 0.0 {
+          0.0
+        } else {
+          0.5
+        }
+      }
+      Normalization::Sigmoid { scale } => 1.0 / (1.0 + f64::exp(-margin / scale)),
+    }
+  }
+}
+
+/// Returns `simulation` with the payoff of each of its playouts normalised by
+/// `normalization`, and its total payoff recomputed from them.
+pub fn normalize_simulation<G: Game, R: Rng>(
+  simulation: Simulation<G>,
+  normalization: &Normalization,
+  rng: &mut R,
+) -> Simulation<G> {
+  let mut payoff = G::Payoff::default();
+  let playouts: Vec<Playout<G>> = simulation
+    .playouts
+    .into_iter()
+    .map(|playout| {
+      let normalized = G::normalize_payoff(&playout.payoff, normalization, rng);
+      payoff += &normalized;
+      Playout {
+        payoff: normalized,
+        actions: playout.actions,
+      }
+    })
+    .collect();
+  Simulation { payoff, playouts }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Normalization;
+
+  #[test]
+  fn values_lie_in_unit_interval() {
+    let normalizations = [
+      Normalization::Linear { max_margin: 32.0 },
+      Normalization::WinDrawLoss,
+      Normalization::Sigmoid { scale: 4.0 },
+    ];
+    for normalization in normalizations.iter() {
+      assert_eq!(0.5, normalization.value(0.0));
+      for &margin in [-100.0, -32.0, -1.0, 1.0, 32.0, 100.0].iter() {
+        let value = normalization.value(margin);
+        assert!(
+          (0.0..=1.0).contains(&value),
+          "{:?}({}) = {}",
+          normalization,
+          margin,
+          value
+        );
+        assert!((value - 0.5) * margin > 0.0);
+      }
+    }
+  }
+
+  #[test]
+  fn linear_rescales_and_clamps() {
+    let linear = Normalization::Linear { max_margin: 8.0 };
+    assert_eq!(0.75, linear.value(4.0));
+    assert_eq!(0.25, linear.value(-4.0));
+    assert_eq!(1.0, linear.value(20.0));
+    assert_eq!(0.0, linear.value(-20.0));
+  }
+}
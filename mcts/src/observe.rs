@@ -0,0 +1,328 @@
+//! Watching the progress of search while it runs.
+//!
+//! A [SearchObserver](trait.SearchObserver.html) is shown a snapshot of the
+//! statistics of the root's actions every so many iterations of search, such
+//! as to display live output during a long search, and may cancel search
+//! early. Search loops report their progress to a
+//! [SearchMonitor](struct.SearchMonitor.html), which checks the search budget,
+//! records measurements of search, and notifies the observer when it is due.
+
+use crate::budget::BudgetTracker;
+use crate::game::{Game, State, Statistics};
+use crate::graph::{EdgeData, VertexData};
+use crate::report::SearchRecorder;
+use crate::solver;
+use crate::ActionStatistics;
+
+use std::sync::atomic::{self, AtomicU32};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Whether search should continue after a
+/// [SearchObserver](trait.SearchObserver.html) has been notified of its
+/// progress.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Observation {
+  /// Continue until the search budget is exhausted.
+  Continue,
+  /// Cancel search before starting another iteration.
+  Stop,
+}
+
+/// A snapshot of the progress of search.
+#[derive(Debug)]
+pub struct SearchProgress<'s, G: Game> {
+  /// The number of iterations of this search that have been completed.
+  pub iterations: u32,
+  /// The wall-clock time since search started.
+  pub elapsed: Duration,
+  /// The statistics of each of the root's actions.
+  pub root_statistics: &'s [ActionStatistics<G>],
+  /// The action that has been visited the most so far, which is the current
+  /// best guess at the action to take. This is `None` if the root has not
+  /// been expanded.
+  pub best: Option<&'s ActionStatistics<G>>,
+}
+
+/// Receives snapshots of the progress of search.
+///
+/// Observers are `Send`, because tree-parallel search notifies the observer
+/// from whichever worker thread completes an iteration that is due.
+pub trait SearchObserver<G: Game>: Send {
+  /// Returns the number of iterations between notifications. This must be
+  /// greater than 0.
+  fn interval(&self) -> u32;
+
+  /// Notifies the observer of the progress of search.
+  fn observe(&mut self, progress: &SearchProgress<G>) -> Observation;
+}
+
+/// Returns the statistics of each child edge of `root` in `graph`.
+pub fn root_statistics<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  root: search_graph::view::NodeRef<'id>,
+  explore_bias: f64,
+) -> Vec<ActionStatistics<G>> {
+  let player = graph.node_state(root).active_player();
+  let parent_visits = graph
+    .children(root)
+    .map(|edge| graph.edge_data(edge).statistics.visits())
+    .sum();
+  graph
+    .children(root)
+    .map(|edge| {
+      let data = graph.edge_data(edge);
+      let mut statistics = ActionStatistics::new(
+        data.action().clone(),
+        &data.statistics,
+        player,
+        parent_visits,
+        explore_bias,
+      );
+      statistics.proven = solver::edge_solution(graph, edge);
+      statistics
+    })
+    .collect()
+}
+
+/// Tracks a round of search on behalf of search loops: whether another
+/// iteration may start, what each completed iteration did, and when to notify
+/// a [SearchObserver](trait.SearchObserver.html).
+///
+/// A monitor may be shared by several threads that search the same graph.
+/// Several monitors may share a recorder, such as to measure independent
+/// searches together, but each counts its own iterations for its observer.
+pub struct SearchMonitor<'m, G: Game> {
+  tracker: &'m BudgetTracker,
+  recorder: &'m SearchRecorder,
+  observer: Option<Mutex<&'m mut dyn SearchObserver<G>>>,
+  iterations: AtomicU32,
+}
+
+impl<'m, G: Game> SearchMonitor<'m, G> {
+  /// Creates a monitor that consumes the budget tracked by `tracker`, records
+  /// measurements with `recorder`, and notifies `observer`, if it is given.
+  ///
+  /// Panics if the interval of `observer` is 0.
+  pub fn new(
+    tracker: &'m BudgetTracker,
+    recorder: &'m SearchRecorder,
+    observer: Option<&'m mut dyn SearchObserver<G>>,
+  ) -> Self {
+    if let Some(ref o) = observer {
+      assert!(o.interval() > 0, "observer interval must be positive");
+    }
+    SearchMonitor {
+      tracker,
+      recorder,
+      observer: observer.map(Mutex::new),
+      iterations: AtomicU32::new(0),
+    }
+  }
+
+  /// Returns the recorder of measurements of search.
+  pub fn recorder(&self) -> &SearchRecorder {
+    self.recorder
+  }
+
+  /// Returns `true` and counts a new iteration as started if search has not
+  /// been cancelled and the budget allows it, given that the search graph
+  /// currently has `node_count` vertices.
+  pub fn start_iteration(&self, node_count: usize) -> bool {
+    !self.recorder.cancelled() && self.tracker.start_iteration(node_count)
+  }
+
+  /// Records a completed iteration (see
+  /// [SearchRecorder::record_iteration](../report/struct.SearchRecorder.html#method.record_iteration)).
+  /// If the observer is due to be notified, it is shown the statistics of the
+  /// child edges of `root` in `graph`, and search is cancelled if it asks to
+  /// stop.
+  pub fn complete_iteration<'a, 'id>(
+    &self,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    root: search_graph::view::NodeRef<'id>,
+    explore_bias: f64,
+    rollout_depth: usize,
+    simulations: usize,
+    nodes_added: usize,
+  ) {
+    self
+      .recorder
+      .record_iteration(rollout_depth, simulations, nodes_added);
+    let iterations = self.iterations.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+    let observer = match self.observer {
+      Some(ref o) => o,
+      None => return,
+    };
+    let mut observer = observer.lock().expect("search observer lock poisoned");
+    if iterations.checked_rem(observer.interval()) != Some(0) {
+      return;
+    }
+    let root_statistics = root_statistics(graph, root, explore_bias);
+    let mut best: Option<&ActionStatistics<G>> = None;
+    for statistics in root_statistics.iter() {
+      let better = match best {
+        Some(b) => statistics.visits > b.visits,
+        None => true,
+      };
+      if better {
+        best = Some(statistics);
+      }
+    }
+    let progress = SearchProgress {
+      iterations,
+      elapsed: self.recorder.elapsed(),
+      root_statistics: &root_statistics,
+      best,
+    };
+    if observer.observe(&progress) == Observation::Stop {
+      self.recorder.cancel();
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Observation, SearchMonitor, SearchObserver, SearchProgress};
+  use crate::budget::SearchBudget;
+  use crate::report::SearchRecorder;
+  use crate::{backprop, parallel, simulation, tictactoe, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+  use std::time::Duration;
+
+  fn settings(search_thread_count: u32) -> SearchSettings {
+    SearchSettings {
+      search_thread_count,
+      ..Default::default()
+    }
+  }
+
+  /// Remembers each snapshot, and stops search after `stop_after` of them.
+  struct Snapshots {
+    interval: u32,
+    stop_after: usize,
+    seen: Vec<(u32, Duration, usize, u32)>,
+  }
+
+  impl SearchObserver<tictactoe::ScoredGame> for Snapshots {
+    fn interval(&self) -> u32 {
+      self.interval
+    }
+
+    fn observe(&mut self, progress: &SearchProgress<tictactoe::ScoredGame>) -> Observation {
+      let best_visits = progress.best.map_or(0, |b| b.visits);
+      for statistics in progress.root_statistics.iter() {
+        assert!(statistics.visits <= best_visits);
+      }
+      self.seen.push((
+        progress.iterations,
+        progress.elapsed,
+        progress.root_statistics.len(),
+        best_visits,
+      ));
+      if self.seen.len() >= self.stop_after {
+        Observation::Stop
+      } else {
+        Observation::Continue
+      }
+    }
+  }
+
+  /// Runs search in the way that search loops use a monitor, and returns the
+  /// number of iterations that were started.
+  fn search(observer: &mut Snapshots, iterations: u32) -> u32 {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let tracker = SearchBudget::iterations(iterations).start();
+    let recorder = SearchRecorder::start();
+    let monitor = SearchMonitor::new(&tracker, &recorder, Some(observer));
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings(1), tictactoe::State::default(), view);
+      while monitor.start_iteration(rollout.graph().node_count()) {
+        let node_count = rollout.graph().node_count();
+        let scoring = rollout.rollout::<ucb::Rollout>().unwrap();
+        let depth = scoring.rollout_depth();
+        let backprop = scoring.score::<simulation::RandomSimulator>().unwrap();
+        let simulations = backprop.simulation_count();
+        rollout = backprop
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+        let added = rollout.graph().node_count() - node_count;
+        monitor.complete_iteration(
+          rollout.graph(),
+          rollout.root_node(),
+          1.0,
+          depth,
+          simulations,
+          added,
+        );
+      }
+    });
+    tracker.iterations_started()
+  }
+
+  #[test]
+  fn observer_is_notified_every_interval() {
+    let mut observer = Snapshots {
+      interval: 10,
+      stop_after: usize::MAX,
+      seen: Vec::new(),
+    };
+    assert_eq!(55, search(&mut observer, 55));
+    let iterations: Vec<u32> = observer.seen.iter().map(|s| s.0).collect();
+    assert_eq!(vec![10, 20, 30, 40, 50], iterations);
+    for window in observer.seen.windows(2) {
+      assert!(window[0].1 <= window[1].1);
+    }
+    for &(iterations, _, children, best_visits) in observer.seen.iter() {
+      assert_eq!(9, children);
+      assert!(best_visits > 0 && best_visits < iterations);
+    }
+  }
+
+  #[test]
+  fn observer_can_stop_search() {
+    let mut observer = Snapshots {
+      interval: 5,
+      stop_after: 2,
+      seen: Vec::new(),
+    };
+    assert_eq!(10, search(&mut observer, 1000));
+    assert_eq!(2, observer.seen.len());
+  }
+
+  #[test]
+  fn observer_can_stop_parallel_search() {
+    let mut observer = Snapshots {
+      interval: 20,
+      stop_after: 3,
+      seen: Vec::new(),
+    };
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let tracker = SearchBudget::iterations(100_000).start();
+    let recorder = SearchRecorder::start();
+    search_graph::view::of_graph(&mut graph, |mut view| {
+      let monitor = SearchMonitor::new(&tracker, &recorder, Some(&mut observer));
+      parallel::search_monitored::<
+        tictactoe::ScoredGame,
+        _,
+        ucb::Rollout,
+        simulation::RandomSimulator,
+        backprop::FirstParentSelector,
+      >(
+        &mut view,
+        &tictactoe::State::default(),
+        &settings(4),
+        &monitor,
+        &mut rng,
+      )
+      .unwrap();
+    });
+    assert_eq!(3, observer.seen.len());
+    assert!(recorder.report(0, 0).cancelled);
+    // Workers that had already started an iteration finish it.
+    assert!(tracker.iterations_started() < 60 + 4);
+  }
+}
@@ -0,0 +1,461 @@
+//! Tree-parallel Monte Carlo tree search.
+//!
+//! Several worker threads repeatedly run rollout, scoring, backprop, and
+//! expansion against one shared search graph. Statistics are updated
+//! atomically, so rollout, scoring, and backprop only need shared access to the
+//! graph and may proceed concurrently. Expansion adds vertices and edges, so it
+//! takes exclusive access.
+//!
+//! Each edge that a worker follows during rollout receives virtual loss (see
+//! [EdgeData](../graph/struct.EdgeData.html)) until that worker has
+//! backpropagated its payoff: the rollout is counted as a visit that lost the
+//! game for the player who chose the edge. This makes the edge look worse to
+//! the other workers, which therefore tend to explore different parts of the
+//! graph.
+
+use crate::backprop::{self, BackpropSelector};
+use crate::budget::SearchBudget;
+use crate::expand;
+use crate::game::Game;
+use crate::graph::{EdgeData, VertexData};
+use crate::normalize;
+use crate::observe::SearchMonitor;
+use crate::rave;
+use crate::report::SearchRecorder;
+use crate::rollout::{self, RolloutError, RolloutSelector};
+use crate::simulation::{Simulation, Simulator};
+use crate::solver;
+use crate::SearchSettings;
+
+use std::error::Error;
+use std::fmt;
+use std::result::Result;
+use std::sync::atomic;
+use std::sync::{Mutex, RwLock};
+
+use log::trace;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use rayon::{ThreadPoolBuildError, ThreadPoolBuilder};
+
+type SharedView<'s, 'a, 'id, G> =
+  RwLock<&'s mut search_graph::view::View<'a, 'id, <G as Game>::State, VertexData<G>, EdgeData<G>>>;
+
+/// Error type for tree-parallel search.
+pub enum SearchError<G: Game, R: Error, S: Error> {
+  /// A worker's rollout failed.
+  Rollout(RolloutError<G, R>),
+  /// A worker's simulation failed.
+  Simulation(S),
+  /// The thread pool for the workers could not be started.
+  ThreadPool(ThreadPoolBuildError),
+}
+
+impl<G: Game, R: Error, S: Error> fmt::Debug for SearchError<G, R, S> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      SearchError::Rollout(ref e) => write!(f, "Rollout error ({:?})", e),
+      SearchError::Simulation(ref e) => write!(f, "Simulation error ({:?})", e),
+      SearchError::ThreadPool(ref e) => write!(f, "Thread pool error ({:?})", e),
+    }
+  }
+}
+
+impl<G: Game, R: Error, S: Error> fmt::Display for SearchError<G, R, S> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      SearchError::Rollout(ref e) => write!(f, "Rollout error ({})", e),
+      SearchError::Simulation(ref e) => write!(f, "Simulation error ({})", e),
+      SearchError::ThreadPool(ref e) => write!(f, "Thread pool error ({})", e),
+    }
+  }
+}
+
+impl<G: Game, R: Error, S: Error> Error for SearchError<G, R, S> {
+  fn description(&self) -> &str {
+    match *self {
+      SearchError::Rollout(_) => "Rollout error",
+      SearchError::Simulation(_) => "Simulation error",
+      SearchError::ThreadPool(_) => "Thread pool error",
+    }
+  }
+
+  fn cause(&self) -> Option<&dyn Error> {
+    match *self {
+      SearchError::Rollout(ref e) => Some(e),
+      SearchError::Simulation(ref e) => Some(e),
+      SearchError::ThreadPool(ref e) => Some(e),
+    }
+  }
+}
+
+/// Runs rounds of rollout, scoring, backprop, and expansion on `graph`,
+/// starting from `root_state`, using `settings.search_thread_count` worker
+/// threads, until `budget` is exhausted.
+///
+/// Each worker draws from its own random number generator, which is seeded
+/// from `rng`. The interleaving of workers is not deterministic, so the
+/// resulting statistics may vary from run to run even for a fixed seed.
+///
+/// Returns the node for `root_state`, which is added to `graph` if it is not
+/// already present. If any worker encounters an error, the remaining workers
+/// stop at the end of their current iteration and the first error is returned.
+pub fn search<'a, 'id, G, R, RS, S, B>(
+  graph: &mut search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  root_state: &G::State,
+  settings: &SearchSettings,
+  budget: &SearchBudget,
+  rng: &mut R,
+) -> Result<search_graph::view::NodeRef<'id>, SearchError<G, RS::Error, S::Error>>
+where
+  G: Game,
+  G::State: Sync,
+  G::Action: Send + Sync,
+  G::Statistics: Send + Sync,
+  R: Rng,
+  RS: RolloutSelector,
+  RS::Error: Send,
+  S: Simulator<G> + Sync,
+  S::Error: Send,
+  B: BackpropSelector<'id>,
+{
+  let tracker = budget.start();
+  let recorder = SearchRecorder::start();
+  search_monitored::<G, R, RS, S, B>(
+    graph,
+    root_state,
+    settings,
+    &SearchMonitor::new(&tracker, &recorder, None),
+    rng,
+  )
+}
+
+/// Like [search](fn.search.html), but reports the progress of search to
+/// `monitor`, whose budget may already have been partly consumed by earlier
+/// rounds of search. Workers stop when the monitor's search is cancelled.
+pub fn search_monitored<'a, 'id, G, R, RS, S, B>(
+  graph: &mut search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  root_state: &G::State,
+  settings: &SearchSettings,
+  monitor: &SearchMonitor<G>,
+  rng: &mut R,
+) -> Result<search_graph::view::NodeRef<'id>, SearchError<G, RS::Error, S::Error>>
+where
+  G: Game,
+  G::State: Sync,
+  G::Action: Send + Sync,
+  G::Statistics: Send + Sync,
+  R: Rng,
+  RS: RolloutSelector,
+  RS::Error: Send,
+  S: Simulator<G> + Sync,
+  S::Error: Send,
+  B: BackpropSelector<'id>,
+{
+  let root = match graph.find_node(root_state) {
+    Some(n) => n,
+    None => graph.append_node(root_state.clone(), VertexData::default()),
+  };
+  let seeds: Vec<[u8; 32]> = (0..settings.search_thread_count)
+    .map(|_| {
+      let mut seed = [0u8; 32];
+      rng.fill_bytes(&mut seed);
+      seed
+    })
+    .collect();
+  // Workers share one simulator, so that its threads are started once per
+  // search rather than once per worker.
+  let simulator = S::from(settings);
+  let thread_pool = ThreadPoolBuilder::new()
+    .num_threads(settings.search_thread_count as usize)
+    .thread_name(|n| format!("mcts-search-thread-{}", n))
+    .build()
+    .map_err(SearchError::ThreadPool)?;
+  let halted = atomic::AtomicBool::new(false);
+  let failure = Mutex::new(None);
+  {
+    let shared: SharedView<'_, 'a, 'id, G> = RwLock::new(graph);
+    let (shared, simulator, halted, failure) = (&shared, &simulator, &halted, &failure);
+    thread_pool.scope(|scope| {
+      for seed in seeds.into_iter() {
+        scope.spawn(move |_| {
+          let result = run_worker::<G, RS, S, B>(
+            shared,
+            root,
+            settings,
+            simulator,
+            monitor,
+            halted,
+            Pcg64::from_seed(seed),
+          );
+          if let Err(e) = result {
+            halted.store(true, atomic::Ordering::SeqCst);
+            let mut failure = failure.lock().expect("search failure mutex poisoned");
+            if failure.is_none() {
+              *failure = Some(e);
+            }
+          }
+        });
+      }
+    });
+  }
+  match failure.into_inner().expect("search failure mutex poisoned") {
+    Some(e) => Err(e),
+    None => Ok(root),
+  }
+}
+
+/// Runs iterations of search on `graph` until `monitor` reports that the
+/// budget is exhausted or search is cancelled, or `halted` is set.
+fn run_worker<'s, 'a, 'id, G, RS, S, B>(
+  graph: &SharedView<'s, 'a, 'id, G>,
+  root: search_graph::view::NodeRef<'id>,
+  settings: &SearchSettings,
+  simulator: &S,
+  monitor: &SearchMonitor<G>,
+  halted: &atomic::AtomicBool,
+  mut rng: Pcg64,
+) -> Result<(), SearchError<G, RS::Error, S::Error>>
+where
+  G: Game,
+  RS: RolloutSelector,
+  S: Simulator<G>,
+  B: BackpropSelector<'id>,
+{
+  let rollout_selector = RS::from(settings);
+  let backprop_selector = B::from(settings);
+  let loss_score = G::loss_score(settings.payoff_normalization.as_ref());
+  let mut actions = Vec::new();
+  while !halted.load(atomic::Ordering::SeqCst) {
+    // Rollout.
+    let (node, path, cycle, state, payoff) = {
+      let view = graph.read().expect("search graph lock poisoned");
+      if view.node_data(root).solved() || !monitor.start_iteration(view.node_count()) {
+        break;
+      }
+      let (node, path, cycle) = rollout::rollout_with_virtual_loss(
+        &**view,
+        root,
+        &rollout_selector,
+        &mut rng,
+        settings.virtual_loss,
+        loss_score,
+        settings.cycle_policy,
+      )
+      .map_err(|e| {
+        monitor.recorder().record_rollout_error(&e);
+        SearchError::Rollout(e)
+      })?;
+      let state = view.node_state(node).clone();
+      let payoff = if cycle {
+        G::draw_payoff()
+      } else {
+        G::payoff_of(&state).or_else(|| view.node_data(node).solution())
+      };
+      (node, path, cycle, state, payoff)
+    };
+    trace!(
+      "worker rollout finds node {:?} at depth {}",
+      node,
+      path.len()
+    );
+
+    // Scoring. The graph is not locked while simulating.
+    let simulated = payoff.is_none();
+    let simulation = match payoff {
+      Some(p) => Ok(Simulation::of_payoff(&p, 1)),
+      None => simulator.simulate(&state, &mut rng),
+    };
+
+    // Backprop.
+    let simulations = {
+      let view = graph.read().expect("search graph lock poisoned");
+      for &edge in path.iter() {
+        view
+          .edge_data(edge)
+          .remove_virtual_loss(settings.virtual_loss);
+      }
+      let simulation = simulation.map_err(|e| {
+        monitor.recorder().record_simulation_error(&e);
+        SearchError::Simulation(e)
+      })?;
+      let simulation = match settings.payoff_normalization {
+        Some(ref normalization) => {
+          normalize::normalize_simulation(simulation, normalization, &mut rng)
+        }
+        None => simulation,
+      };
+      trace!("worker scoring finds payoff {:?}", simulation.payoff);
+      backprop::backprop(
+        &**view,
+        node,
+        &simulation.payoff,
+        &simulation.playouts,
+        &backprop_selector,
+        &mut rng,
+      );
+      if settings.rave_schedule.is_some() {
+        rave::update_amaf(&**view, &path, &simulation.playouts);
+      }
+      if settings.solver {
+        match path.last() {
+          Some(&edge) if cycle => solver::update_repetition(&**view, edge),
+          _ => solver::update(&**view, node),
+        }
+      }
+      if simulated {
+        simulation.playouts.len()
+      } else {
+        0
+      }
+    };
+
+    // Expand.
+    let nodes_added = {
+      let mut view = graph.write().expect("search graph lock poisoned");
+      let node_count = view.node_count();
+      match settings.progressive_widening {
+        Some(ref widening) => {
+          expand::expand_progressively(&mut **view, node, &path, widening, &mut actions)
+        }
+        None => expand::expand(&mut **view, node, &mut actions),
+      }
+      view.node_count() - node_count
+    };
+
+    // The observer, if any, only needs shared access to the graph.
+    let view = graph.read().expect("search graph lock poisoned");
+    monitor.complete_iteration(
+      &**view,
+      root,
+      settings.explore_bias,
+      path.len(),
+      simulations,
+      nodes_added,
+    );
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::budget::SearchBudget;
+  use crate::graph::{EdgeData, VertexData};
+  use crate::{backprop, simulation, tictactoe, ucb, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    VertexData<tictactoe::ScoredGame>,
+    EdgeData<tictactoe::ScoredGame>,
+  >;
+
+  fn parallel_settings() -> SearchSettings {
+    SearchSettings {
+      search_thread_count: 4,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn virtual_loss_makes_losing_child_less_attractive() {
+    use crate::game::{Game, Statistics};
+    use crate::statistics::two_player::ScoredPayoff;
+
+    let data: EdgeData<tictactoe::ScoredGame> = EdgeData::new(tictactoe::Action {
+      row: 0,
+      column: 0,
+      player: tictactoe::Player::X,
+    });
+    // X wins one of four visits and loses the rest.
+    for &(score_one, score_two) in [(1, 0), (0, 1), (0, 1), (0, 1)].iter() {
+      let payoff = ScoredPayoff {
+        visits: 1,
+        score_one,
+        score_two,
+      };
+      Statistics::<tictactoe::State, ScoredPayoff>::increment(&data.statistics, &payoff);
+    }
+    let player = tictactoe::Player::X;
+    let mean = |data: &EdgeData<tictactoe::ScoredGame>| {
+      data.score_with_virtual_loss(&player) / data.visits_with_virtual_loss() as f32
+    };
+    assert_eq!(-0.5, mean(&data));
+    data.add_virtual_loss(1, tictactoe::ScoredGame::loss_score(None));
+    assert_eq!(-0.6, mean(&data));
+    assert!(
+      ucb::ucb_value(
+        f64::ln(6.0),
+        1.0,
+        5.0,
+        data.score_with_virtual_loss(&player) as f64
+      ) < ucb::ucb_value(f64::ln(6.0), 1.0, 4.0, -2.0)
+    );
+    data.remove_virtual_loss(1);
+    assert_eq!(-0.5, mean(&data));
+  }
+
+  #[test]
+  fn parallel_search_visits_root() {
+    let mut graph = Graph::new();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |mut view| {
+      super::search::<
+        tictactoe::ScoredGame,
+        _,
+        ucb::Rollout,
+        simulation::RandomSimulator,
+        backprop::FirstParentSelector,
+      >(
+        &mut view,
+        &tictactoe::State::default(),
+        &parallel_settings(),
+        &SearchBudget::iterations(2000),
+        &mut rng,
+      )
+      .unwrap();
+    });
+
+    let root = graph.find_node(&tictactoe::State::default()).unwrap();
+    assert_eq!(9, root.get_child_list().len());
+    let mut root_visits = 0;
+    for child in root.get_child_list().iter() {
+      root_visits += child.get_data().statistics.visits();
+      assert_eq!(0, child.get_data().virtual_loss());
+      for grandchild in child.get_target().get_child_list().iter() {
+        assert_eq!(0, grandchild.get_data().virtual_loss());
+      }
+    }
+    // Every iteration but those that rolled out to the unexpanded root
+    // backpropagates through exactly one of the root's children.
+    assert!(root_visits <= 2000);
+    assert!(root_visits >= 2000 - parallel_settings().search_thread_count);
+  }
+
+  #[test]
+  fn parallel_search_respects_node_count() {
+    let mut graph = Graph::new();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |mut view| {
+      super::search::<
+        tictactoe::ScoredGame,
+        _,
+        ucb::Rollout,
+        simulation::RandomSimulator,
+        backprop::FirstParentSelector,
+      >(
+        &mut view,
+        &tictactoe::State::default(),
+        &parallel_settings(),
+        &SearchBudget::node_count(100),
+        &mut rng,
+      )
+      .unwrap();
+    });
+    // Workers that started an iteration before the limit was reached may still
+    // expand a vertex, and each expansion adds at most 9 vertices.
+    assert!(graph.vertex_count() >= 100);
+    assert!(graph.vertex_count() < 100 + 9 * parallel_settings().search_thread_count as usize);
+  }
+}
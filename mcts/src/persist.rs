@@ -0,0 +1,360 @@
+//! Saving search graphs to disk and loading them back.
+//!
+//! A search graph is saved as lines of tab-separated text. After a header
+//! line, there is one `vertex` line for each vertex, giving whether it has
+//! been expanded and its proven payoff from the [solver](../solver/index.html),
+//! if any. Then there is one `edge` line for each edge, giving the positions of
+//! its source and target vertices among the `vertex` lines, the prior of its
+//! action, the action itself, and its statistics and AMAF statistics.
+//!
+//! Game states are not saved. The first vertex is the root state, which the
+//! caller supplies when loading a graph, and the state of each other vertex is
+//! recomputed by playing the action of an edge that leads to it. Only the
+//! vertices that can be reached from the root are saved.
+//!
+//! Actions, payoffs, and statistics are written with the
+//! [Persist](trait.Persist.html) trait, which the statistics types in this
+//! crate implement.
+
+use crate::game::{Game, State, Statistics};
+use crate::graph::{EdgeData, VertexData};
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+/// The first line of a saved search graph.
+const HEADER: &str = "mcts-search-graph\t1";
+
+/// Written in place of the proven payoff of a vertex that has none.
+const NO_SOLUTION: &str = "-";
+
+/// A value that can be saved as part of a search graph.
+pub trait Persist: Sized {
+  /// Returns this value as text that contains no tabs or line breaks.
+  fn persist(&self) -> String;
+
+  /// Parses a value from text that was returned by `persist`, or returns
+  /// `None` if the text does not describe a valid value.
+  fn restore(text: &str) -> Option<Self>;
+}
+
+/// Parses each of the whitespace-separated fields of `text` as a number, or
+/// returns `None` if any of them is not one.
+pub(crate) fn parse_numbers<T: FromStr>(text: &str) -> Option<Vec<T>> {
+  text
+    .split_whitespace()
+    .map(|field| field.parse().ok())
+    .collect()
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Writes the part of `graph` that can be reached from `root` to `out`.
+///
+/// If `root` is not in `graph`, only the header is written, and loading the
+/// result gives an empty graph.
+pub fn save<G, W>(
+  graph: &search_graph::Graph<G::State, VertexData<G>, EdgeData<G>>,
+  root: &G::State,
+  mut out: W,
+) -> io::Result<()>
+where
+  G: Game,
+  G::Action: Persist,
+  G::Payoff: Persist,
+  G::Statistics: Persist,
+  W: Write,
+{
+  writeln!(out, "{}", HEADER)?;
+  let root = match graph.find_node(root) {
+    Some(n) => n,
+    None => return Ok(()),
+  };
+  // Number vertices in breadth-first order, so that each vertex other than the
+  // root is the target of an edge from a vertex with a lower number.
+  let mut positions = HashMap::new();
+  positions.insert(root.get_id(), 0);
+  let mut vertices = vec![root];
+  let mut next = 0;
+  while next < vertices.len() {
+    let children = vertices[next].get_child_list();
+    for edge in children.iter() {
+      let target = edge.get_target();
+      if let Entry::Vacant(entry) = positions.entry(target.get_id()) {
+        entry.insert(vertices.len());
+        vertices.push(target);
+      }
+    }
+    next += 1;
+  }
+  for vertex in vertices.iter() {
+    let data = vertex.get_data();
+    let solution = match data.solution() {
+      Some(payoff) => payoff.persist(),
+      None => NO_SOLUTION.into(),
+    };
+    writeln!(out, "vertex\t{}\t{}", data.expanded() as u8, solution)?;
+  }
+  for (source, vertex) in vertices.iter().enumerate() {
+    for edge in vertex.get_child_list().iter() {
+      let data = edge.get_data();
+      writeln!(
+        out,
+        "edge\t{}\t{}\t{}\t{}\t{}\t{}",
+        source,
+        positions[&edge.get_target().get_id()],
+        data.prior(),
+        data.action().persist(),
+        data.statistics.persist(),
+        data.amaf.persist(),
+      )?;
+    }
+  }
+  out.flush()
+}
+
+/// Reads a search graph that was written by [save](fn.save.html) from
+/// `input`. The state of its first vertex is `root`.
+///
+/// Returns an error of kind `InvalidData` if `input` is not a saved search
+/// graph, or if the actions in it do not lead from `root` to the vertices that
+/// they were saved with.
+pub fn load<G, R>(
+  root: &G::State,
+  input: R,
+) -> io::Result<search_graph::Graph<G::State, VertexData<G>, EdgeData<G>>>
+where
+  G: Game,
+  G::Action: Persist,
+  G::Payoff: Persist,
+  G::Statistics: Persist,
+  R: BufRead,
+{
+  let mut lines = input.lines();
+  match lines.next() {
+    Some(Ok(ref line)) if line == HEADER => (),
+    Some(Err(e)) => return Err(e),
+    _ => return Err(invalid_data("missing search graph header")),
+  }
+  let mut vertices: Vec<VertexData<G>> = Vec::new();
+  let mut edges: Vec<(usize, usize, EdgeData<G>)> = Vec::new();
+  for (index, line) in lines.enumerate() {
+    let line = line?;
+    let fields: Vec<&str> = line.split('\t').collect();
+    let parsed = match fields[..] {
+      ["vertex", expanded, solution] => restore_vertex::<G>(expanded, solution).map(|data| {
+        vertices.push(data);
+      }),
+      ["edge", source, target, prior, action, statistics, amaf] => {
+        restore_edge::<G>(source, target, prior, action, statistics, amaf).map(|edge| {
+          edges.push(edge);
+        })
+      }
+      _ => None,
+    };
+    if parsed.is_none() {
+      // Line numbers count from 1, and the header is line 1.
+      return Err(invalid_data(format!(
+        "invalid search graph line {}",
+        index + 2
+      )));
+    }
+  }
+
+  let mut states: Vec<Option<G::State>> = vec![None; vertices.len()];
+  if let Some(state) = states.first_mut() {
+    *state = Some(root.clone());
+  }
+  for &(source, target, ref data) in edges.iter() {
+    let mut state = match states.get(source) {
+      Some(Some(state)) => state.clone(),
+      _ => {
+        return Err(invalid_data(format!(
+          "edge from vertex {} precedes its state",
+          source
+        )))
+      }
+    };
+    state.do_action(data.action());
+    match states.get_mut(target) {
+      Some(slot @ None) => *slot = Some(state),
+      Some(Some(ref existing)) if *existing == state => (),
+      _ => {
+        return Err(invalid_data(format!(
+          "edge to vertex {} does not match its state",
+          target
+        )))
+      }
+    }
+  }
+
+  let mut graph = crate::new_search_graph::<G>();
+  search_graph::view::of_graph(&mut graph, |mut view| -> io::Result<()> {
+    let mut nodes = Vec::with_capacity(vertices.len());
+    let mut actions = Vec::new();
+    for (position, (state, data)) in states.into_iter().zip(vertices).enumerate() {
+      let state = match state {
+        Some(s) => s,
+        None => {
+          return Err(invalid_data(format!(
+            "vertex {} is not reachable",
+            position
+          )))
+        }
+      };
+      if view.find_node(&state).is_some() {
+        return Err(invalid_data(format!("vertex {} repeats a state", position)));
+      }
+      if data.expanded() {
+        state.fill_actions(&mut actions);
+        data.set_action_count(actions.len());
+      }
+      nodes.push(view.append_node(state, data));
+    }
+    for (source, target, data) in edges {
+      view.append_edge(nodes[source], nodes[target], data);
+    }
+    Ok(())
+  })?;
+  Ok(graph)
+}
+
+fn restore_vertex<G>(expanded: &str, solution: &str) -> Option<VertexData<G>>
+where
+  G: Game,
+  G::Payoff: Persist,
+{
+  let data = VertexData::default();
+  match expanded {
+    "0" => (),
+    "1" => {
+      data.mark_expanded();
+    }
+    _ => return None,
+  }
+  if solution != NO_SOLUTION {
+    data.mark_solved(G::Payoff::restore(solution)?);
+  }
+  Some(data)
+}
+
+fn restore_edge<G>(
+  source: &str,
+  target: &str,
+  prior: &str,
+  action: &str,
+  statistics: &str,
+  amaf: &str,
+) -> Option<(usize, usize, EdgeData<G>)>
+where
+  G: Game,
+  G::Action: Persist,
+  G::Statistics: Persist,
+{
+  let data = EdgeData::with_prior(G::Action::restore(action)?, prior.parse().ok()?);
+  Statistics::<G::State, G::Payoff>::merge(&data.statistics, &G::Statistics::restore(statistics)?);
+  Statistics::<G::State, G::Payoff>::merge(&data.amaf, &G::Statistics::restore(amaf)?);
+  Some((source.parse().ok()?, target.parse().ok()?, data))
+}
+
+#[cfg(test)]
+mod test {
+  use crate::game::Statistics;
+  use crate::{backprop, simulation, tictactoe, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    crate::graph::VertexData<tictactoe::ScoredGame>,
+    crate::graph::EdgeData<tictactoe::ScoredGame>,
+  >;
+
+  fn settings() -> SearchSettings {
+    SearchSettings {
+      solver: true,
+      ..Default::default()
+    }
+  }
+
+  fn search(graph: &mut Graph, iterations: usize) {
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings(), tictactoe::State::default(), view);
+      for _ in 0..iterations {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+  }
+
+  fn save(graph: &Graph) -> Vec<u8> {
+    let mut saved = Vec::new();
+    super::save(graph, &tictactoe::State::default(), &mut saved).unwrap();
+    saved
+  }
+
+  #[test]
+  fn saved_graph_loads_with_same_contents() {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 200);
+    let saved = save(&graph);
+    let loaded: Graph =
+      super::load::<tictactoe::ScoredGame, _>(&tictactoe::State::default(), &saved[..]).unwrap();
+    assert_eq!(graph.vertex_count(), loaded.vertex_count());
+    assert_eq!(graph.edge_count(), loaded.edge_count());
+    assert_eq!(saved, save(&loaded));
+
+    let root = loaded.find_node(&tictactoe::State::default()).unwrap();
+    assert!(root.get_data().expanded());
+    assert_eq!(Some(9), root.get_data().action_count());
+    let original = graph.find_node(&tictactoe::State::default()).unwrap();
+    for (a, b) in original
+      .get_child_list()
+      .iter()
+      .zip(root.get_child_list().iter())
+    {
+      assert_eq!(a.get_data().action(), b.get_data().action());
+      assert_eq!(
+        Statistics::<tictactoe::State, _>::payoff(&a.get_data().statistics),
+        Statistics::<tictactoe::State, _>::payoff(&b.get_data().statistics)
+      );
+      assert_eq!(a.get_target().get_label(), b.get_target().get_label());
+    }
+  }
+
+  #[test]
+  fn loaded_graph_can_be_searched() {
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 50);
+    let saved = save(&graph);
+    let mut loaded: Graph =
+      super::load::<tictactoe::ScoredGame, _>(&tictactoe::State::default(), &saved[..]).unwrap();
+    search(&mut loaded, 50);
+    assert!(loaded.vertex_count() > graph.vertex_count());
+  }
+
+  #[test]
+  fn invalid_input_is_rejected() {
+    let state = tictactoe::State::default();
+    let load = |text: &str| super::load::<tictactoe::ScoredGame, _>(&state, text.as_bytes());
+    assert!(load("").is_err());
+    assert!(load("mcts-search-graph\t1\nvertex\t2\t-\n").is_err());
+    // The edge's action does not lead to the state of an earlier vertex.
+    let mut graph = crate::new_search_graph::<tictactoe::ScoredGame>();
+    search(&mut graph, 20);
+    let saved = String::from_utf8(save(&graph)).unwrap();
+    let first_edge = saved.lines().find(|line| line.starts_with("edge")).unwrap();
+    let bad_edge = first_edge.replacen("\t0\t1\t", "\t0\t2\t", 1);
+    assert!(load(&saved.replacen(first_edge, &bad_edge, 1)).is_err());
+    assert!(load("mcts-search-graph\t1\n").unwrap().vertex_count() == 0);
+  }
+}
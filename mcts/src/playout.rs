@@ -0,0 +1,270 @@
+//! Policies for choosing actions during simulated playouts.
+//!
+//! A [PlayoutSimulator](../simulation/struct.PlayoutSimulator.html) plays out
+//! a game from a newly expanded vertex by repeatedly asking a
+//! [PlayoutPolicy](trait.PlayoutPolicy.html) which action to take. The stock
+//! policies choose actions uniformly at random, epsilon-greedily according to
+//! an [ActionScore](trait.ActionScore.html), or with probability given by the
+//! softmax of an `ActionScore`.
+
+use crate::game::{Game, State};
+use crate::SearchSettings;
+
+use std::cmp::Ordering;
+use std::convert::From;
+use std::f64;
+use std::marker::PhantomData;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Parameters of the stock playout policies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayoutSettings {
+  /// The probability with which [EpsilonGreedy](struct.EpsilonGreedy.html)
+  /// chooses an action uniformly at random instead of the best-scoring action.
+  pub epsilon: f64,
+  /// The temperature of [Softmax](struct.Softmax.html). Higher temperatures
+  /// make choices closer to uniform, and lower temperatures make them closer
+  /// to greedy.
+  pub temperature: f64,
+}
+
+impl Default for PlayoutSettings {
+  fn default() -> Self {
+    PlayoutSettings {
+      epsilon: 0.1,
+      temperature: 1.0,
+    }
+  }
+}
+
+/// Chooses the actions taken during a simulated playout of the game `G`.
+pub trait PlayoutPolicy<G: Game>: for<'a> From<&'a SearchSettings> + Sync {
+  /// Returns the action to take at `state`, or `None` if there are no legal
+  /// actions.
+  ///
+  /// `actions` is a scratch buffer that the caller reuses for every step of a
+  /// playout, such as to pass to `State::fill_actions`. Its contents are
+  /// unspecified both before and after the call.
+  fn choose<R: Rng>(
+    &self,
+    state: &G::State,
+    actions: &mut Vec<G::Action>,
+    rng: &mut R,
+  ) -> Option<G::Action>;
+}
+
+/// A heuristic score for taking an action in a game state. Higher scores are
+/// better for the player who takes the action.
+pub trait ActionScore<G: Game> {
+  fn score(state: &G::State, action: &G::Action) -> f64;
+}
+
+/// Chooses each legal action with equal probability.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uniform;
+
+impl<'a> From<&'a SearchSettings> for Uniform {
+  fn from(_settings: &'a SearchSettings) -> Self {
+    Uniform
+  }
+}
+
+impl<G: Game> PlayoutPolicy<G> for Uniform {
+  fn choose<R: Rng>(
+    &self,
+    state: &G::State,
+    actions: &mut Vec<G::Action>,
+    rng: &mut R,
+  ) -> Option<G::Action> {
+    state.fill_actions(actions);
+    actions.choose(rng).cloned()
+  }
+}
+
+/// Chooses an action uniformly at random with probability
+/// `PlayoutSettings::epsilon`, and otherwise chooses the action with the
+/// highest score according to `F`. Ties are broken uniformly at random.
+pub struct EpsilonGreedy<F> {
+  epsilon: f64,
+  score: PhantomData<fn() -> F>,
+}
+
+impl<'a, F> From<&'a SearchSettings> for EpsilonGreedy<F> {
+  fn from(settings: &'a SearchSettings) -> Self {
+    EpsilonGreedy {
+      epsilon: settings.playout.epsilon,
+      score: PhantomData,
+    }
+  }
+}
+
+impl<G: Game, F: ActionScore<G>> PlayoutPolicy<G> for EpsilonGreedy<F> {
+  fn choose<R: Rng>(
+    &self,
+    state: &G::State,
+    actions: &mut Vec<G::Action>,
+    rng: &mut R,
+  ) -> Option<G::Action> {
+    state.fill_actions(actions);
+    if rng.gen::<f64>() < self.epsilon {
+      return actions.choose(rng).cloned();
+    }
+    let mut best = None;
+    let mut best_score = f64::NEG_INFINITY;
+    let mut sampling_count = 0u32;
+    for action in actions.iter() {
+      let score = F::score(state, action);
+      match score.partial_cmp(&best_score) {
+        Some(Ordering::Greater) => {
+          best = Some(action);
+          best_score = score;
+          sampling_count = 1;
+        }
+        Some(Ordering::Equal) => {
+          sampling_count += 1;
+          if rng.gen_ratio(1, sampling_count) {
+            best = Some(action);
+          }
+        }
+        Some(Ordering::Less) | None => (),
+      }
+    }
+    best.or_else(|| actions.choose(rng)).cloned()
+  }
+}
+
+/// Chooses each action with probability proportional to `exp(score /
+/// temperature)`, where `score` is given by `F` and `temperature` by
+/// `PlayoutSettings::temperature`.
+pub struct Softmax<F> {
+  temperature: f64,
+  score: PhantomData<fn() -> F>,
+}
+
+impl<'a, F> From<&'a SearchSettings> for Softmax<F> {
+  fn from(settings: &'a SearchSettings) -> Self {
+    Softmax {
+      temperature: settings.playout.temperature,
+      score: PhantomData,
+    }
+  }
+}
+
+impl<G: Game, F: ActionScore<G>> PlayoutPolicy<G> for Softmax<F> {
+  fn choose<R: Rng>(
+    &self,
+    state: &G::State,
+    actions: &mut Vec<G::Action>,
+    rng: &mut R,
+  ) -> Option<G::Action> {
+    state.fill_actions(actions);
+    let weights = softmax_weights(
+      actions.iter().map(|action| F::score(state, action)),
+      self.temperature,
+    );
+    let total: f64 = weights.iter().sum();
+    if !(total > 0.0 && total.is_finite()) {
+      return actions.choose(rng).cloned();
+    }
+    let mut target = rng.gen::<f64>() * total;
+    let last = actions.len() - 1;
+    for (i, (action, weight)) in actions.iter().zip(weights).enumerate() {
+      if target < weight || i == last {
+        return Some(action.clone());
+      }
+      target -= weight;
+    }
+    None
+  }
+}
+
+/// Returns `exp((score - max_score) / temperature)` for each of `scores`.
+/// Subtracting the maximum score avoids overflow and does not change the
+/// resulting distribution.
+fn softmax_weights<I: Iterator<Item = f64> + Clone>(scores: I, temperature: f64) -> Vec<f64> {
+  let max_score = scores.clone().fold(f64::NEG_INFINITY, f64::max);
+  scores
+    .map(|score| f64::exp((score - max_score) / temperature))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{softmax_weights, ActionScore, EpsilonGreedy, PlayoutPolicy, Softmax};
+  use crate::playout::PlayoutSettings;
+  use crate::{tictactoe, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  /// Prefers the center square, then corners, then edges.
+  struct CenterFirst;
+
+  impl ActionScore<tictactoe::ScoredGame> for CenterFirst {
+    fn score(_state: &tictactoe::State, action: &tictactoe::Action) -> f64 {
+      match (action.row, action.column) {
+        (1, 1) => 2.0,
+        (0, 0) | (0, 2) | (2, 0) | (2, 2) => 1.0,
+        _ => 0.0,
+      }
+    }
+  }
+
+  fn settings(playout: PlayoutSettings) -> SearchSettings {
+    SearchSettings {
+      playout,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn greedy_chooses_best_action() {
+    let policy = EpsilonGreedy::<CenterFirst>::from(&settings(PlayoutSettings {
+      epsilon: 0.0,
+      temperature: 1.0,
+    }));
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let mut actions = Vec::new();
+    for _ in 0..20 {
+      let action = PlayoutPolicy::<tictactoe::ScoredGame>::choose(
+        &policy,
+        &tictactoe::State::default(),
+        &mut actions,
+        &mut rng,
+      )
+      .unwrap();
+      assert_eq!((1, 1), (action.row, action.column));
+    }
+  }
+
+  #[test]
+  fn softmax_prefers_higher_scores() {
+    let policy = Softmax::<CenterFirst>::from(&settings(PlayoutSettings {
+      epsilon: 0.0,
+      temperature: 0.5,
+    }));
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let mut actions = Vec::new();
+    let mut counts = [[0u32; 3]; 3];
+    for _ in 0..2000 {
+      let action = PlayoutPolicy::<tictactoe::ScoredGame>::choose(
+        &policy,
+        &tictactoe::State::default(),
+        &mut actions,
+        &mut rng,
+      )
+      .unwrap();
+      counts[action.row][action.column] += 1;
+    }
+    assert!(counts[1][1] > counts[0][0]);
+    assert!(counts[0][0] > counts[0][1]);
+  }
+
+  #[test]
+  fn softmax_weights_are_stable() {
+    let weights = softmax_weights(vec![1000.0, 999.0].into_iter(), 1.0);
+    assert_eq!(1.0, weights[0]);
+    assert!((weights[1] - f64::exp(-1.0)).abs() < 1e-12);
+  }
+}
@@ -0,0 +1,261 @@
+//! Predictor + upper confidence bound (PUCT) algorithm for graph search.
+//!
+//! PUCT weights the exploration term of each child by the prior probability
+//! of its action (see [Game::action_priors](../game/trait.Game.html)), so
+//! that search concentrates on the actions that the prior favors instead of
+//! visiting every child once before any child is visited twice.
+
+use crate::backprop::BackpropSelector;
+use crate::game::{Game, State};
+use crate::graph::{EdgeData, VertexData};
+use crate::rollout::RolloutSelector;
+use crate::ucb::{self, UcbError};
+use rand::Rng;
+use search_graph;
+
+use std::f64;
+use std::result::Result;
+
+/// Returns the PUCT value of a child whose action has the given `prior`
+/// probability, with `child_visits` visits and a total score of `child_score`
+/// for the player to move at its parent.
+///
+/// Unvisited children have a mean score of 0.
+pub fn puct_value(
+  sqrt_parent_visits: f64,
+  explore_bias: f64,
+  prior: f64,
+  child_visits: f64,
+  child_score: f64,
+) -> f64 {
+  let mean_score = if child_visits == 0.0 {
+    0.0
+  } else {
+    child_score / child_visits
+  };
+  mean_score + explore_bias * prior * sqrt_parent_visits / (1.0 + child_visits)
+}
+
+/// Returns `sqrt` of the total visits to the children of `parent`, or 1 if
+/// there are none, so that priors still order the children of a vertex that
+/// has not yet been visited.
+fn sqrt_parent_visits<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  parent: search_graph::view::NodeRef<'id>,
+) -> f64 {
+  let parent_visits: u32 = graph
+    .children(parent)
+    .map(|child| graph.edge_data(child).visits_with_virtual_loss())
+    .sum();
+  f64::sqrt(std::cmp::max(parent_visits, 1) as f64)
+}
+
+/// Returns the PUCT value of `child`.
+///
+/// Any virtual loss on `child` is counted as visits that lost the game for the
+/// player to move at its parent.
+pub fn child_score<'a, 'id, G: Game>(
+  sqrt_parent_visits: f64,
+  explore_bias: f64,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  child: search_graph::view::EdgeRef<'id>,
+) -> f64 {
+  let data = graph.edge_data(child);
+  let child_score =
+    data.score_with_virtual_loss(graph.node_state(graph.edge_source(child)).active_player()) as f64;
+  puct_value(
+    sqrt_parent_visits,
+    explore_bias,
+    data.prior() as f64,
+    data.visits_with_virtual_loss() as f64,
+    child_score,
+  )
+}
+
+/// Returns the child edge of `parent` that is best according to the PUCT
+/// criterion. Ties are broken uniformly at random.
+///
+/// This function will panic if `parent` has no children.
+pub fn find_best_child<'a, 'id, G, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  parent: search_graph::view::NodeRef<'id>,
+  explore_bias: f64,
+  rng: &mut R,
+) -> Result<search_graph::view::EdgeRef<'id>, UcbError>
+where
+  G: Game,
+  R: Rng,
+{
+  let sqrt_parent_visits = sqrt_parent_visits(graph, parent);
+  ucb::find_best_child_by(graph, parent, rng, |child| {
+    child_score(sqrt_parent_visits, explore_bias, graph, child)
+  })
+}
+
+/// Returns `true` iff `e` could be selected by the PUCT policy during rollout
+/// from its parent vertex (i.e., no sibling of `e` has a higher PUCT value).
+pub fn is_best_child<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  e: search_graph::view::EdgeRef<'id>,
+  explore_bias: f64,
+) -> bool {
+  let sqrt_parent_visits = sqrt_parent_visits(graph, graph.edge_source(e));
+  ucb::is_best_child_by(graph, e, |child| {
+    child_score(sqrt_parent_visits, explore_bias, graph, child)
+  })
+}
+
+/// [Rollout selector](../rollout/trait.RolloutSelector.html) that chooses a
+/// child with the highest PUCT value.
+///
+/// The exploration bias is taken from `SearchSettings::explore_bias`. If more
+/// than one child has the same value, chooses one such child at random.
+pub struct Rollout {
+  explore_bias: f64,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for Rollout {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    Rollout {
+      explore_bias: settings.explore_bias,
+    }
+  }
+}
+
+impl RolloutSelector for Rollout {
+  type Error = UcbError;
+
+  fn select<'a, 'id, G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    parent: search_graph::view::NodeRef<'id>,
+    rng: &mut R,
+  ) -> Result<search_graph::view::EdgeRef<'id>, UcbError> {
+    find_best_child(graph, parent, self.explore_bias, rng)
+  }
+}
+
+/// [Backprop selector](../backprop/trait.BackpropSelector.html) that traverses
+/// upward edges that would have been selected by the [PUCT
+/// rollout](struct.Rollout.html) policy.
+pub struct BestParentBackprop {
+  explore_bias: f64,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for BestParentBackprop {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    BestParentBackprop {
+      explore_bias: settings.explore_bias,
+    }
+  }
+}
+
+impl<'id> BackpropSelector<'id> for BestParentBackprop {
+  type Items = std::vec::IntoIter<search_graph::view::EdgeRef<'id>>;
+
+  fn select<G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    node: search_graph::view::NodeRef<'id>,
+    _payoff: &G::Payoff,
+    _rng: &mut R,
+  ) -> Self::Items {
+    ucb::best_parents(graph, node, |e| is_best_child(graph, e, self.explore_bias))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::puct_value;
+  use crate::game::Game;
+  use crate::graph::{EdgeData, VertexData};
+  use crate::{simulation, statistics, tictactoe, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  /// Tic-tac-toe with a prior that strongly favors the center square.
+  #[derive(Debug)]
+  struct CenterPriorGame {}
+
+  impl Game for CenterPriorGame {
+    type Action = tictactoe::Action;
+    type PlayerId = tictactoe::Player;
+    type Payoff = statistics::two_player::ScoredPayoff;
+    type State = tictactoe::State;
+    type Statistics = statistics::two_player::ScoredStatistics<tictactoe::Player>;
+
+    fn payoff_of(state: &tictactoe::State) -> Option<Self::Payoff> {
+      tictactoe::ScoredGame::payoff_of(state)
+    }
+
+    fn action_priors(_state: &tictactoe::State, actions: &[tictactoe::Action]) -> Vec<f32> {
+      actions
+        .iter()
+        .map(|a| {
+          if a.row == 1 && a.column == 1 {
+            92.0
+          } else {
+            1.0
+          }
+        })
+        .collect()
+    }
+  }
+
+  #[test]
+  fn search_follows_prior() {
+    let settings = SearchSettings::default();
+    let mut graph = search_graph::Graph::<
+      tictactoe::State,
+      VertexData<CenterPriorGame>,
+      EdgeData<CenterPriorGame>,
+    >::new();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings, tictactoe::State::default(), view);
+      for _ in 0..2 {
+        rollout = rollout
+          .rollout::<super::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<super::BestParentBackprop>()
+          .expand();
+      }
+    });
+
+    let root = graph.find_node(&tictactoe::State::default()).unwrap();
+    let children = root.get_child_list();
+    assert_eq!(9, children.len());
+    let prior_total: f32 = children.iter().map(|c| c.get_data().prior()).sum();
+    assert!((prior_total - 1.0).abs() < 1e-6);
+    for child in children.iter() {
+      let data = child.get_data();
+      if data.action().row == 1 && data.action().column == 1 {
+        assert_eq!(0.92, data.prior());
+        assert_eq!(1, data.statistics.visits());
+      } else {
+        assert_eq!(0.01, data.prior());
+        assert_eq!(0, data.statistics.visits());
+      }
+    }
+  }
+
+  #[test]
+  fn prior_orders_unvisited_children() {
+    assert!(puct_value(1.0, 1.0, 0.75, 0.0, 0.0) > puct_value(1.0, 1.0, 0.25, 0.0, 0.0));
+  }
+
+  #[test]
+  fn visits_decay_exploration() {
+    // A child with a high prior that has been visited often and scored poorly
+    // loses out to an unvisited child with a low prior.
+    let parent = f64::sqrt(100.0);
+    assert!(puct_value(parent, 1.0, 0.9, 99.0, 0.0) < puct_value(parent, 1.0, 0.1, 0.0, 0.0));
+    assert_eq!(
+      0.5 + 2.0 * 0.5 * 3.0 / 5.0,
+      puct_value(3.0, 2.0, 0.5, 4.0, 2.0)
+    );
+  }
+}
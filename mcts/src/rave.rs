@@ -0,0 +1,367 @@
+//! Rapid action value estimation (RAVE).
+//!
+//! In addition to its ordinary statistics, each edge in the search graph
+//! carries "all moves as first" (AMAF) statistics (see
+//! [EdgeData](../graph/struct.EdgeData.html)). After each iteration of search,
+//! every edge out of a vertex on the rollout path receives the payoff of a
+//! playout if its action was taken by the same player at any later point in
+//! the rollout path or the playout. AMAF statistics accumulate much faster
+//! than ordinary statistics, but they are biased when the value of an action
+//! depends on when it is taken.
+//!
+//! The [RAVE rollout selector](struct.Rollout.html) blends the two, weighting
+//! AMAF statistics according to a [RaveSchedule](enum.RaveSchedule.html) that
+//! shifts weight to the ordinary statistics as an edge gathers visits.
+
+use crate::backprop::BackpropSelector;
+use crate::game::{Game, State, Statistics};
+use crate::graph::{EdgeData, VertexData};
+use crate::rollout::RolloutSelector;
+use crate::simulation::Playout;
+use crate::ucb::{self, UcbError};
+use rand::Rng;
+use search_graph;
+
+use std::collections::HashSet;
+use std::f64;
+use std::result::Result;
+
+/// Controls the weight given to AMAF statistics relative to ordinary
+/// statistics when selecting an edge during rollout with RAVE.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaveSchedule {
+  /// The hand-selected schedule of Gelly and Silver, which gives AMAF weight
+  /// `sqrt(k / (3n + k))` for an edge with `n` visits. AMAF and ordinary
+  /// statistics are weighted equally when `n` equals the given equivalence
+  /// parameter `k`.
+  Equivalence(f64),
+  /// The minimum mean squared error schedule of Silver, which gives AMAF
+  /// weight `m / (n + m + 4 b^2 n m)` for an edge with `n` visits and `m` AMAF
+  /// visits, where `b` is the given estimate of AMAF bias.
+  MinimumMse(f64),
+}
+
+impl RaveSchedule {
+  /// Returns the weight of AMAF statistics, between 0 and 1, for an edge with
+  /// `visits` visits and `amaf_visits` AMAF visits.
+  pub fn amaf_weight(&self, visits: f64, amaf_visits: f64) -> f64 {
+    if amaf_visits == 0.0 {
+      return 0.0;
+    }
+    match *self {
+      RaveSchedule::Equivalence(k) => f64::sqrt(k / (3.0 * visits + k)),
+      RaveSchedule::MinimumMse(b) => {
+        amaf_visits / (visits + amaf_visits + 4.0 * b * b * visits * amaf_visits)
+      }
+    }
+  }
+}
+
+/// Adds the payoff of each of `playouts` to the AMAF statistics of the edges
+/// out of each vertex on the rollout `path` whose actions were taken later on
+/// by the same player.
+///
+/// `path` is the sequence of edges followed during rollout, starting at the
+/// root, and each of `playouts` began at the target of its last edge.
+pub fn update_amaf<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  path: &[search_graph::view::EdgeRef<'id>],
+  playouts: &[Playout<G>],
+) {
+  for playout in playouts.iter() {
+    // (player, action) pairs that were taken at or below the source of the
+    // current edge.
+    let mut later_actions: HashSet<(&G::PlayerId, &G::Action)> =
+      playout.actions.iter().map(|(p, a)| (p, a)).collect();
+    for &edge in path.iter().rev() {
+      let parent = graph.edge_source(edge);
+      let player = graph.node_state(parent).active_player();
+      later_actions.insert((player, graph.edge_data(edge).action()));
+      for sibling in graph.children(parent) {
+        let data = graph.edge_data(sibling);
+        if later_actions.contains(&(player, data.action())) {
+          data.amaf.increment(&playout.payoff);
+        }
+      }
+    }
+  }
+}
+
+/// Returns the RAVE value of `child`, or infinity if `child` has not been
+/// visited.
+///
+/// Both the statistics and the AMAF statistics of `child` count one visit per
+/// playout, so that their mean scores can be blended.
+///
+/// Any virtual loss on `child` is counted as visits that lost the game for the
+/// player to move at its parent.
+pub fn child_score<'a, 'id, G: Game>(
+  log_parent_visits: f64,
+  explore_bias: f64,
+  schedule: Option<RaveSchedule>,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  child: search_graph::view::EdgeRef<'id>,
+) -> f64 {
+  let data = graph.edge_data(child);
+  let visits = data.visits_with_virtual_loss() as f64;
+  if visits == 0.0 {
+    return f64::INFINITY;
+  }
+  let player = graph.node_state(graph.edge_source(child)).active_player();
+  let mean_score = data.score_with_virtual_loss(player) as f64 / visits;
+  let amaf_visits = data.amaf.visits() as f64;
+  let beta = match schedule {
+    Some(schedule) => schedule.amaf_weight(visits, amaf_visits),
+    None => 0.0,
+  };
+  let blended_score = if beta > 0.0 {
+    let amaf_mean_score = data.amaf.score(player) as f64 / amaf_visits;
+    (1.0 - beta) * mean_score + beta * amaf_mean_score
+  } else {
+    mean_score
+  };
+  blended_score + explore_bias * f64::sqrt(log_parent_visits / visits)
+}
+
+/// Returns the child edge of `parent` that is best according to the RAVE
+/// criterion. Unvisited children are preferred, and ties are broken uniformly
+/// at random.
+///
+/// This function will panic if `parent` has no children.
+pub fn find_best_child<'a, 'id, G, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  parent: search_graph::view::NodeRef<'id>,
+  explore_bias: f64,
+  schedule: Option<RaveSchedule>,
+  rng: &mut R,
+) -> Result<search_graph::view::EdgeRef<'id>, UcbError>
+where
+  G: Game,
+  R: Rng,
+{
+  let log_parent_visits = ucb::log_parent_visits(graph, parent);
+  ucb::find_best_child_by(graph, parent, rng, |child| {
+    child_score(log_parent_visits, explore_bias, schedule, graph, child)
+  })
+}
+
+/// Returns `true` iff `e` could be selected by the RAVE policy during rollout
+/// from its parent vertex (i.e., no sibling of `e` has a higher RAVE value).
+pub fn is_best_child<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  e: search_graph::view::EdgeRef<'id>,
+  explore_bias: f64,
+  schedule: Option<RaveSchedule>,
+) -> bool {
+  let log_parent_visits = ucb::log_parent_visits(graph, graph.edge_source(e));
+  ucb::is_best_child_by(graph, e, |child| {
+    child_score(log_parent_visits, explore_bias, schedule, graph, child)
+  })
+}
+
+/// [Rollout selector](../rollout/trait.RolloutSelector.html) that chooses a
+/// child with the highest UCB1 score, computed from a blend of its ordinary
+/// and AMAF statistics.
+///
+/// The blend is controlled by `SearchSettings::rave_schedule`. If it is
+/// `None`, no AMAF statistics are gathered and this selector behaves like
+/// [UCB1 rollout](../ucb/struct.Rollout.html).
+pub struct Rollout {
+  explore_bias: f64,
+  schedule: Option<RaveSchedule>,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for Rollout {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    Rollout {
+      explore_bias: settings.explore_bias,
+      schedule: settings.rave_schedule,
+    }
+  }
+}
+
+impl RolloutSelector for Rollout {
+  type Error = UcbError;
+
+  fn select<'a, 'id, G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    parent: search_graph::view::NodeRef<'id>,
+    rng: &mut R,
+  ) -> Result<search_graph::view::EdgeRef<'id>, UcbError> {
+    find_best_child(graph, parent, self.explore_bias, self.schedule, rng)
+  }
+}
+
+/// [Backprop selector](../backprop/trait.BackpropSelector.html) that traverses
+/// upward edges that would have been selected by the [RAVE
+/// rollout](struct.Rollout.html) policy.
+pub struct BestParentBackprop {
+  explore_bias: f64,
+  schedule: Option<RaveSchedule>,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for BestParentBackprop {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    BestParentBackprop {
+      explore_bias: settings.explore_bias,
+      schedule: settings.rave_schedule,
+    }
+  }
+}
+
+impl<'id> BackpropSelector<'id> for BestParentBackprop {
+  type Items = std::vec::IntoIter<search_graph::view::EdgeRef<'id>>;
+
+  fn select<G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    node: search_graph::view::NodeRef<'id>,
+    _payoff: &G::Payoff,
+    _rng: &mut R,
+  ) -> Self::Items {
+    ucb::best_parents(graph, node, |e| {
+      is_best_child(graph, e, self.explore_bias, self.schedule)
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::RaveSchedule;
+  use crate::graph::{EdgeData, VertexData};
+  use crate::simulation::Playout;
+  use crate::statistics::two_player::{Player, ScoredPayoff};
+  use crate::{backprop, expand, simulation, tictactoe, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    VertexData<tictactoe::ScoredGame>,
+    EdgeData<tictactoe::ScoredGame>,
+  >;
+
+  fn rave_settings() -> SearchSettings {
+    SearchSettings {
+      rave_schedule: Some(RaveSchedule::Equivalence(100.0)),
+      ..Default::default()
+    }
+  }
+
+  fn action(row: usize, column: usize, player: tictactoe::Player) -> tictactoe::Action {
+    tictactoe::Action {
+      row,
+      column,
+      player,
+    }
+  }
+
+  #[test]
+  fn schedule_weights() {
+    let equivalence = RaveSchedule::Equivalence(100.0);
+    assert_eq!(0.0, equivalence.amaf_weight(0.0, 0.0));
+    assert_eq!(1.0, equivalence.amaf_weight(0.0, 10.0));
+    assert_eq!(0.5, equivalence.amaf_weight(100.0, 10.0));
+    assert!(equivalence.amaf_weight(1000.0, 10.0) < 0.5);
+    let mse = RaveSchedule::MinimumMse(0.0);
+    assert_eq!(0.5, mse.amaf_weight(10.0, 10.0));
+    assert!(RaveSchedule::MinimumMse(0.1).amaf_weight(10.0, 10.0) < 0.5);
+  }
+
+  #[test]
+  fn amaf_credits_later_actions_of_same_player() {
+    let mut graph = Graph::new();
+    search_graph::view::of_graph(&mut graph, |mut view| {
+      let root = view.append_node(tictactoe::State::default(), VertexData::default());
+      expand::expand(&mut view, root, &mut Vec::new());
+      let first = view
+        .children(root)
+        .find(|&e| *view[e].action() == action(0, 0, tictactoe::Player::X))
+        .unwrap();
+      let playout = Playout::<tictactoe::ScoredGame> {
+        payoff: ScoredPayoff {
+          visits: 1,
+          score_one: 1,
+          score_two: 0,
+        },
+        actions: vec![
+          (tictactoe::Player::O, action(1, 1, tictactoe::Player::O)),
+          (tictactoe::Player::X, action(2, 2, tictactoe::Player::X)),
+        ],
+      };
+      super::update_amaf(&view, &[first], &[playout]);
+      for child in view.children(root) {
+        let a = view[child].action();
+        let expected = if (a.row, a.column) == (0, 0) || (a.row, a.column) == (2, 2) {
+          1
+        } else {
+          0
+        };
+        assert_eq!(expected, view[child].amaf.visits(), "action {:?}", a);
+      }
+    });
+  }
+
+  fn search(settings: SearchSettings) -> Graph {
+    let mut graph = Graph::new();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings, tictactoe::State::default(), view);
+      for _ in 0..50 {
+        rollout = rollout
+          .rollout::<super::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+    graph
+  }
+
+  #[test]
+  fn search_gathers_amaf_statistics() {
+    let graph = search(rave_settings());
+    let root = graph.find_node(&tictactoe::State::default()).unwrap();
+    let mut visits = 0;
+    let mut amaf_visits = 0;
+    for child in root.get_child_list().iter() {
+      let data = child.get_data();
+      // Each edge receives AMAF credit whenever it is traversed.
+      assert!(data.amaf.visits() >= data.statistics.visits());
+      visits += data.statistics.visits();
+      amaf_visits += data.amaf.visits();
+    }
+    assert_eq!(49, visits);
+    assert!(amaf_visits > visits);
+  }
+
+  #[test]
+  fn several_simulations_blend_scores_per_playout() {
+    let settings = SearchSettings {
+      simulation_count: 4,
+      simulation_thread_limit: 2,
+      ..rave_settings()
+    };
+    let graph = search(settings);
+    let root = graph.find_node(&tictactoe::State::default()).unwrap();
+    let mut visits = 0;
+    for child in root.get_child_list().iter() {
+      let data = child.get_data();
+      assert!(data.amaf.visits() >= data.statistics.visits());
+      // A playout scores at most 1 for either player, both in the statistics
+      // of an edge and in its AMAF statistics.
+      for statistics in [&data.statistics, &data.amaf].iter() {
+        let net_score = statistics.net_score(Player::One).unsigned_abs();
+        assert!(net_score <= statistics.visits());
+      }
+      visits += data.statistics.visits();
+    }
+    // Each of the 49 rollouts below the root simulated 4 playouts, unless it
+    // reached the end of the game.
+    assert!(visits > 49);
+  }
+}
@@ -0,0 +1,310 @@
+//! Measurements of a round of search, for tuning search settings.
+//!
+//! A [SearchRecorder](struct.SearchRecorder.html) is updated as each
+//! iteration of search completes, possibly by several threads at once, and
+//! then summarised in a [SearchReport](struct.SearchReport.html).
+
+use crate::game::Game;
+use crate::rollout::RolloutError;
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{atomic, Mutex};
+use std::time::{Duration, Instant};
+
+/// A summary of a round of search.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchReport {
+  /// The number of iterations of rollout, scoring, backprop, and expansion
+  /// that were completed.
+  pub iterations: u32,
+  /// The wall-clock time that search took.
+  pub duration: Duration,
+  /// The number of vertices in the search graph when search ended.
+  pub node_count: usize,
+  /// The number of edges in the search graph when search ended.
+  pub edge_count: usize,
+  /// The number of vertices that expansion added to the search graph. This
+  /// may exceed the growth of the graph if vertices were evicted.
+  pub nodes_added: usize,
+  /// The greatest number of edges followed by a single rollout.
+  pub max_rollout_depth: usize,
+  /// The total number of edges followed by all rollouts.
+  pub total_rollout_depth: u64,
+  /// The number of playouts that were simulated.
+  pub simulations: u64,
+  /// The error that ended search, if any. Search stops at the first failed
+  /// rollout or simulation.
+  pub failure: Option<SearchFailure>,
+  /// Whether search was cancelled before its budget was exhausted, such as by
+  /// a [SearchObserver](../observe/trait.SearchObserver.html).
+  pub cancelled: bool,
+}
+
+/// The kind of error that ended a round of search.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchFailure {
+  /// Rollout found a cycle. See `RolloutError::Cycle`.
+  RolloutCycle,
+  /// The rollout selector reported the error with this message.
+  RolloutSelector(String),
+  /// The simulator reported the error with this message, such as that of
+  /// `RandomSimulatorError::DeadEnd`.
+  Simulation(String),
+}
+
+impl fmt::Display for SearchFailure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      SearchFailure::RolloutCycle => write!(f, "rollout found a cycle"),
+      SearchFailure::RolloutSelector(ref message) => {
+        write!(f, "rollout selector error: {}", message)
+      }
+      SearchFailure::Simulation(ref message) => write!(f, "simulation error: {}", message),
+    }
+  }
+}
+
+impl SearchReport {
+  /// Returns the mean number of edges followed by each rollout, or 0 if no
+  /// iterations were completed.
+  pub fn mean_rollout_depth(&self) -> f64 {
+    if self.iterations == 0 {
+      0.0
+    } else {
+      self.total_rollout_depth as f64 / self.iterations as f64
+    }
+  }
+
+  /// Returns the number of playouts simulated per second of search, or 0 if
+  /// search took no measurable time.
+  pub fn playouts_per_second(&self) -> f64 {
+    let seconds = self.duration.as_secs_f64();
+    if seconds > 0.0 {
+      self.simulations as f64 / seconds
+    } else {
+      0.0
+    }
+  }
+}
+
+impl fmt::Display for SearchReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} iterations in {:.3}s; ",
+      self.iterations,
+      self.duration.as_secs_f64(),
+    )?;
+    write!(
+      f,
+      "{} nodes and {} edges ({} nodes added); ",
+      self.node_count, self.edge_count, self.nodes_added,
+    )?;
+    write!(
+      f,
+      "rollout depth max {}, mean {:.2}; ",
+      self.max_rollout_depth,
+      self.mean_rollout_depth(),
+    )?;
+    write!(
+      f,
+      "{} simulations ({:.1}/s)",
+      self.simulations,
+      self.playouts_per_second(),
+    )?;
+    if let Some(ref failure) = self.failure {
+      write!(f, "; failed: {}", failure)?;
+    }
+    if self.cancelled {
+      write!(f, "; cancelled")?;
+    }
+    Ok(())
+  }
+}
+
+/// Gathers the measurements for a [SearchReport](struct.SearchReport.html).
+///
+/// A recorder may be shared by several threads that search concurrently.
+#[derive(Debug)]
+pub struct SearchRecorder {
+  started: Instant,
+  iterations: atomic::AtomicU32,
+  nodes_added: atomic::AtomicUsize,
+  max_rollout_depth: atomic::AtomicUsize,
+  total_rollout_depth: atomic::AtomicU64,
+  simulations: atomic::AtomicU64,
+  failure: Mutex<Option<SearchFailure>>,
+  cancelled: atomic::AtomicBool,
+}
+
+impl SearchRecorder {
+  /// Starts the clock on a new round of search.
+  pub fn start() -> Self {
+    SearchRecorder {
+      started: Instant::now(),
+      iterations: atomic::AtomicU32::new(0),
+      nodes_added: atomic::AtomicUsize::new(0),
+      max_rollout_depth: atomic::AtomicUsize::new(0),
+      total_rollout_depth: atomic::AtomicU64::new(0),
+      simulations: atomic::AtomicU64::new(0),
+      failure: Mutex::new(None),
+      cancelled: atomic::AtomicBool::new(false),
+    }
+  }
+
+  /// Returns the wall-clock time since search started.
+  pub fn elapsed(&self) -> Duration {
+    self.started.elapsed()
+  }
+
+  /// Records a completed iteration, whose rollout followed `rollout_depth`
+  /// edges, which simulated `simulations` playouts, and whose expansion added
+  /// `nodes_added` vertices.
+  pub fn record_iteration(&self, rollout_depth: usize, simulations: usize, nodes_added: usize) {
+    self.iterations.fetch_add(1, atomic::Ordering::SeqCst);
+    self
+      .nodes_added
+      .fetch_add(nodes_added, atomic::Ordering::SeqCst);
+    self
+      .max_rollout_depth
+      .fetch_max(rollout_depth, atomic::Ordering::SeqCst);
+    self
+      .total_rollout_depth
+      .fetch_add(rollout_depth as u64, atomic::Ordering::SeqCst);
+    self
+      .simulations
+      .fetch_add(simulations as u64, atomic::Ordering::SeqCst);
+  }
+
+  /// Records a failed rollout, which ends search.
+  pub fn record_rollout_error<G: Game, E: Error>(&self, error: &RolloutError<G, E>) {
+    self.record_failure(match *error {
+      RolloutError::Cycle { .. } => SearchFailure::RolloutCycle,
+      RolloutError::Selector(ref e) => SearchFailure::RolloutSelector(e.to_string()),
+    });
+  }
+
+  /// Records a failed simulation, which ends search.
+  pub fn record_simulation_error<E: Error>(&self, error: &E) {
+    self.record_failure(SearchFailure::Simulation(error.to_string()));
+  }
+
+  /// Records `failure` unless an earlier failure has been recorded, such as
+  /// by another thread searching concurrently.
+  fn record_failure(&self, failure: SearchFailure) {
+    let mut recorded = self.failure.lock().expect("search failure lock poisoned");
+    if recorded.is_none() {
+      *recorded = Some(failure);
+    }
+  }
+
+  /// Cancels search. Every search that shares this recorder stops before
+  /// starting another iteration.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, atomic::Ordering::SeqCst);
+  }
+
+  /// Returns `true` iff search has been cancelled.
+  pub fn cancelled(&self) -> bool {
+    self.cancelled.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Returns a report of what has been recorded so far, for a search graph
+  /// that now has `node_count` vertices and `edge_count` edges.
+  pub fn report(&self, node_count: usize, edge_count: usize) -> SearchReport {
+    SearchReport {
+      iterations: self.iterations.load(atomic::Ordering::SeqCst),
+      duration: self.started.elapsed(),
+      node_count,
+      edge_count,
+      nodes_added: self.nodes_added.load(atomic::Ordering::SeqCst),
+      max_rollout_depth: self.max_rollout_depth.load(atomic::Ordering::SeqCst),
+      total_rollout_depth: self.total_rollout_depth.load(atomic::Ordering::SeqCst),
+      simulations: self.simulations.load(atomic::Ordering::SeqCst),
+      failure: self
+        .failure
+        .lock()
+        .expect("search failure lock poisoned")
+        .clone(),
+      cancelled: self.cancelled(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{SearchFailure, SearchRecorder, SearchReport};
+  use crate::rollout::RolloutError;
+  use crate::simulation::RandomSimulatorError;
+  use crate::{tictactoe, ucb};
+  use std::time::Duration;
+
+  #[test]
+  fn recorder_sums_iterations() {
+    let recorder = SearchRecorder::start();
+    recorder.record_iteration(2, 4, 9);
+    recorder.record_iteration(5, 0, 0);
+    recorder.record_iteration(1, 4, 8);
+    let report = recorder.report(18, 17);
+    assert_eq!(3, report.iterations);
+    assert_eq!(17, report.nodes_added);
+    assert_eq!(5, report.max_rollout_depth);
+    assert_eq!(8, report.total_rollout_depth);
+    assert_eq!(8, report.simulations);
+    assert_eq!((18, 17), (report.node_count, report.edge_count));
+    assert!((report.mean_rollout_depth() - 8.0 / 3.0).abs() < 1e-9);
+    assert!(!report.cancelled);
+    recorder.cancel();
+    assert!(recorder.report(18, 17).cancelled);
+  }
+
+  #[test]
+  fn recorder_keeps_first_failure() {
+    let recorder = SearchRecorder::start();
+    assert_eq!(None, recorder.report(0, 0).failure);
+    let cycle: RolloutError<tictactoe::ScoredGame, ucb::UcbError> = RolloutError::Cycle {
+      root: tictactoe::State::default(),
+      elements: Vec::new(),
+    };
+    recorder.record_rollout_error(&cycle);
+    recorder.record_simulation_error(&RandomSimulatorError::DeadEnd);
+    let report = recorder.report(0, 0);
+    assert_eq!(Some(SearchFailure::RolloutCycle), report.failure);
+    assert!(report.to_string().contains("failed: rollout found a cycle"));
+  }
+
+  #[test]
+  fn recorder_names_failure_variant() {
+    let recorder = SearchRecorder::start();
+    recorder.record_rollout_error::<tictactoe::ScoredGame, _>(&RolloutError::Selector(
+      ucb::UcbError::InvalidComputation,
+    ));
+    assert_eq!(
+      Some(SearchFailure::RolloutSelector(
+        ucb::UcbError::InvalidComputation.to_string()
+      )),
+      recorder.report(0, 0).failure
+    );
+    let recorder = SearchRecorder::start();
+    recorder.record_simulation_error(&RandomSimulatorError::DeadEnd);
+    assert_eq!(
+      Some(SearchFailure::Simulation(
+        RandomSimulatorError::DeadEnd.to_string()
+      )),
+      recorder.report(0, 0).failure
+    );
+  }
+
+  #[test]
+  fn playouts_per_second() {
+    let report = SearchReport {
+      simulations: 500,
+      duration: Duration::from_millis(250),
+      ..Default::default()
+    };
+    assert!((report.playouts_per_second() - 2000.0).abs() < 1e-9);
+    assert_eq!(0.0, SearchReport::default().playouts_per_second());
+    assert_eq!(0.0, SearchReport::default().mean_rollout_depth());
+  }
+}
@@ -4,13 +4,44 @@ use crate::game::Game;
 use crate::graph::{EdgeData, VertexData};
 use crate::SearchSettings;
 
+use std::collections::HashSet;
 use std::convert::From;
 use std::error::Error;
 use std::fmt;
 use std::result::Result;
 
+use rand::seq::SliceRandom;
 use rand::Rng;
 
+/// How rollout handles an edge that leads back to a vertex already on the
+/// rollout path. Such cycles occur when game states can repeat, since the
+/// search graph has a single vertex for each distinct state.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CyclePolicy {
+  /// Rollout fails with [RolloutError::Cycle](enum.RolloutError.html).
+  #[default]
+  Error,
+  /// Rollout follows the edge and stops, and the vertex that it reaches is
+  /// scored with [Game::draw_payoff](../game/trait.Game.html#method.draw_payoff)
+  /// instead of being simulated. The edge is marked as a
+  /// [repetition](../graph/struct.EdgeData.html#method.repetition), which the
+  /// [solver](../solver/index.html) counts as a proven draw. If the game has no
+  /// draw payoff, this behaves like `Error`.
+  Draw,
+  /// Rollout follows a child chosen uniformly at random from those that do
+  /// not lead back to the path. If there are none, this behaves like `Error`.
+  Backoff,
+}
+
+/// The terminating vertex of a rollout, the edges that were followed to reach
+/// it, in order, and whether the last of those edges closed a cycle that
+/// should be scored as a draw.
+pub type RolloutPath<'id> = (
+  search_graph::view::NodeRef<'id>,
+  Vec<search_graph::view::EdgeRef<'id>>,
+  bool,
+);
+
 /// Error type for MCTS rollout.
 pub enum RolloutError<G: Game, E: Error> {
   /// Rollout encountered a cycle.
@@ -75,7 +106,7 @@ pub trait RolloutSelector: for<'a> From<&'a SearchSettings> {
   /// Returns the element of `children` that should be followed, or an error.
   fn select<'a, 'id, G: Game, R: Rng>(
     &self,
-    graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
     parent: search_graph::view::NodeRef<'id>,
     rng: &mut R,
   ) -> Result<search_graph::view::EdgeRef<'id>, Self::Error>;
@@ -89,30 +120,419 @@ pub trait RolloutSelector: for<'a> From<&'a SearchSettings> {
 /// whose last element is the terminating vertex, or an error.
 ///
 /// Selection will be done minimax-style, i.e., always trying to maximize the
-/// score for the currently active player.
+/// score for the currently active player. Edges that lead back to a vertex
+/// already on the path are handled according to `cycle_policy`.
 pub fn rollout<'a, 'id, G, S, R>(
-  graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
-  mut node: search_graph::view::NodeRef<'id>,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
   selector: S,
   rng: &mut R,
+  cycle_policy: CyclePolicy,
 ) -> Result<search_graph::view::NodeRef<'id>, RolloutError<G, S::Error>>
 where
   G: Game,
   S: RolloutSelector,
   R: Rng,
 {
+  rollout_path(graph, node, &selector, rng, cycle_policy).map(|(node, _, _)| node)
+}
+
+/// Traverses the game graph downwards from `node` in the same manner as
+/// [rollout](fn.rollout.html).
+///
+/// Returns the [RolloutPath](type.RolloutPath.html) that was followed.
+pub fn rollout_path<'a, 'id, G, S, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  mut node: search_graph::view::NodeRef<'id>,
+  selector: &S,
+  rng: &mut R,
+  cycle_policy: CyclePolicy,
+) -> Result<RolloutPath<'id>, RolloutError<G, S::Error>>
+where
+  G: Game,
+  S: RolloutSelector,
+  R: Rng,
+{
+  let root = node;
+  let mut visited = HashSet::new();
+  visited.insert(node);
+  let mut path = Vec::new();
   loop {
-    if let Some(_) = G::payoff_of(graph.node_state(node)) {
+    if G::payoff_of(graph.node_state(node)).is_some() {
       // Hit known payoff.
       break;
+    } else if graph.node_data(node).solved() {
+      // Hit proven payoff.
+      break;
     } else if graph.child_count(node) == 0 {
       // Hit leaf in search graph.
       break;
     } else {
       let child = selector.select(graph, node, rng)?;
+      let (child, cycle) = resolve_cycle(graph, root, &path, &visited, child, rng, cycle_policy)?;
       graph.edge_data(child).mark_rollout_traversal();
+      path.push(child);
+      node = graph.edge_target(child);
+      if cycle {
+        return Ok((node, path, true));
+      }
+      visited.insert(node);
+    }
+  }
+  Ok((node, path, false))
+}
+
+/// Traverses the game graph downwards from `node` in the same manner as
+/// [rollout](fn.rollout.html), adding `virtual_loss` pending visits to each
+/// edge that it follows. Each pending visit scores `loss_score` until it is
+/// removed (see `Game::loss_score`).
+///
+/// Returns the [RolloutPath](type.RolloutPath.html) that was followed.
+/// Callers are responsible for removing the virtual loss
+/// from each of those edges once the payoff found from the terminating vertex
+/// has been backpropagated.
+pub fn rollout_with_virtual_loss<'a, 'id, G, S, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  mut node: search_graph::view::NodeRef<'id>,
+  selector: &S,
+  rng: &mut R,
+  virtual_loss: u32,
+  loss_score: f32,
+  cycle_policy: CyclePolicy,
+) -> Result<RolloutPath<'id>, RolloutError<G, S::Error>>
+where
+  G: Game,
+  S: RolloutSelector,
+  R: Rng,
+{
+  let root = node;
+  let mut visited = HashSet::new();
+  visited.insert(node);
+  let mut path = Vec::new();
+  loop {
+    if G::payoff_of(graph.node_state(node)).is_some() {
+      // Hit known payoff.
+      break;
+    } else if graph.node_data(node).solved() {
+      // Hit proven payoff.
+      break;
+    } else if graph.child_count(node) == 0 {
+      // Hit leaf in search graph.
+      break;
+    } else {
+      let selected = selector
+        .select(graph, node, rng)
+        .map_err(RolloutError::Selector)
+        .and_then(|child| resolve_cycle(graph, root, &path, &visited, child, rng, cycle_policy));
+      let (child, cycle) = match selected {
+        Ok(c) => c,
+        Err(e) => {
+          for edge in path {
+            graph.edge_data(edge).remove_virtual_loss(virtual_loss);
+          }
+          return Err(e);
+        }
+      };
+      let data = graph.edge_data(child);
+      data.mark_rollout_traversal();
+      data.add_virtual_loss(virtual_loss, loss_score);
+      path.push(child);
       node = graph.edge_target(child);
+      if cycle {
+        return Ok((node, path, true));
+      }
+      visited.insert(node);
+    }
+  }
+  Ok((node, path, false))
+}
+
+/// Checks whether `child`, the edge selected at the end of `path`, leads back
+/// to one of the `visited` vertices on the path from `root`, and handles it
+/// according to `cycle_policy` if so.
+///
+/// Returns the edge to follow and whether it closes a cycle that should be
+/// scored as a draw, or an error describing the cycle.
+fn resolve_cycle<'a, 'id, G, E, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  root: search_graph::view::NodeRef<'id>,
+  path: &[search_graph::view::EdgeRef<'id>],
+  visited: &HashSet<search_graph::view::NodeRef<'id>>,
+  child: search_graph::view::EdgeRef<'id>,
+  rng: &mut R,
+  cycle_policy: CyclePolicy,
+) -> Result<(search_graph::view::EdgeRef<'id>, bool), RolloutError<G, E>>
+where
+  G: Game,
+  E: Error,
+  R: Rng,
+{
+  if !visited.contains(&graph.edge_target(child)) {
+    return Ok((child, false));
+  }
+  match cycle_policy {
+    CyclePolicy::Draw if G::draw_payoff().is_some() => {
+      graph.edge_data(child).mark_repetition();
+      return Ok((child, true));
+    }
+    CyclePolicy::Backoff => {
+      let source = graph.edge_source(child);
+      let alternatives: Vec<_> = graph
+        .children(source)
+        .filter(|&edge| !visited.contains(&graph.edge_target(edge)))
+        .collect();
+      if let Some(&edge) = alternatives.choose(rng) {
+        return Ok((edge, false));
+      }
+    }
+    _ => (),
+  }
+  Err(RolloutError::Cycle {
+    root: graph.node_state(root).clone(),
+    elements: path
+      .iter()
+      .chain(Some(&child))
+      .map(|&edge| {
+        (
+          graph.edge_data(edge).action().clone(),
+          graph.node_state(graph.edge_target(edge)).clone(),
+        )
+      })
+      .collect(),
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{rollout_path, CyclePolicy, RolloutError, RolloutSelector};
+  use crate::graph::{EdgeData, VertexData};
+  use crate::statistics::two_player::{Player, ScoredPayoff, ScoredStatistics};
+  use crate::{backprop, expand, game, simulation, ucb, RolloutPhase, SearchSettings};
+  use rand::{Rng, SeedableRng};
+  use std::fmt;
+
+  #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+  enum Step {
+    Next,
+    Exit,
+  }
+
+  /// A one-player game in which the player moves around a ring of three
+  /// positions, and may leave the ring from the last of them to end the game.
+  #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+  struct RingState {
+    position: u8,
+  }
+
+  /// The position of a `RingState` after leaving the ring.
+  const EXIT: u8 = 3;
+
+  impl game::State for RingState {
+    type Action = Step;
+    type PlayerId = Player;
+    type Undo = u8;
+
+    fn active_player(&self) -> &Player {
+      &Player::One
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Step>) {
+      actions.clear();
+      match self.position {
+        EXIT => (),
+        2 => actions.extend_from_slice(&[Step::Next, Step::Exit]),
+        _ => actions.push(Step::Next),
+      }
+    }
+
+    fn do_action(&mut self, step: &Step) -> u8 {
+      let position = self.position;
+      self.position = match *step {
+        Step::Next => (self.position + 1) % EXIT,
+        Step::Exit => EXIT,
+      };
+      position
+    }
+
+    fn undo_action(&mut self, _step: &Step, position: u8) {
+      self.position = position;
+    }
+  }
+
+  #[derive(Debug)]
+  struct Ring {}
+
+  impl game::Game for Ring {
+    type Action = Step;
+    type PlayerId = Player;
+    type Payoff = ScoredPayoff;
+    type State = RingState;
+    type Statistics = ScoredStatistics<Player>;
+
+    fn payoff_of(state: &RingState) -> Option<ScoredPayoff> {
+      if state.position == EXIT {
+        Some(ScoredPayoff {
+          visits: 1,
+          score_one: 1,
+          score_two: 0,
+        })
+      } else {
+        None
+      }
+    }
+
+    fn draw_payoff() -> Option<ScoredPayoff> {
+      Some(ScoredPayoff {
+        visits: 1,
+        score_one: 0,
+        score_two: 0,
+      })
+    }
+  }
+
+  /// Always follows the first child of a vertex, which for `Ring` is the step
+  /// to the next position around the ring.
+  struct FirstChild;
+
+  impl<'a> From<&'a SearchSettings> for FirstChild {
+    fn from(_: &'a SearchSettings) -> Self {
+      FirstChild
+    }
+  }
+
+  impl RolloutSelector for FirstChild {
+    type Error = fmt::Error;
+
+    fn select<'a, 'id, G: game::Game, R: Rng>(
+      &self,
+      graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+      parent: search_graph::view::NodeRef<'id>,
+      _rng: &mut R,
+    ) -> Result<search_graph::view::EdgeRef<'id>, fmt::Error> {
+      graph.children(parent).next().ok_or(fmt::Error)
+    }
+  }
+
+  fn settings(cycle_policy: CyclePolicy) -> SearchSettings {
+    SearchSettings {
+      cycle_policy,
+      ..Default::default()
+    }
+  }
+
+  /// Runs `f` on a view of a search graph in which each position on the ring
+  /// has been expanded, and the vertex of position 0.
+  fn with_expanded_ring<F>(f: F)
+  where
+    F: for<'a, 'id> FnOnce(
+      search_graph::view::View<'a, 'id, RingState, VertexData<Ring>, EdgeData<Ring>>,
+      search_graph::view::NodeRef<'id>,
+    ),
+  {
+    let mut graph = crate::new_search_graph::<Ring>();
+    search_graph::view::of_graph(&mut graph, |mut view| {
+      let root = view.append_node(RingState { position: 0 }, VertexData::default());
+      for position in 0..EXIT {
+        let node = view.find_node(&RingState { position }).unwrap();
+        expand::expand(&mut view, node, &mut Vec::new());
+      }
+      f(view, root)
+    });
+  }
+
+  #[test]
+  fn cycle_is_reported_with_its_path() {
+    with_expanded_ring(|view, root| {
+      let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+      match rollout_path(&view, root, &FirstChild, &mut rng, CyclePolicy::Error) {
+        Err(RolloutError::Cycle { root, elements }) => {
+          assert_eq!(RingState { position: 0 }, root);
+          let positions: Vec<u8> = elements.iter().map(|(_, s)| s.position).collect();
+          assert_eq!(vec![1, 2, 0], positions);
+          assert!(elements.iter().all(|(action, _)| *action == Step::Next));
+        }
+        Err(e) => panic!("unexpected rollout error: {:?}", e),
+        Ok(_) => panic!("rollout did not report cycle"),
+      }
+    });
+  }
+
+  #[test]
+  fn cycle_ends_rollout_as_draw() {
+    with_expanded_ring(|view, root| {
+      let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+      let (node, path, cycle) =
+        rollout_path(&view, root, &FirstChild, &mut rng, CyclePolicy::Draw).unwrap();
+      assert!(cycle);
+      assert_eq!(root, node);
+      assert_eq!(3, path.len());
+    });
+  }
+
+  #[test]
+  fn cycle_backs_off_to_another_child() {
+    with_expanded_ring(|view, root| {
+      let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+      let (node, path, cycle) =
+        rollout_path(&view, root, &FirstChild, &mut rng, CyclePolicy::Backoff).unwrap();
+      assert!(!cycle);
+      assert_eq!(EXIT, view.node_state(node).position);
+      assert_eq!(3, path.len());
+      assert_eq!(Step::Exit, *view.edge_data(path[2]).action());
+    });
+  }
+
+  #[test]
+  fn search_with_draw_policy_terminates() {
+    let mut graph = crate::new_search_graph::<Ring>();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout = RolloutPhase::initialize(
+        &mut rng,
+        settings(CyclePolicy::Draw),
+        RingState { position: 0 },
+        view,
+      );
+      for _ in 0..30 {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+    assert_eq!(4, graph.vertex_count());
+  }
+
+  #[test]
+  fn solver_counts_cycle_as_draw() {
+    let mut graph = crate::new_search_graph::<Ring>();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let settings = SearchSettings {
+      solver: true,
+      ..settings(CyclePolicy::Draw)
+    };
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings, RingState { position: 0 }, view);
+      for _ in 0..30 {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+    // Going around the ring again is a draw, so leaving it is the best action
+    // at position 2, and each position is proven to be a win.
+    for position in 0..EXIT {
+      let node = graph.find_node(&RingState { position }).unwrap();
+      let solution = node.get_data().solution().unwrap();
+      assert_eq!((1, 0), (solution.score_one, solution.score_two));
     }
   }
-  Ok(node)
 }
@@ -1,9 +1,9 @@
 //! Interface for deriving payoffs from game state.
 
 use crate::game::{Game, State};
+use crate::playout::{self, PlayoutPolicy};
 use crate::SearchSettings;
 use log::trace;
-use rand::seq::IteratorRandom;
 use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg64;
 use rayon::prelude::*;
@@ -11,55 +11,145 @@ use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::convert::From;
 use std::error::Error;
 use std::fmt;
+use std::marker::PhantomData;
 use std::result::Result;
 
-pub trait Simulator: for<'a> From<&'a SearchSettings> {
+pub trait Simulator<G: Game>: for<'a> From<&'a SearchSettings> {
   type Error: Error;
 
-  fn simulate<G: Game, R: Rng>(
-    &self,
-    state: &G::State,
-    rng: &mut R,
-  ) -> Result<G::Payoff, Self::Error>;
+  fn simulate<R: Rng>(&self, state: &G::State, rng: &mut R) -> Result<Simulation<G>, Self::Error>;
+}
+
+/// A single simulated game, played out from some game state until its payoff
+/// was known.
+#[derive(Debug)]
+pub struct Playout<G: Game> {
+  /// The payoff at the end of the playout.
+  pub payoff: G::Payoff,
+  /// The actions taken during the playout, in order, each paired with the
+  /// player who took it.
+  pub actions: Vec<(G::PlayerId, G::Action)>,
+}
+
+/// The result of running one or more simulations from a game state.
+#[derive(Debug)]
+pub struct Simulation<G: Game> {
+  /// The sum of the payoffs of all `playouts`.
+  pub payoff: G::Payoff,
+  /// The individual playouts.
+  pub playouts: Vec<Playout<G>>,
+}
+
+impl<G: Game> Simulation<G> {
+  /// Returns the result of `count` simulations from a game state whose payoff
+  /// is `payoff` and so needs no actions to be played out.
+  pub fn of_payoff(payoff: &G::Payoff, count: u32) -> Self {
+    let mut total = G::Payoff::default();
+    let mut playouts = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+      total += payoff;
+      let mut playout_payoff = G::Payoff::default();
+      playout_payoff += payoff;
+      playouts.push(Playout {
+        payoff: playout_payoff,
+        actions: Vec::new(),
+      });
+    }
+    Simulation {
+      payoff: total,
+      playouts,
+    }
+  }
+}
+
+/// Estimates the payoff of a game state at which a playout was cut off
+/// because it reached `SearchSettings::max_playout_depth`.
+pub trait Evaluator<G: Game> {
+  /// Returns the estimated payoff of `state`, or `None` if the playout should
+  /// continue until the game ends.
+  fn evaluate(state: &G::State) -> Option<G::Payoff>;
 }
 
-pub struct RandomSimulator {
+/// Evaluator that never estimates a payoff, so that playouts always continue
+/// until the game ends.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoEvaluator;
+
+impl<G: Game> Evaluator<G> for NoEvaluator {
+  fn evaluate(_state: &G::State) -> Option<G::Payoff> {
+    None
+  }
+}
+
+/// Simulates playouts in parallel, choosing each action with the playout
+/// policy `P`.
+///
+/// If `SearchSettings::max_playout_depth` is set, a playout that reaches that
+/// many actions without ending the game is scored by the evaluator `E`
+/// instead.
+pub struct PlayoutSimulator<P, E = NoEvaluator> {
+  policy: P,
+  evaluator: PhantomData<fn() -> E>,
   simulation_count: u32,
+  max_depth: Option<u32>,
   thread_pool: ThreadPool,
 }
 
+/// Simulates playouts in which each action is chosen uniformly at random.
+pub type RandomSimulator = PlayoutSimulator<playout::Uniform>;
+
 #[derive(Debug)]
 pub enum RandomSimulatorError {
+  /// A playout reached a game state that has no payoff and no actions.
   DeadEnd,
 }
 
 impl fmt::Display for RandomSimulatorError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{:?}", self)
+    match *self {
+      RandomSimulatorError::DeadEnd => write!(f, "dead end: game state has no valid actions"),
+    }
   }
 }
 
 impl Error for RandomSimulatorError {
   fn description(&self) -> &'static str {
-    "game state has no valid actions"
+    match *self {
+      RandomSimulatorError::DeadEnd => "game state has no valid actions",
+    }
   }
 }
 
-impl RandomSimulator {
+impl<P, E> PlayoutSimulator<P, E> {
   /// Runs a single simulation.
   fn run_simulation<G: Game, R: Rng>(
+    &self,
     mut state: G::State,
     mut rng: R,
-  ) -> Result<G::Payoff, RandomSimulatorError> {
+  ) -> Result<Playout<G>, RandomSimulatorError>
+  where
+    P: PlayoutPolicy<G>,
+    E: Evaluator<G>,
+  {
+    let mut actions = Vec::new();
+    let mut choices = Vec::new();
     loop {
-      if let Some(p) = G::payoff_of(&state) {
-        return Ok(p);
+      if let Some(payoff) = G::payoff_of(&state) {
+        return Ok(Playout { payoff, actions });
       }
-      match state.actions().choose(&mut rng) {
+      if self.max_depth == Some(actions.len() as u32) {
+        if let Some(payoff) = E::evaluate(&state) {
+          trace!("playout cut off after {} actions", actions.len());
+          return Ok(Playout { payoff, actions });
+        }
+      }
+      match self.policy.choose(&state, &mut choices, &mut rng) {
         Some(a) => {
           trace!("doing action: {:?}", a);
+          let player = state.active_player().clone();
           state.do_action(&a);
           trace!("updated state: {:?}", state);
+          actions.push((player, a));
         }
         None => return Err(RandomSimulatorError::DeadEnd),
       }
@@ -67,10 +157,16 @@ impl RandomSimulator {
   }
 }
 
-impl<'a> From<&'a SearchSettings> for RandomSimulator {
+impl<'a, P, E> From<&'a SearchSettings> for PlayoutSimulator<P, E>
+where
+  P: for<'b> From<&'b SearchSettings>,
+{
   fn from(settings: &'a SearchSettings) -> Self {
-    RandomSimulator {
+    PlayoutSimulator {
+      policy: P::from(settings),
+      evaluator: PhantomData,
       simulation_count: settings.simulation_count,
+      max_depth: settings.max_playout_depth,
       thread_pool: ThreadPoolBuilder::new()
         .num_threads(settings.simulation_thread_limit as usize)
         .thread_name(|n| format!("random-simulator-thread-{}", n))
@@ -80,20 +176,17 @@ impl<'a> From<&'a SearchSettings> for RandomSimulator {
   }
 }
 
-impl Simulator for RandomSimulator {
+impl<G, P, E> Simulator<G> for PlayoutSimulator<P, E>
+where
+  G: Game,
+  P: PlayoutPolicy<G>,
+  E: Evaluator<G>,
+{
   type Error = RandomSimulatorError;
 
-  fn simulate<G: Game, R: Rng>(
-    &self,
-    state: &G::State,
-    rng: &mut R,
-  ) -> Result<G::Payoff, Self::Error> {
+  fn simulate<R: Rng>(&self, state: &G::State, rng: &mut R) -> Result<Simulation<G>, Self::Error> {
     if let Some(p) = G::payoff_of(state) {
-      let mut payoff = G::Payoff::default();
-      for _ in 0..self.simulation_count {
-        payoff += &p;
-      }
-      Ok(payoff)
+      Ok(Simulation::of_payoff(&p, self.simulation_count))
     } else {
       let parameters = (0..self.simulation_count)
         .map(|_| {
@@ -103,30 +196,77 @@ impl Simulator for RandomSimulator {
         })
         .collect::<Vec<(G::State, Pcg64)>>();
 
-      self.thread_pool.install(move || {
+      let playouts = self.thread_pool.install(move || {
         parameters
           .into_par_iter()
-          .try_fold(
-            || G::Payoff::default(),
-            |mut acc, params| {
-              let (state, rng) = params;
-              match RandomSimulator::run_simulation::<G, _>(state, rng) {
-                Err(e) => Err(e),
-                Ok(p) => {
-                  acc += &p;
-                  Ok(acc)
-                }
-              }
-            },
-          )
-          .try_reduce(
-            || G::Payoff::default(),
-            |mut acc, result| {
-              acc += &result;
-              Ok(acc)
-            },
-          )
+          .map(|(state, rng)| self.run_simulation::<G, _>(state, rng))
+          .collect::<Result<Vec<Playout<G>>, RandomSimulatorError>>()
+      })?;
+      let mut payoff = G::Payoff::default();
+      for playout in playouts.iter() {
+        payoff += &playout.payoff;
+      }
+      Ok(Simulation { payoff, playouts })
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Evaluator, PlayoutSimulator, Simulator};
+  use crate::playout::Uniform;
+  use crate::statistics::two_player::ScoredPayoff;
+  use crate::{tictactoe, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  /// Scores every cut-off game as a win for player one.
+  struct PlayerOneWins;
+
+  impl Evaluator<tictactoe::ScoredGame> for PlayerOneWins {
+    fn evaluate(_state: &tictactoe::State) -> Option<ScoredPayoff> {
+      Some(ScoredPayoff {
+        visits: 1,
+        score_one: 1,
+        score_two: 0,
       })
     }
   }
+
+  fn settings(max_playout_depth: Option<u32>) -> SearchSettings {
+    SearchSettings {
+      simulation_count: 8,
+      simulation_thread_limit: 2,
+      max_playout_depth,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn playouts_are_cut_off_at_max_depth() {
+    let simulator = PlayoutSimulator::<Uniform, PlayerOneWins>::from(&settings(Some(2)));
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let simulation: super::Simulation<tictactoe::ScoredGame> = simulator
+      .simulate(&tictactoe::State::default(), &mut rng)
+      .unwrap();
+    assert_eq!(8, simulation.playouts.len());
+    for playout in simulation.playouts.iter() {
+      assert_eq!(2, playout.actions.len());
+    }
+    assert_eq!(8, simulation.payoff.visits);
+    assert_eq!(8, simulation.payoff.score_one);
+  }
+
+  #[test]
+  fn playouts_without_evaluation_run_to_end() {
+    let simulator = super::RandomSimulator::from(&settings(Some(2)));
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let simulation: super::Simulation<tictactoe::ScoredGame> = simulator
+      .simulate(&tictactoe::State::default(), &mut rng)
+      .unwrap();
+    for playout in simulation.playouts.iter() {
+      // Tic-tac-toe cannot be won in fewer than five moves.
+      assert!(playout.actions.len() >= 5);
+    }
+  }
 }
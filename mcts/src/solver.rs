@@ -0,0 +1,232 @@
+//! MCTS-Solver: propagation of proven game-theoretic values through a search
+//! graph.
+//!
+//! A vertex whose game state is terminal is solved with its payoff. A vertex
+//! whose player to move has an action leading to a solved vertex with an
+//! [optimal payoff](../game/trait.Game.html#method.is_optimal_payoff) is
+//! solved with that payoff. Otherwise, a vertex is solved once all of its
+//! legal actions have been added to the graph and lead to solved vertices, with
+//! the payoff that is best for its player to move.
+//!
+//! Repetitions count as proven draws, scored with
+//! [Game::draw_payoff](../game/trait.Game.html#method.draw_payoff), so that
+//! positions from which play can cycle may still be proven. These are edges
+//! that rollout followed back to a vertex on its path under
+//! [CyclePolicy::Draw](../rollout/enum.CyclePolicy.html#variant.Draw), and
+//! actions for which
+//! [Game::repeats_position](../game/trait.Game.html#method.repeats_position)
+//! is `true`. Whether an edge closes a cycle depends on the path taken to it,
+//! so a position proven this way is only proven along the lines that search
+//! has followed.
+//!
+//! Rollout stops at solved vertices, and their proven payoffs are used in place
+//! of simulations.
+
+use crate::game::{Game, State, Statistics};
+use crate::graph::{self, EdgeData, VertexData};
+
+use log::trace;
+
+/// Marks `node` as solved if its game state is terminal, and then re-examines
+/// its ancestors, marking each that can now be proven as solved.
+pub fn update<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+) {
+  let mut pending = Vec::new();
+  if graph.node_data(node).solved() {
+    pending.push(node);
+  } else if let Some(payoff) = G::payoff_of(graph.node_state(node)) {
+    trace!("solver: terminal payoff {:?}", payoff);
+    if !graph.node_data(node).mark_solved(payoff) {
+      pending.push(node);
+    }
+  }
+  propagate(graph, pending);
+}
+
+/// Re-examines the source of `edge`, which rollout has just marked as a
+/// [repetition](../graph/struct.EdgeData.html#method.repetition), and then
+/// its ancestors, marking each that can now be proven as solved.
+pub fn update_repetition<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  edge: search_graph::view::EdgeRef<'id>,
+) {
+  let mut pending = Vec::new();
+  try_solve(graph, graph.edge_source(edge), &mut pending);
+  propagate(graph, pending);
+}
+
+/// Re-examines the parents of each of the newly solved vertices in `pending`,
+/// and in turn the parents of each that is solved.
+fn propagate<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  mut pending: Vec<search_graph::view::NodeRef<'id>>,
+) {
+  while let Some(child) = pending.pop() {
+    for edge in graph.parents(child) {
+      try_solve(graph, graph.edge_source(edge), &mut pending);
+    }
+  }
+}
+
+/// Marks `node` as solved if it can be proven, and adds it to `pending` if so.
+fn try_solve<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+  pending: &mut Vec<search_graph::view::NodeRef<'id>>,
+) {
+  if graph.node_data(node).solved() {
+    return;
+  }
+  if let Some(payoff) = solve(graph, node) {
+    trace!("solver: proved payoff {:?}", payoff);
+    if !graph.node_data(node).mark_solved(payoff) {
+      pending.push(node);
+    }
+  }
+}
+
+/// Returns the proven payoff of following `edge`: a draw if it is a
+/// repetition, and otherwise the proven payoff of its target, if any.
+pub fn edge_solution<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  edge: search_graph::view::EdgeRef<'id>,
+) -> Option<G::Payoff> {
+  let data = graph.edge_data(edge);
+  if data.repetition()
+    || G::repeats_position(graph.node_state(graph.edge_source(edge)), data.action())
+  {
+    if let Some(payoff) = G::draw_payoff() {
+      return Some(payoff);
+    }
+  }
+  graph.node_data(graph.edge_target(edge)).solution()
+}
+
+/// Returns the proven payoff of `node` given the proven payoffs of its
+/// children, or `None` if it cannot yet be proven.
+fn solve<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+) -> Option<G::Payoff> {
+  let state = graph.node_state(node);
+  let player = state.active_player();
+  let mut best = None;
+  let mut best_score = f32::NEG_INFINITY;
+  let mut all_solved = true;
+  for edge in graph.children(node) {
+    match edge_solution(graph, edge) {
+      Some(payoff) => {
+        if G::is_optimal_payoff(state, &payoff) {
+          return Some(payoff);
+        }
+        let score = payoff_score::<G>(&payoff, player);
+        if best.is_none() || score > best_score {
+          best = Some(payoff);
+          best_score = score;
+        }
+      }
+      None => all_solved = false,
+    }
+  }
+  let data = graph.node_data(node);
+  if !all_solved || !data.expanded() || data.action_count() != Some(graph.child_count(node)) {
+    return None;
+  }
+  best
+}
+
+/// Returns the score of `payoff` for `player`, as it would be reported by
+/// statistics that had recorded only `payoff`.
+pub fn payoff_score<G: Game>(payoff: &G::Payoff, player: &G::PlayerId) -> f32 {
+  let statistics = G::Statistics::default();
+  statistics.increment(&graph::copy_payoff::<G>(payoff));
+  statistics.score(player)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::graph::{EdgeData, VertexData};
+  use crate::tictactoe::{self, Player};
+  use crate::{backprop, simulation, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    VertexData<tictactoe::ScoredGame>,
+    EdgeData<tictactoe::ScoredGame>,
+  >;
+
+  fn settings() -> SearchSettings {
+    SearchSettings {
+      solver: true,
+      ..Default::default()
+    }
+  }
+
+  fn state(rows: [&str; 3], active_player: Player) -> tictactoe::State {
+    let mut state = tictactoe::State {
+      active_player,
+      board: tictactoe::Board::new(),
+    };
+    for (row, cells) in rows.iter().enumerate() {
+      for (column, cell) in cells.chars().enumerate() {
+        match cell {
+          'X' => state.board.set(row, column, Player::X),
+          'O' => state.board.set(row, column, Player::O),
+          _ => (),
+        }
+      }
+    }
+    state
+  }
+
+  fn search(graph: &mut Graph, root: tictactoe::State, iterations: usize) {
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(graph, |view| {
+      let mut rollout = RolloutPhase::initialize(&mut rng, settings(), root, view);
+      for _ in 0..iterations {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+  }
+
+  #[test]
+  fn immediate_win_is_proven() {
+    let root = state(["XX_", "OO_", "___"], Player::X);
+    let mut graph = Graph::new();
+    search(&mut graph, root.clone(), 20);
+    let node = graph.find_node(&root).unwrap();
+    let solution = node.get_data().solution().unwrap();
+    assert_eq!((1, 0), (solution.score_one, solution.score_two));
+  }
+
+  #[test]
+  fn forced_draw_is_proven() {
+    let root = state(["XOX", "XOO", "OX_"], Player::X);
+    let mut graph = Graph::new();
+    search(&mut graph, root.clone(), 4);
+    let node = graph.find_node(&root).unwrap();
+    let solution = node.get_data().solution().unwrap();
+    assert_eq!((0, 0), (solution.score_one, solution.score_two));
+  }
+
+  #[test]
+  fn losing_position_is_proven() {
+    // X cannot block both the top row and the left column.
+    let root = state(["OO_", "OXX", "_X_"], Player::X);
+    let mut graph = Graph::new();
+    search(&mut graph, root.clone(), 200);
+    let node = graph.find_node(&root).unwrap();
+    let solution = node.get_data().solution().unwrap();
+    assert_eq!((0, 1), (solution.score_one, solution.score_two));
+  }
+}
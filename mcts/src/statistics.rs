@@ -3,4 +3,6 @@
 //! The statistics types under this module may be adapted for use with various
 //! games.
 
+pub mod multi_player;
+pub mod outcome;
 pub mod two_player;
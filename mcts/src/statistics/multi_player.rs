@@ -0,0 +1,376 @@
+//! Statistics types for games with any number of players.
+//!
+//! Payoffs hold a score for each player, indexed by
+//! [PlayerIndex](trait.PlayerIndex.html). The score that statistics report for
+//! a player is that player's own total, so that rollout selects the child that
+//! is best for the player to move, whatever the other players score.
+
+use crate::game;
+use crate::persist::{self, Persist};
+
+use std::cmp;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::AddAssign;
+use std::sync::atomic;
+
+/// Maps a game's player type to positions in the score vector of a
+/// [MultiPlayerPayoff](struct.MultiPlayerPayoff.html).
+pub trait PlayerIndex: fmt::Debug {
+  /// Returns the number of players in the game.
+  fn player_count() -> usize;
+  /// Returns the position of this player, which is less than `player_count()`.
+  fn index(&self) -> usize;
+}
+
+/// Generic game payoff for a game where each of any number of players gets a
+/// whole-number score at the end of the game. This sums one or more outcomes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MultiPlayerPayoff {
+  pub visits: u32,
+  /// The score of each player, indexed by
+  /// [PlayerIndex::index](trait.PlayerIndex.html#tymethod.index). Players
+  /// beyond the end of this vector have a score of 0.
+  pub scores: Vec<u32>,
+}
+
+impl MultiPlayerPayoff {
+  /// Returns the payoff of a single game that ended with the given score for
+  /// each player.
+  pub fn new(scores: Vec<u32>) -> Self {
+    MultiPlayerPayoff { visits: 1, scores }
+  }
+
+  /// Returns the score of `player`.
+  pub fn score<M: PlayerIndex>(&self, player: &M) -> u32 {
+    self.scores.get(player.index()).cloned().unwrap_or(0)
+  }
+}
+
+impl<'a> AddAssign<&'a MultiPlayerPayoff> for MultiPlayerPayoff {
+  fn add_assign(&mut self, payoff: &'a MultiPlayerPayoff) {
+    self.visits += payoff.visits;
+    if self.scores.len() < payoff.scores.len() {
+      self.scores.resize(payoff.scores.len(), 0);
+    }
+    for (score, other) in self.scores.iter_mut().zip(payoff.scores.iter()) {
+      *score += other;
+    }
+  }
+}
+
+impl Persist for MultiPlayerPayoff {
+  fn persist(&self) -> String {
+    let mut text = self.visits.to_string();
+    for score in self.scores.iter() {
+      text.push_str(&format!(" {}", score));
+    }
+    text
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    let numbers = persist::parse_numbers(text)?;
+    let (&visits, scores) = numbers.split_first()?;
+    Some(MultiPlayerPayoff {
+      visits,
+      scores: scores.to_vec(),
+    })
+  }
+}
+
+/// Atomically mutable game statistics for a game where each of any number of
+/// players gets a whole-number score at the end of the game. This type counts
+/// the number of games that have been observed and the sum of the final score
+/// of each game for each player.
+///
+/// Counters are updated independently of each other, as in
+/// [WideStatistics](../two_player/struct.WideStatistics.html).
+pub struct MultiPlayerStatistics<M: PlayerIndex> {
+  visits: atomic::AtomicU64,
+  scores: Box<[atomic::AtomicU64]>,
+  player: PhantomData<M>,
+}
+
+fn clamp_u32(value: u64) -> u32 {
+  cmp::min(value, u32::MAX as u64) as u32
+}
+
+impl<M: PlayerIndex> MultiPlayerStatistics<M> {
+  /// Creates statistics with no observed outcomes and starting scores of 0 for
+  /// each player.
+  pub fn new() -> Self {
+    MultiPlayerStatistics::from_scores(0, &vec![0; M::player_count()])
+  }
+
+  /// Creates statistics for the given number of observed outcomes (`visits`)
+  /// and the sum of the final scores for each player. Players beyond the end
+  /// of `scores` start with a score of 0.
+  pub fn from_scores(visits: u64, scores: &[u64]) -> Self {
+    MultiPlayerStatistics {
+      visits: atomic::AtomicU64::new(visits),
+      scores: (0..M::player_count())
+        .map(|i| atomic::AtomicU64::new(scores.get(i).cloned().unwrap_or(0)))
+        .collect(),
+      player: PhantomData,
+    }
+  }
+
+  /// Returns the number of outcomes that have been recorded.
+  pub fn visits(&self) -> u64 {
+    self.visits.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Returns the score for `player`.
+  pub fn score(&self, player: &M) -> u64 {
+    self
+      .scores
+      .get(player.index())
+      .map_or(0, |score| score.load(atomic::Ordering::SeqCst))
+  }
+
+  /// Returns the score for each player, in index order.
+  pub fn scores(&self) -> Vec<u64> {
+    self
+      .scores
+      .iter()
+      .map(|score| score.load(atomic::Ordering::SeqCst))
+      .collect()
+  }
+
+  /// Adds `visits` to the number of outcomes observed and each of `scores` to
+  /// the running total for the player at its index. Scores for players beyond
+  /// `M::player_count()` are ignored.
+  pub fn record_scores(&self, visits: u64, scores: &[u64]) {
+    self.visits.fetch_add(visits, atomic::Ordering::SeqCst);
+    for (total, score) in self.scores.iter().zip(scores.iter()) {
+      total.fetch_add(*score, atomic::Ordering::SeqCst);
+    }
+  }
+}
+
+impl<M: PlayerIndex> Clone for MultiPlayerStatistics<M> {
+  fn clone(&self) -> Self {
+    MultiPlayerStatistics::from_scores(self.visits(), &self.scores())
+  }
+}
+
+impl<M: PlayerIndex> Default for MultiPlayerStatistics<M> {
+  fn default() -> Self {
+    MultiPlayerStatistics::new()
+  }
+}
+
+impl<M: PlayerIndex> fmt::Debug for MultiPlayerStatistics<M> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    write!(
+      f,
+      "Statistics(visits: {}, scores: {:?})",
+      self.visits(),
+      self.scores()
+    )
+  }
+}
+
+impl<M: PlayerIndex> Persist for MultiPlayerStatistics<M> {
+  fn persist(&self) -> String {
+    let mut text = self.visits().to_string();
+    for score in self.scores() {
+      text.push_str(&format!(" {}", score));
+    }
+    text
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    let numbers = persist::parse_numbers(text)?;
+    match numbers.split_first() {
+      Some((&visits, scores)) if scores.len() == M::player_count() => {
+        Some(MultiPlayerStatistics::from_scores(visits, scores))
+      }
+      _ => None,
+    }
+  }
+}
+
+impl<S, M> game::Statistics<S, MultiPlayerPayoff> for MultiPlayerStatistics<M>
+where
+  S: game::State<PlayerId = M>,
+  M: PlayerIndex,
+{
+  fn increment(&self, payoff: &MultiPlayerPayoff) {
+    let scores: Vec<u64> = payoff.scores.iter().map(|&s| s as u64).collect();
+    self.record_scores(payoff.visits as u64, &scores)
+  }
+
+  fn visits(&self) -> u32 {
+    clamp_u32(self.visits())
+  }
+
+  fn score(&self, player: &S::PlayerId) -> f32 {
+    self.score(player) as f32
+  }
+
+  fn payoff(&self) -> MultiPlayerPayoff {
+    MultiPlayerPayoff {
+      visits: clamp_u32(self.visits()),
+      scores: self.scores().into_iter().map(clamp_u32).collect(),
+    }
+  }
+
+  fn merge(&self, other: &Self) {
+    self.record_scores(other.visits(), &other.scores())
+  }
+
+  fn saturated(&self) -> bool {
+    self.visits() > u32::MAX as u64 || self.scores().into_iter().any(|s| s > u32::MAX as u64)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{MultiPlayerPayoff, MultiPlayerStatistics, PlayerIndex};
+  use crate::game::{self, Statistics};
+  use crate::normalize::Normalization;
+  use crate::{backprop, simulation, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+
+  /// The number of points at which a game of `Race` ends.
+  const RACE_TARGET: u8 = 10;
+
+  #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+  struct Seat(u8);
+
+  impl PlayerIndex for Seat {
+    fn player_count() -> usize {
+      3
+    }
+    fn index(&self) -> usize {
+      self.0 as usize
+    }
+  }
+
+  /// A three-player game in which each player in turn adds 1 or 2 to a shared
+  /// total. The player who brings the total to `RACE_TARGET` wins.
+  #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+  struct RaceState {
+    total: u8,
+    active: Seat,
+    winner: Option<Seat>,
+  }
+
+  impl game::State for RaceState {
+    type Action = u8;
+    type PlayerId = Seat;
+    type Undo = Seat;
+
+    fn active_player(&self) -> &Seat {
+      &self.active
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<u8>) {
+      actions.clear();
+      if self.winner.is_none() {
+        actions.extend((1..=2).filter(|step| self.total + step <= RACE_TARGET));
+      }
+    }
+
+    fn do_action(&mut self, step: &u8) -> Seat {
+      let active = self.active;
+      self.total += step;
+      if self.total == RACE_TARGET {
+        self.winner = Some(self.active);
+      }
+      self.active = Seat((self.active.0 + 1) % 3);
+      active
+    }
+
+    fn undo_action(&mut self, step: &u8, active: Seat) {
+      self.total -= step;
+      self.winner = None;
+      self.active = active;
+    }
+  }
+
+  #[derive(Debug)]
+  struct Race {}
+
+  impl game::Game for Race {
+    type Action = u8;
+    type PlayerId = Seat;
+    type Payoff = MultiPlayerPayoff;
+    type State = RaceState;
+    type Statistics = MultiPlayerStatistics<Seat>;
+
+    fn payoff_of(state: &RaceState) -> Option<MultiPlayerPayoff> {
+      state.winner.map(|winner| {
+        let mut scores = vec![0; Seat::player_count()];
+        scores[winner.index()] = 1;
+        MultiPlayerPayoff::new(scores)
+      })
+    }
+
+    /// Losers score nothing.
+    fn loss_score(_normalization: Option<&Normalization>) -> f32 {
+      0.0
+    }
+  }
+
+  #[test]
+  fn payoffs_sum_by_player() {
+    let mut payoff = MultiPlayerPayoff::default();
+    payoff += &MultiPlayerPayoff::new(vec![1, 0, 2]);
+    payoff += &MultiPlayerPayoff::new(vec![0, 3]);
+    assert_eq!(2, payoff.visits);
+    assert_eq!(vec![1, 3, 2], payoff.scores);
+    assert_eq!(2, payoff.score(&Seat(2)));
+    assert_eq!(0, MultiPlayerPayoff::default().score(&Seat(1)));
+  }
+
+  #[test]
+  fn statistics_report_each_players_score() {
+    let stats: MultiPlayerStatistics<Seat> = MultiPlayerStatistics::new();
+    Statistics::<RaceState, _>::increment(&stats, &MultiPlayerPayoff::new(vec![1, 0, 0]));
+    Statistics::<RaceState, _>::increment(&stats, &MultiPlayerPayoff::new(vec![0, 0, 4]));
+    assert_eq!(2, Statistics::<RaceState, _>::visits(&stats));
+    assert_eq!(1.0, Statistics::<RaceState, _>::score(&stats, &Seat(0)));
+    assert_eq!(0.0, Statistics::<RaceState, _>::score(&stats, &Seat(1)));
+    assert_eq!(4.0, Statistics::<RaceState, _>::score(&stats, &Seat(2)));
+
+    let other = stats.clone();
+    Statistics::<RaceState, _>::merge(&stats, &other);
+    let payoff = Statistics::<RaceState, _>::payoff(&stats);
+    assert_eq!(4, payoff.visits);
+    assert_eq!(vec![2, 0, 8], payoff.scores);
+  }
+
+  #[test]
+  fn search_finds_winning_move_for_third_player() {
+    let settings = SearchSettings::default();
+    let root = RaceState {
+      total: RACE_TARGET - 2,
+      active: Seat(2),
+      winner: None,
+    };
+    let mut graph = crate::new_search_graph::<Race>();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout = RolloutPhase::initialize(&mut rng, settings, root.clone(), view);
+      for _ in 0..50 {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+    let node = graph.find_node(&root).unwrap();
+    let best = node
+      .get_child_list()
+      .iter()
+      .max_by_key(|c| c.get_data().statistics.visits())
+      .unwrap()
+      .get_target();
+    assert_eq!(Some(Seat(2)), best.get_label().winner);
+  }
+}
@@ -0,0 +1,461 @@
+//! Statistics types for two-player games whose result is an outcome (a win for
+//! one player or a draw) rather than a score.
+//!
+//! Players are designated with the [PlayerMapping](../two_player/trait.PlayerMapping.html)
+//! of the [two_player](../two_player/index.html) module. An outcome may carry
+//! the margin by which it was won, which is used only to break ties between
+//! actions that win equally often.
+
+use super::two_player::{Player, PlayerMapping};
+use crate::game;
+use crate::persist::{self, Persist};
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::AddAssign;
+use std::sync::atomic;
+
+/// The result of a two-player game.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Outcome {
+  /// The game was won by the given player.
+  Win(Player),
+  /// Neither player won.
+  Draw,
+}
+
+/// The bound on the total that margins add to a player's score, as a
+/// fraction of one win.
+pub const MAX_MARGIN_SCORE: f32 = 0.25;
+
+/// Returns what a net margin of `net_margin`, summed over `visits` outcomes,
+/// adds to a player's score: `MAX_MARGIN_SCORE * net_margin / (|net_margin| +
+/// visits)`.
+///
+/// This grows with the margin, so it orders actions whose net wins are equal.
+/// Its magnitude is always less than `MAX_MARGIN_SCORE`, however many outcomes
+/// there are and however wide their margins. So two scores whose net wins
+/// differ by one are still ordered by their wins.
+pub fn margin_score(net_margin: i64, visits: u64) -> f32 {
+  if net_margin == 0 {
+    0.0
+  } else {
+    MAX_MARGIN_SCORE * net_margin as f32 / (net_margin.abs() as f32 + visits as f32)
+  }
+}
+
+/// Generic game payoff for a two-player game whose result is an outcome. This
+/// sums one or more outcomes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OutcomePayoff {
+  pub visits: u32,
+  /// The number of outcomes won by player one.
+  pub wins_one: u32,
+  /// The number of outcomes won by player two.
+  pub wins_two: u32,
+  /// The sum of the margins by which player one's score exceeded player
+  /// two's, or 0 for games that have no margin.
+  pub margin: i64,
+}
+
+impl OutcomePayoff {
+  /// Returns the payoff of a single game that ended with `outcome`.
+  pub fn of_outcome(outcome: Outcome) -> Self {
+    OutcomePayoff::with_margin(outcome, 0)
+  }
+
+  /// Returns the payoff of a single game that ended with `outcome`, in which
+  /// player one's score exceeded player two's by `margin`.
+  pub fn with_margin(outcome: Outcome, margin: i64) -> Self {
+    let (wins_one, wins_two) = match outcome {
+      Outcome::Win(Player::One) => (1, 0),
+      Outcome::Win(Player::Two) => (0, 1),
+      Outcome::Draw => (0, 0),
+    };
+    OutcomePayoff {
+      visits: 1,
+      wins_one,
+      wins_two,
+      margin,
+    }
+  }
+
+  /// Returns the number of outcomes that neither player won.
+  pub fn draws(&self) -> u32 {
+    self.visits.saturating_sub(self.wins_one + self.wins_two)
+  }
+}
+
+impl<'a> AddAssign<&'a OutcomePayoff> for OutcomePayoff {
+  fn add_assign(&mut self, payoff: &'a OutcomePayoff) {
+    self.visits += payoff.visits;
+    self.wins_one += payoff.wins_one;
+    self.wins_two += payoff.wins_two;
+    self.margin += payoff.margin;
+  }
+}
+
+impl Persist for OutcomePayoff {
+  fn persist(&self) -> String {
+    format!(
+      "{} {} {} {}",
+      self.visits, self.wins_one, self.wins_two, self.margin
+    )
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    match persist::parse_numbers::<i64>(text)?[..] {
+      [visits, wins_one, wins_two, margin] => Some(OutcomePayoff {
+        visits: u32::try_from(visits).ok()?,
+        wins_one: u32::try_from(wins_one).ok()?,
+        wins_two: u32::try_from(wins_two).ok()?,
+        margin,
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// Atomically mutable game statistics for a two-player game whose result is an
+/// outcome. This type counts the number of games that have been observed, the
+/// number won by each player, and the sum of their margins.
+///
+/// The score of a player is its number of wins less its number of losses, plus
+/// the [margin_score](fn.margin_score.html) of its net margin.
+/// Counters are updated independently of each other, as in
+/// [WideStatistics](../two_player/struct.WideStatistics.html).
+pub struct OutcomeStatistics<M: PlayerMapping> {
+  visits: atomic::AtomicU64,
+  wins_one: atomic::AtomicU64,
+  wins_two: atomic::AtomicU64,
+  margin: atomic::AtomicI64,
+  player: PhantomData<M>,
+}
+
+impl<M: PlayerMapping> OutcomeStatistics<M> {
+  /// Creates statistics with no observed outcomes.
+  pub fn new() -> Self {
+    OutcomeStatistics::from_outcomes(0, 0, 0, 0)
+  }
+
+  /// Creates statistics for the given number of observed outcomes (`visits`),
+  /// the number won by each player, and the sum of their margins for player
+  /// one.
+  pub fn from_outcomes(visits: u64, wins_one: u64, wins_two: u64, margin: i64) -> Self {
+    OutcomeStatistics {
+      visits: atomic::AtomicU64::new(visits),
+      wins_one: atomic::AtomicU64::new(wins_one),
+      wins_two: atomic::AtomicU64::new(wins_two),
+      margin: atomic::AtomicI64::new(margin),
+      player: PhantomData,
+    }
+  }
+
+  /// Returns the number of outcomes that have been recorded.
+  pub fn visits(&self) -> u64 {
+    self.visits.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Returns the number of outcomes won by `player`.
+  pub fn wins(&self, player: Player) -> u64 {
+    match player {
+      Player::One => self.wins_one.load(atomic::Ordering::SeqCst),
+      Player::Two => self.wins_two.load(atomic::Ordering::SeqCst),
+    }
+  }
+
+  /// Returns the number of outcomes that neither player won.
+  pub fn draws(&self) -> u64 {
+    self
+      .visits()
+      .saturating_sub(self.wins(Player::One) + self.wins(Player::Two))
+  }
+
+  /// Returns the sum of the margins of all outcomes for `player`.
+  pub fn net_margin(&self, player: Player) -> i64 {
+    let margin = self.margin.load(atomic::Ordering::SeqCst);
+    match player {
+      Player::One => margin,
+      Player::Two => -margin,
+    }
+  }
+
+  /// Returns the number of outcomes won by `player` less the number that it
+  /// lost.
+  pub fn net_wins(&self, player: Player) -> i64 {
+    let wins_one = self.wins(Player::One) as i64;
+    let wins_two = self.wins(Player::Two) as i64;
+    match player {
+      Player::One => wins_one - wins_two,
+      Player::Two => wins_two - wins_one,
+    }
+  }
+
+  /// Adds `visits` to the number of outcomes observed, the given numbers of
+  /// wins to each player's total, and `margin` to player one's net margin.
+  pub fn record_outcomes(&self, visits: u64, wins_one: u64, wins_two: u64, margin: i64) {
+    self.visits.fetch_add(visits, atomic::Ordering::SeqCst);
+    self.wins_one.fetch_add(wins_one, atomic::Ordering::SeqCst);
+    self.wins_two.fetch_add(wins_two, atomic::Ordering::SeqCst);
+    self.margin.fetch_add(margin, atomic::Ordering::SeqCst);
+  }
+}
+
+impl<M: PlayerMapping> Clone for OutcomeStatistics<M> {
+  fn clone(&self) -> Self {
+    OutcomeStatistics::from_outcomes(
+      self.visits(),
+      self.wins(Player::One),
+      self.wins(Player::Two),
+      self.net_margin(Player::One),
+    )
+  }
+}
+
+impl<M: PlayerMapping> Default for OutcomeStatistics<M> {
+  fn default() -> Self {
+    OutcomeStatistics::new()
+  }
+}
+
+impl<M: PlayerMapping> fmt::Debug for OutcomeStatistics<M> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    write!(
+      f,
+      "Statistics(visits: {}, {:?} wins: {}, {:?} wins: {}, margin: {})",
+      self.visits(),
+      M::player_one(),
+      self.wins(Player::One),
+      M::player_two(),
+      self.wins(Player::Two),
+      self.net_margin(Player::One)
+    )
+  }
+}
+
+fn clamp_u32(value: u64) -> u32 {
+  cmp::min(value, u32::MAX as u64) as u32
+}
+
+impl<M: PlayerMapping> Persist for OutcomeStatistics<M> {
+  fn persist(&self) -> String {
+    format!(
+      "{} {} {} {}",
+      self.visits(),
+      self.wins(Player::One),
+      self.wins(Player::Two),
+      self.net_margin(Player::One)
+    )
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    match persist::parse_numbers::<i64>(text)?[..] {
+      [visits, wins_one, wins_two, margin] => Some(OutcomeStatistics::from_outcomes(
+        u64::try_from(visits).ok()?,
+        u64::try_from(wins_one).ok()?,
+        u64::try_from(wins_two).ok()?,
+        margin,
+      )),
+      _ => None,
+    }
+  }
+}
+
+impl<S, M> game::Statistics<S, OutcomePayoff> for OutcomeStatistics<M>
+where
+  S: game::State<PlayerId = M>,
+  M: PlayerMapping,
+{
+  fn increment(&self, payoff: &OutcomePayoff) {
+    self.record_outcomes(
+      payoff.visits as u64,
+      payoff.wins_one as u64,
+      payoff.wins_two as u64,
+      payoff.margin,
+    )
+  }
+
+  fn visits(&self) -> u32 {
+    clamp_u32(self.visits())
+  }
+
+  fn score(&self, player: &S::PlayerId) -> f32 {
+    let player = player.resolve_player();
+    self.net_wins(player) as f32 + margin_score(self.net_margin(player), self.visits())
+  }
+
+  fn payoff(&self) -> OutcomePayoff {
+    OutcomePayoff {
+      visits: clamp_u32(self.visits()),
+      wins_one: clamp_u32(self.wins(Player::One)),
+      wins_two: clamp_u32(self.wins(Player::Two)),
+      margin: self.net_margin(Player::One),
+    }
+  }
+
+  fn merge(&self, other: &Self) {
+    self.record_outcomes(
+      other.visits(),
+      other.wins(Player::One),
+      other.wins(Player::Two),
+      other.net_margin(Player::One),
+    )
+  }
+
+  fn saturated(&self) -> bool {
+    self.visits() > u32::MAX as u64
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{margin_score, Outcome, OutcomePayoff, OutcomeStatistics};
+  use crate::game::{self, Statistics};
+  use crate::statistics::two_player::Player;
+  use crate::tictactoe::{self, OutcomeGame};
+  use crate::{backprop, simulation, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+
+  fn score(stats: &OutcomeStatistics<tictactoe::Player>, player: tictactoe::Player) -> f32 {
+    Statistics::<tictactoe::State, OutcomePayoff>::score(stats, &player)
+  }
+
+  fn record(stats: &OutcomeStatistics<tictactoe::Player>, payoff: OutcomePayoff) {
+    Statistics::<tictactoe::State, OutcomePayoff>::increment(stats, &payoff)
+  }
+
+  #[test]
+  fn statistics_count_outcomes() {
+    let stats: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    record(&stats, OutcomePayoff::of_outcome(Outcome::Win(Player::One)));
+    record(&stats, OutcomePayoff::of_outcome(Outcome::Win(Player::One)));
+    record(&stats, OutcomePayoff::of_outcome(Outcome::Win(Player::Two)));
+    record(&stats, OutcomePayoff::of_outcome(Outcome::Draw));
+    assert_eq!(4, stats.visits());
+    assert_eq!(2, stats.wins(Player::One));
+    assert_eq!(1, stats.wins(Player::Two));
+    assert_eq!(1, stats.draws());
+    assert_eq!(1.0, score(&stats, tictactoe::Player::X));
+    assert_eq!(-1.0, score(&stats, tictactoe::Player::O));
+
+    let payoff = Statistics::<tictactoe::State, _>::payoff(&stats);
+    assert_eq!(
+      (4, 2, 1, 1),
+      (
+        payoff.visits,
+        payoff.wins_one,
+        payoff.wins_two,
+        payoff.draws()
+      )
+    );
+
+    let other: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    record(&other, OutcomePayoff::of_outcome(Outcome::Win(Player::Two)));
+    Statistics::<tictactoe::State, _>::merge(&stats, &other);
+    assert_eq!(5, stats.visits());
+    assert_eq!(0.0, score(&stats, tictactoe::Player::X));
+  }
+
+  #[test]
+  fn margin_breaks_ties_between_equal_win_counts() {
+    let narrow: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    record(
+      &narrow,
+      OutcomePayoff::with_margin(Outcome::Win(Player::One), 1),
+    );
+    let wide: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    record(
+      &wide,
+      OutcomePayoff::with_margin(Outcome::Win(Player::One), 20),
+    );
+    let draw: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    record(&draw, OutcomePayoff::of_outcome(Outcome::Draw));
+    assert!(score(&wide, tictactoe::Player::X) > score(&narrow, tictactoe::Player::X));
+    assert!(score(&narrow, tictactoe::Player::X) > score(&draw, tictactoe::Player::X));
+    assert_eq!(
+      1.0 + margin_score(20, 1),
+      score(&wide, tictactoe::Player::X)
+    );
+    assert_eq!(
+      -1.0 - margin_score(20, 1),
+      score(&wide, tictactoe::Player::O)
+    );
+  }
+
+  #[test]
+  fn margin_never_outweighs_a_win() {
+    // Player one wins one more game with `narrow` than with `wide`, but with
+    // `narrow` it wins by a point and loses by 32, and with `wide` the reverse.
+    let narrow: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    let wide: OutcomeStatistics<tictactoe::Player> = OutcomeStatistics::new();
+    for _ in 0..32 {
+      record(
+        &narrow,
+        OutcomePayoff::with_margin(Outcome::Win(Player::One), 1),
+      );
+      record(
+        &narrow,
+        OutcomePayoff::with_margin(Outcome::Win(Player::Two), -32),
+      );
+      record(
+        &wide,
+        OutcomePayoff::with_margin(Outcome::Win(Player::One), 32),
+      );
+      record(
+        &wide,
+        OutcomePayoff::with_margin(Outcome::Win(Player::Two), -1),
+      );
+    }
+    record(
+      &narrow,
+      OutcomePayoff::with_margin(Outcome::Win(Player::One), 1),
+    );
+    record(&wide, OutcomePayoff::of_outcome(Outcome::Draw));
+    assert_eq!(1, narrow.net_wins(Player::One));
+    assert_eq!(0, wide.net_wins(Player::One));
+    assert!(narrow.net_margin(Player::One) < -900);
+    assert!(wide.net_margin(Player::One) > 900);
+    assert!(score(&narrow, tictactoe::Player::X) > score(&wide, tictactoe::Player::X));
+    assert!(score(&narrow, tictactoe::Player::O) < score(&wide, tictactoe::Player::O));
+  }
+
+  #[test]
+  fn search_finds_winning_move_by_outcome() {
+    use game::Game;
+    let settings = SearchSettings::default();
+    let mut root = tictactoe::State::default();
+    for &(row, column) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+      let action = tictactoe::Action {
+        row,
+        column,
+        player: root.active_player,
+      };
+      game::State::do_action(&mut root, &action);
+    }
+    let mut graph = crate::new_search_graph::<OutcomeGame>();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout = RolloutPhase::initialize(&mut rng, settings, root.clone(), view);
+      for _ in 0..200 {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+    let node = graph.find_node(&root).unwrap();
+    let best = node
+      .get_child_list()
+      .iter()
+      .max_by_key(|c| c.get_data().statistics.visits())
+      .unwrap()
+      .get_target();
+    let outcome = OutcomeGame::payoff_of(best.get_label()).unwrap();
+    assert_eq!(1, outcome.wins_one);
+  }
+}
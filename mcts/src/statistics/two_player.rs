@@ -1,6 +1,8 @@
 //! Statistics types for two-player games.
 
 use crate::game;
+use crate::normalize::Normalization;
+use crate::persist::{self, Persist};
 
 use std::cmp;
 use std::fmt;
@@ -8,6 +10,7 @@ use std::marker::PhantomData;
 use std::ops::AddAssign;
 use std::sync::atomic;
 
+use rand::Rng;
 use syncbox::atomic::AtomicU64;
 
 /// Generic player designations for a two-player game.
@@ -54,6 +57,40 @@ impl Default for ScoredPayoff {
   }
 }
 
+impl ScoredPayoff {
+  /// Returns this payoff with each of its outcomes normalised by
+  /// `normalization`, given the mean margin of player one's score over player
+  /// two's.
+  ///
+  /// Normalisation gives player one a share `v` in [0, 1] of each outcome,
+  /// which should be recorded as a net score of `2v - 1`. Since scores are
+  /// whole numbers, each outcome is instead recorded as a win for player one
+  /// with probability `2v - 1` if that is positive, as a win for player two
+  /// with probability `1 - 2v` if that is positive, and otherwise as a draw
+  /// with no score for either player. The expected net score of each outcome
+  /// is then `2v - 1`, so that mean scores lie in [-1, 1].
+  pub fn normalized<R: Rng>(&self, normalization: &Normalization, rng: &mut R) -> ScoredPayoff {
+    let mut normalized = ScoredPayoff {
+      visits: self.visits,
+      score_one: 0,
+      score_two: 0,
+    };
+    if self.visits == 0 {
+      return normalized;
+    }
+    let margin = (self.score_one as f64 - self.score_two as f64) / self.visits as f64;
+    let net = 2.0 * normalization.value(margin) - 1.0;
+    for _ in 0..self.visits {
+      if net >= 1.0 || (net > 0.0 && rng.gen::<f64>() < net) {
+        normalized.score_one += 1;
+      } else if net <= -1.0 || (net < 0.0 && rng.gen::<f64>() < -net) {
+        normalized.score_two += 1;
+      }
+    }
+    normalized
+  }
+}
+
 impl<'a> AddAssign<&'a ScoredPayoff> for ScoredPayoff {
   fn add_assign(&mut self, payoff: &'a ScoredPayoff) {
     self.visits += payoff.visits;
@@ -62,6 +99,23 @@ impl<'a> AddAssign<&'a ScoredPayoff> for ScoredPayoff {
   }
 }
 
+impl Persist for ScoredPayoff {
+  fn persist(&self) -> String {
+    format!("{} {} {}", self.visits, self.score_one, self.score_two)
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    match persist::parse_numbers(text)?[..] {
+      [visits, score_one, score_two] => Some(ScoredPayoff {
+        visits,
+        score_one,
+        score_two,
+      }),
+      _ => None,
+    }
+  }
+}
+
 /// Atomically mutable game statistics for a two-player game where each player
 /// gets a whole-number score at the end of the game. This type counts the
 /// number of games that have been observed and the sum of the final score of
@@ -152,14 +206,22 @@ impl<M: PlayerMapping> ScoredStatistics<M> {
   ///
   /// If any field becomes saturated, it will stay at its maximum value.
   pub fn record_final_score(&self, score_one: u32, score_two: u32) {
+    self.record_scores(1, score_one, score_two)
+  }
+
+  /// Adds `visits` to the number of outcomes observed and each score to the
+  /// running total for its respective player.
+  ///
+  /// If any field becomes saturated, it will stay at its maximum value.
+  pub fn record_scores(&self, visits: u32, score_one: u32, score_two: u32) {
     let mut success = false;
     // CAS loop because we have multiple fields to check for saturation.
     while !success {
       let old_packed = self.packed.load(atomic::Ordering::SeqCst);
       let (old_visits, old_score_one, old_score_two) = unpack_scores(old_packed);
-      let visits = cmp::min(old_visits + 1, VISITS_MAX);
-      let score_one = cmp::min(old_score_one + score_one, SCORE_MAX);
-      let score_two = cmp::min(old_score_two + score_two, SCORE_MAX);
+      let visits = cmp::min(old_visits.saturating_add(visits), VISITS_MAX);
+      let score_one = cmp::min(old_score_one.saturating_add(score_one), SCORE_MAX);
+      let score_two = cmp::min(old_score_two.saturating_add(score_two), SCORE_MAX);
       success = self.packed.compare_and_swap(
         old_packed,
         pack_scores(visits, score_one, score_two),
@@ -198,13 +260,31 @@ impl<M: PlayerMapping> fmt::Debug for ScoredStatistics<M> {
   }
 }
 
+impl<M: PlayerMapping> Persist for ScoredStatistics<M> {
+  fn persist(&self) -> String {
+    let (visits, score_one, score_two) = unpack_scores(self.packed.load(atomic::Ordering::SeqCst));
+    format!("{} {} {}", visits, score_one, score_two)
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    match persist::parse_numbers(text)?[..] {
+      [visits, score_one, score_two]
+        if visits <= VISITS_MAX && score_one <= SCORE_MAX && score_two <= SCORE_MAX =>
+      {
+        Some(ScoredStatistics::from_scores(visits, score_one, score_two))
+      }
+      _ => None,
+    }
+  }
+}
+
 impl<S, M> game::Statistics<S, ScoredPayoff> for ScoredStatistics<M>
 where
   S: game::State<PlayerId = M>,
   M: PlayerMapping,
 {
   fn increment(&self, payoff: &ScoredPayoff) {
-    self.record_final_score(payoff.score_one, payoff.score_two)
+    self.record_scores(payoff.visits, payoff.score_one, payoff.score_two)
   }
 
   fn visits(&self) -> u32 {
@@ -214,11 +294,371 @@ where
   fn score(&self, player: &S::PlayerId) -> f32 {
     self.net_score(player.resolve_player()) as f32
   }
+
+  fn payoff(&self) -> ScoredPayoff {
+    let (visits, score_one, score_two) = unpack_scores(self.packed.load(atomic::Ordering::SeqCst));
+    ScoredPayoff {
+      visits,
+      score_one,
+      score_two,
+    }
+  }
+
+  fn merge(&self, other: &Self) {
+    let (visits, score_one, score_two) = unpack_scores(other.packed.load(atomic::Ordering::SeqCst));
+    self.record_scores(visits, score_one, score_two)
+  }
+
+  fn saturated(&self) -> bool {
+    let (visits, score_one, score_two) = unpack_scores(self.packed.load(atomic::Ordering::SeqCst));
+    visits == VISITS_MAX || score_one == SCORE_MAX || score_two == SCORE_MAX
+  }
+}
+
+/// Atomically mutable game statistics for a two-player game where each player
+/// gets a whole-number score at the end of the game, like
+/// [ScoredStatistics](struct.ScoredStatistics.html) but with a separate 64-bit
+/// counter for visits and for each player's score.
+///
+/// The counters are updated independently of each other, so a concurrent reader
+/// may observe an outcome that has been counted in some fields but not yet in
+/// others. The `Statistics` methods that report counters as `u32` values clamp
+/// them to `u32::MAX`, and `saturated` reports when this happens.
+pub struct WideStatistics<M: PlayerMapping> {
+  visits: atomic::AtomicU64,
+  score_one: atomic::AtomicU64,
+  score_two: atomic::AtomicU64,
+  player: PhantomData<M>,
+}
+
+fn saturating_add(counter: &atomic::AtomicU64, value: u64) {
+  let mut old = counter.load(atomic::Ordering::SeqCst);
+  loop {
+    match counter.compare_exchange_weak(
+      old,
+      old.saturating_add(value),
+      atomic::Ordering::SeqCst,
+      atomic::Ordering::SeqCst,
+    ) {
+      Ok(_) => return,
+      Err(current) => old = current,
+    }
+  }
+}
+
+fn clamp_u32(value: u64) -> u32 {
+  cmp::min(value, u32::MAX as u64) as u32
+}
+
+impl<M: PlayerMapping> WideStatistics<M> {
+  /// Creates statistics with no observed outcomes and starting scores of 0 for
+  /// each player.
+  pub fn new() -> Self {
+    WideStatistics::from_scores(0, 0, 0)
+  }
+
+  /// Creates statistics for the given number of observed outcomes (`visits`)
+  /// and the sum of the final scores for each player.
+  pub fn from_scores(visits: u64, score_one: u64, score_two: u64) -> Self {
+    WideStatistics {
+      visits: atomic::AtomicU64::new(visits),
+      score_one: atomic::AtomicU64::new(score_one),
+      score_two: atomic::AtomicU64::new(score_two),
+      player: PhantomData,
+    }
+  }
+
+  /// Returns the number of outcomes that have been recorded.
+  pub fn visits(&self) -> u64 {
+    self.visits.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Returns the net score for `player`, which is equal to the difference
+  /// between `player`'s score and that of the opponent.
+  pub fn net_score(&self, player: Player) -> i64 {
+    let score_one = self.score(Player::One) as i64;
+    let score_two = self.score(Player::Two) as i64;
+    match player {
+      Player::One => score_one - score_two,
+      Player::Two => score_two - score_one,
+    }
+  }
+
+  /// Returns the score for `player`.
+  pub fn score(&self, player: Player) -> u64 {
+    match player {
+      Player::One => self.score_one.load(atomic::Ordering::SeqCst),
+      Player::Two => self.score_two.load(atomic::Ordering::SeqCst),
+    }
+  }
+
+  /// Adds `visits` to the number of outcomes observed and each score to the
+  /// running total for its respective player.
+  ///
+  /// If any field becomes saturated, it will stay at its maximum value.
+  pub fn record_scores(&self, visits: u64, score_one: u64, score_two: u64) {
+    saturating_add(&self.visits, visits);
+    saturating_add(&self.score_one, score_one);
+    saturating_add(&self.score_two, score_two);
+  }
+}
+
+impl<M: PlayerMapping> Clone for WideStatistics<M> {
+  fn clone(&self) -> Self {
+    WideStatistics::from_scores(
+      self.visits(),
+      self.score(Player::One),
+      self.score(Player::Two),
+    )
+  }
+}
+
+impl<M: PlayerMapping> Default for WideStatistics<M> {
+  fn default() -> Self {
+    WideStatistics::new()
+  }
+}
+
+impl<M: PlayerMapping> fmt::Debug for WideStatistics<M> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    write!(
+      f,
+      "Statistics(visits: {}, {:?}: {}, {:?}: {})",
+      self.visits(),
+      M::player_one(),
+      self.score(Player::One),
+      M::player_two(),
+      self.score(Player::Two)
+    )
+  }
+}
+
+impl<M: PlayerMapping> Persist for WideStatistics<M> {
+  fn persist(&self) -> String {
+    format!(
+      "{} {} {}",
+      self.visits(),
+      self.score(Player::One),
+      self.score(Player::Two)
+    )
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    match persist::parse_numbers(text)?[..] {
+      [visits, score_one, score_two] => {
+        Some(WideStatistics::from_scores(visits, score_one, score_two))
+      }
+      _ => None,
+    }
+  }
+}
+
+impl<S, M> game::Statistics<S, ScoredPayoff> for WideStatistics<M>
+where
+  S: game::State<PlayerId = M>,
+  M: PlayerMapping,
+{
+  fn increment(&self, payoff: &ScoredPayoff) {
+    self.record_scores(
+      payoff.visits as u64,
+      payoff.score_one as u64,
+      payoff.score_two as u64,
+    )
+  }
+
+  fn visits(&self) -> u32 {
+    clamp_u32(self.visits())
+  }
+
+  fn score(&self, player: &S::PlayerId) -> f32 {
+    self.net_score(player.resolve_player()) as f32
+  }
+
+  fn payoff(&self) -> ScoredPayoff {
+    ScoredPayoff {
+      visits: clamp_u32(self.visits()),
+      score_one: clamp_u32(self.score(Player::One)),
+      score_two: clamp_u32(self.score(Player::Two)),
+    }
+  }
+
+  fn merge(&self, other: &Self) {
+    self.record_scores(
+      other.visits(),
+      other.score(Player::One),
+      other.score(Player::Two),
+    )
+  }
+
+  fn saturated(&self) -> bool {
+    let limit = u32::MAX as u64;
+    self.visits() > limit || self.score(Player::One) > limit || self.score(Player::Two) > limit
+  }
+}
+
+/// Game statistics for a two-player game where each player gets a
+/// whole-number score at the end of the game, like
+/// [WideStatistics](struct.WideStatistics.html), that also track the sum of the
+/// squared net score of each outcome. This gives the variance of the net score,
+/// which is the same for both players.
+pub struct VarianceStatistics<M: PlayerMapping> {
+  totals: WideStatistics<M>,
+  squared_net_score: atomic::AtomicU64,
+}
+
+impl<M: PlayerMapping> VarianceStatistics<M> {
+  /// Creates statistics with no observed outcomes.
+  pub fn new() -> Self {
+    VarianceStatistics {
+      totals: WideStatistics::new(),
+      squared_net_score: atomic::AtomicU64::new(0),
+    }
+  }
+
+  /// Returns the number of outcomes that have been recorded.
+  pub fn visits(&self) -> u64 {
+    self.totals.visits()
+  }
+
+  /// Returns the net score for `player`, which is equal to the difference
+  /// between `player`'s score and that of the opponent.
+  pub fn net_score(&self, player: Player) -> i64 {
+    self.totals.net_score(player)
+  }
+
+  /// Returns the score for `player`.
+  pub fn score(&self, player: Player) -> u64 {
+    self.totals.score(player)
+  }
+
+  /// Returns the sum over all recorded outcomes of the square of their net
+  /// score.
+  pub fn squared_net_score(&self) -> u64 {
+    self.squared_net_score.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Returns the variance of the net score per outcome, or 0 if no outcomes
+  /// have been recorded.
+  pub fn net_score_variance(&self) -> f64 {
+    let visits = self.visits() as f64;
+    if visits == 0.0 {
+      return 0.0;
+    }
+    let mean = self.net_score(Player::One) as f64 / visits;
+    let mean_square = self.squared_net_score() as f64 / visits;
+    f64::max(0.0, mean_square - mean * mean)
+  }
+
+  /// Increments the number of outcomes observed by 1 and adds each score to the
+  /// running total for its respective player.
+  pub fn record_final_score(&self, score_one: u32, score_two: u32) {
+    self.record_scores(1, score_one, score_two)
+  }
+
+  /// Adds `visits` to the number of outcomes observed and each score to the
+  /// running total for its respective player. The outcomes are taken to have
+  /// the same net score, so that the squared net score of their mean is added
+  /// once for each of them.
+  pub fn record_scores(&self, visits: u32, score_one: u32, score_two: u32) {
+    self
+      .totals
+      .record_scores(visits as u64, score_one as u64, score_two as u64);
+    if visits > 0 {
+      let net = (score_one as i64 - score_two as i64) as f64;
+      saturating_add(
+        &self.squared_net_score,
+        (net * net / visits as f64).round() as u64,
+      );
+    }
+  }
+}
+
+impl<M: PlayerMapping> Clone for VarianceStatistics<M> {
+  fn clone(&self) -> Self {
+    VarianceStatistics {
+      totals: self.totals.clone(),
+      squared_net_score: atomic::AtomicU64::new(self.squared_net_score()),
+    }
+  }
+}
+
+impl<M: PlayerMapping> Default for VarianceStatistics<M> {
+  fn default() -> Self {
+    VarianceStatistics::new()
+  }
+}
+
+impl<M: PlayerMapping> fmt::Debug for VarianceStatistics<M> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    write!(
+      f,
+      "Statistics(visits: {}, {:?}: {}, {:?}: {}, variance: {})",
+      self.visits(),
+      M::player_one(),
+      self.score(Player::One),
+      M::player_two(),
+      self.score(Player::Two),
+      self.net_score_variance()
+    )
+  }
+}
+
+impl<M: PlayerMapping> Persist for VarianceStatistics<M> {
+  fn persist(&self) -> String {
+    format!("{} {}", self.totals.persist(), self.squared_net_score())
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    match persist::parse_numbers(text)?[..] {
+      [visits, score_one, score_two, squared_net_score] => Some(VarianceStatistics {
+        totals: WideStatistics::from_scores(visits, score_one, score_two),
+        squared_net_score: atomic::AtomicU64::new(squared_net_score),
+      }),
+      _ => None,
+    }
+  }
+}
+
+impl<S, M> game::Statistics<S, ScoredPayoff> for VarianceStatistics<M>
+where
+  S: game::State<PlayerId = M>,
+  M: PlayerMapping,
+{
+  fn increment(&self, payoff: &ScoredPayoff) {
+    self.record_scores(payoff.visits, payoff.score_one, payoff.score_two)
+  }
+
+  fn visits(&self) -> u32 {
+    clamp_u32(self.visits())
+  }
+
+  fn score(&self, player: &S::PlayerId) -> f32 {
+    self.net_score(player.resolve_player()) as f32
+  }
+
+  fn payoff(&self) -> ScoredPayoff {
+    game::Statistics::<S, ScoredPayoff>::payoff(&self.totals)
+  }
+
+  fn merge(&self, other: &Self) {
+    game::Statistics::<S, ScoredPayoff>::merge(&self.totals, &other.totals);
+    saturating_add(&self.squared_net_score, other.squared_net_score());
+  }
+
+  fn score_variance(&self, _player: &S::PlayerId) -> Option<f32> {
+    Some(self.net_score_variance() as f32)
+  }
+
+  fn saturated(&self) -> bool {
+    game::Statistics::<S, ScoredPayoff>::saturated(&self.totals)
+      || self.squared_net_score() == u64::MAX
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use super::{Player, ScoredStatistics};
+  use super::{Player, ScoredPayoff, ScoredStatistics, VarianceStatistics, WideStatistics};
+  use crate::{game, tictactoe};
   use std::u32;
 
   #[test]
@@ -305,4 +745,135 @@ mod test {
     assert_eq!(0x3FFFFF - 1, stats.score(Player::One));
     assert_eq!(0x3FFFFF, stats.score(Player::Two));
   }
+
+  #[test]
+  fn statistics_merge() {
+    let stats: ScoredStatistics<tictactoe::Player> = ScoredStatistics::from_scores(2, 10, 4);
+    let other: ScoredStatistics<tictactoe::Player> = ScoredStatistics::from_scores(3, 1, 20);
+    game::Statistics::<tictactoe::State, ScoredPayoff>::merge(&stats, &other);
+    assert_eq!(5, stats.visits());
+    assert_eq!(11, stats.score(Player::One));
+    assert_eq!(24, stats.score(Player::Two));
+    assert_eq!(
+      ScoredPayoff {
+        visits: 5,
+        score_one: 11,
+        score_two: 24,
+      },
+      game::Statistics::<tictactoe::State, ScoredPayoff>::payoff(&stats)
+    );
+    assert_eq!(3, other.visits());
+  }
+
+  #[test]
+  fn statistics_saturation_is_reported() {
+    let stats: ScoredStatistics<tictactoe::Player> =
+      ScoredStatistics::from_scores(0xFFFFF - 1, 0, 0);
+    assert!(!game::Statistics::<tictactoe::State, ScoredPayoff>::saturated(&stats));
+    stats.record_final_score(0, 0);
+    assert!(game::Statistics::<tictactoe::State, ScoredPayoff>::saturated(&stats));
+  }
+
+  #[test]
+  fn wide_statistics_count_past_packed_limits() {
+    let stats: WideStatistics<tictactoe::Player> =
+      WideStatistics::from_scores(0xFFFFF, 0x3FFFFF, 0);
+    stats.record_scores(1, 1, 2);
+    assert_eq!(0x100000, stats.visits());
+    assert_eq!(0x400000, stats.score(Player::One));
+    assert_eq!(2, stats.score(Player::Two));
+    assert_eq!(0x400000 - 2, stats.net_score(Player::One));
+    assert!(!game::Statistics::<tictactoe::State, ScoredPayoff>::saturated(&stats));
+
+    let other: WideStatistics<tictactoe::Player> =
+      WideStatistics::from_scores(u32::MAX as u64, 0, 0);
+    game::Statistics::<tictactoe::State, ScoredPayoff>::merge(&stats, &other);
+    assert!(game::Statistics::<tictactoe::State, ScoredPayoff>::saturated(&stats));
+    assert_eq!(
+      u32::MAX,
+      game::Statistics::<tictactoe::State, ScoredPayoff>::payoff(&stats).visits
+    );
+  }
+
+  #[test]
+  fn wide_statistics_saturate() {
+    let stats: WideStatistics<Player> = WideStatistics::from_scores(u64::MAX - 1, 0, 0);
+    stats.record_scores(5, 0, 0);
+    assert_eq!(u64::MAX, stats.visits());
+  }
+
+  #[test]
+  fn variance_statistics_track_net_score_variance() {
+    let stats: VarianceStatistics<tictactoe::Player> = VarianceStatistics::new();
+    assert_eq!(0.0, stats.net_score_variance());
+    // Net scores for player one of 2, -2, 4 and 0.
+    stats.record_final_score(3, 1);
+    stats.record_final_score(0, 2);
+    stats.record_final_score(4, 0);
+    stats.record_final_score(5, 5);
+    assert_eq!(4, stats.visits());
+    assert_eq!(4, stats.net_score(Player::One));
+    assert_eq!(24, stats.squared_net_score());
+    // Mean 1, mean square 6.
+    assert!((stats.net_score_variance() - 5.0).abs() < 1e-9);
+    assert_eq!(
+      Some(5.0),
+      game::Statistics::<tictactoe::State, ScoredPayoff>::score_variance(
+        &stats,
+        &tictactoe::Player::O
+      )
+    );
+
+    let other: VarianceStatistics<tictactoe::Player> = VarianceStatistics::new();
+    other.record_final_score(1, 0);
+    game::Statistics::<tictactoe::State, ScoredPayoff>::merge(&stats, &other);
+    assert_eq!(5, stats.visits());
+    assert_eq!(25, stats.squared_net_score());
+  }
+
+  #[test]
+  fn normalized_payoff_preserves_expected_margin() {
+    use crate::normalize::Normalization;
+    use rand::SeedableRng;
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let wdl = Normalization::WinDrawLoss;
+    let win = ScoredPayoff {
+      visits: 2,
+      score_one: 20,
+      score_two: 4,
+    };
+    assert_eq!(
+      ScoredPayoff {
+        visits: 2,
+        score_one: 2,
+        score_two: 0
+      },
+      win.normalized(&wdl, &mut rng)
+    );
+    let draw = ScoredPayoff {
+      visits: 1,
+      score_one: 7,
+      score_two: 7,
+    };
+    assert_eq!(
+      ScoredPayoff {
+        visits: 1,
+        score_one: 0,
+        score_two: 0
+      },
+      draw.normalized(&wdl, &mut rng)
+    );
+
+    // A margin of -16 out of 32 is a share of 1/4, or a net score of -1/2.
+    let linear = Normalization::Linear { max_margin: 32.0 };
+    let loss = ScoredPayoff {
+      visits: 10000,
+      score_one: 0,
+      score_two: 160000,
+    };
+    let normalized = loss.normalized(&linear, &mut rng);
+    assert_eq!(10000, normalized.visits);
+    assert_eq!(0, normalized.score_one);
+    assert!((normalized.score_two as f64 / 10000.0 - 0.5).abs() < 0.02);
+  }
 }
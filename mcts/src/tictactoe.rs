@@ -1,4 +1,4 @@
-use crate::{game, statistics};
+use crate::{game, normalize, persist, statistics};
 use r4::iterate;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -34,6 +34,36 @@ pub struct Action {
   pub player: Player,
 }
 
+impl persist::Persist for Action {
+  fn persist(&self) -> String {
+    let player = match self.player {
+      Player::X => 'X',
+      Player::O => 'O',
+    };
+    format!("{}{}{}", player, self.row, self.column)
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    let mut chars = text.chars();
+    let player = match chars.next()? {
+      'X' => Player::X,
+      'O' => Player::O,
+      _ => return None,
+    };
+    let row = chars.next()?.to_digit(10)? as usize;
+    let column = chars.next()?.to_digit(10)? as usize;
+    if row < 3 && column < 3 && chars.next().is_none() {
+      Some(Action {
+        row,
+        column,
+        player,
+      })
+    } else {
+      None
+    }
+  }
+}
+
 fn winning_player(a: Option<Player>, b: Option<Player>, c: Option<Player>) -> Option<Player> {
   if a.is_some() && a == b && b == c {
     a
@@ -61,6 +91,13 @@ impl Board {
     self.cells[row][column] = Some(value);
   }
 
+  pub fn clear(&mut self, row: usize, column: usize) {
+    assert!(row < 3);
+    assert!(column < 3);
+    assert!(self.cells[row][column].is_some());
+    self.cells[row][column] = None;
+  }
+
   pub fn get(&self, row: usize, column: usize) -> Option<Player> {
     assert!(row < 3);
     assert!(column < 3);
@@ -116,16 +153,18 @@ impl Default for State {
 impl game::State for State {
   type Action = Action;
   type PlayerId = Player;
+  type Undo = ();
 
   fn active_player(&self) -> &Player {
     &self.active_player
   }
 
-  fn actions<'s>(&'s self) -> Box<dyn Iterator<Item = Action> + 's> {
-    Box::new(iterate![for row in 0..3;
-                      for column in 0..3;
-                      if self.board.get(row, column).is_none();
-                      yield Action { row, column, player: self.active_player, }])
+  fn fill_actions(&self, actions: &mut Vec<Action>) {
+    actions.clear();
+    actions.extend(iterate![for row in 0..3;
+                            for column in 0..3;
+                            if self.board.get(row, column).is_none();
+                            yield Action { row, column, player: self.active_player, }]);
   }
 
   fn do_action(&mut self, action: &Action) {
@@ -135,6 +174,11 @@ impl game::State for State {
       Player::O => Player::X,
     };
   }
+
+  fn undo_action(&mut self, action: &Action, _undo: ()) {
+    self.board.clear(action.row, action.column);
+    self.active_player = action.player;
+  }
 }
 
 #[derive(Debug)]
@@ -166,4 +210,50 @@ impl game::Game for ScoredGame {
       },
     })
   }
+
+  fn is_optimal_payoff(state: &State, payoff: &statistics::two_player::ScoredPayoff) -> bool {
+    match state.active_player {
+      Player::X => payoff.score_one > payoff.score_two,
+      Player::O => payoff.score_two > payoff.score_one,
+    }
+  }
+
+  fn normalize_payoff<R: rand::Rng>(
+    payoff: &statistics::two_player::ScoredPayoff,
+    normalization: &normalize::Normalization,
+    rng: &mut R,
+  ) -> statistics::two_player::ScoredPayoff {
+    payoff.normalized(normalization, rng)
+  }
+}
+
+/// Tic-tac-toe scored by outcome, so that a win counts the same however the
+/// game was played.
+#[derive(Debug)]
+pub struct OutcomeGame {}
+
+impl game::Game for OutcomeGame {
+  type Action = Action;
+  type PlayerId = Player;
+  type Payoff = statistics::outcome::OutcomePayoff;
+  type State = State;
+  type Statistics = statistics::outcome::OutcomeStatistics<Player>;
+
+  fn payoff_of(state: &State) -> Option<statistics::outcome::OutcomePayoff> {
+    state.board.outcome().map(|p| {
+      statistics::outcome::OutcomePayoff::of_outcome(match p {
+        Outcome::Winner(player) => statistics::outcome::Outcome::Win(
+          statistics::two_player::PlayerMapping::resolve_player(&player),
+        ),
+        Outcome::Tie => statistics::outcome::Outcome::Draw,
+      })
+    })
+  }
+
+  fn is_optimal_payoff(state: &State, payoff: &statistics::outcome::OutcomePayoff) -> bool {
+    match state.active_player {
+      Player::X => payoff.wins_one > payoff.wins_two,
+      Player::O => payoff.wins_two > payoff.wins_one,
+    }
+  }
 }
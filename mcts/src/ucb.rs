@@ -42,7 +42,7 @@ where
 {
   log_parent_visits: f64,
   explore_bias: f64,
-  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   edges: I,
 }
 
@@ -80,7 +80,7 @@ where
   pub fn new(
     log_parent_visits: f64,
     explore_bias: f64,
-    graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &'b search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
     edges: I,
   ) -> Self {
     EdgeUcbIter {
@@ -101,7 +101,7 @@ where
 
   fn next(&mut self) -> Option<Result<UcbSuccess<'id>, UcbError>> {
     self.edges.next().map(|e| {
-      if self.graph.edge_data(e).statistics.visits() == 0 {
+      if self.graph.edge_data(e).visits_with_virtual_loss() == 0 {
         trace!("EdgeUcbIter selects unvisited action");
         Ok(UcbSuccess::Select(e))
       } else {
@@ -125,195 +125,216 @@ where
 }
 
 /// Returns the UCB policy result for the given values.
+///
+/// Any virtual loss on `child` is counted as visits that lost the game for the
+/// player to move at its parent.
 pub fn child_score<'a, 'id, G: Game>(
   log_parent_visits: f64,
   explore_bias: f64,
-  graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   child: search_graph::view::EdgeRef<'id>,
 ) -> UcbSuccess<'id> {
-  let statistics = &graph.edge_data(child).statistics;
-  if statistics.visits() == 0 {
+  let data = graph.edge_data(child);
+  if data.visits_with_virtual_loss() == 0 {
     UcbSuccess::Select(child)
   } else {
-    let child_visits = statistics.visits() as f64;
-    let child_score =
-      statistics.score(graph.node_state(graph.edge_source(child)).active_player()) as f64;
-    let ucb =
-      child_score / child_visits + explore_bias * f64::sqrt(log_parent_visits / child_visits);
-    UcbSuccess::Value(child, ucb)
+    let child_visits = data.visits_with_virtual_loss() as f64;
+    let child_score = data
+      .score_with_virtual_loss(graph.node_state(graph.edge_source(child)).active_player())
+      as f64;
+    UcbSuccess::Value(
+      child,
+      ucb_value(log_parent_visits, explore_bias, child_visits, child_score),
+    )
   }
 }
 
-/// Returns `true` iff `e` could be selected by the UCB policy during rollout
-/// from its parent vertex. Assumes we haven't yet altered the parent vertex
-/// statistics. (Callers should ensure that this is not called more than once
-/// for a given parent vertex.)
-///
-/// "Could be" reflects how this is different from calling `find_best_child`: it
-/// is possible (and common) for multiple child edges of a vertex to be best
-/// children (as when there are multiple children that have not yet been
-/// explored). This is dealt with in `find_best_child` by randomly choosing one
-/// of the best children. But when doing backpropagation on a full game state
-/// graph (not just a tree), we want to know all of the parent edges which could
-/// have rolled out to a given child.
-pub fn is_best_child<'a, 'id, G: Game>(
-  graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
-  e: search_graph::view::EdgeRef<'id>,
+/// Returns the UCB1 value of a child with `child_visits` visits and a total
+/// score of `child_score` for the player to move at its parent.
+pub fn ucb_value(
+  log_parent_visits: f64,
   explore_bias: f64,
-) -> bool {
-  let statistics = &graph.edge_data(e).statistics;
-  // trace!("is_best_child: edge {} has {} visits", e.get_id(), stats.visits);
-  if statistics.visits() == 0 {
-    // Edge has been visited, but statistics aren't yet updated.
-    // trace!("is_best_child: edge {} is a best child because stats.visits == 0", e.get_id());
-    return true;
+  child_visits: f64,
+  child_score: f64,
+) -> f64 {
+  child_score / child_visits + explore_bias * f64::sqrt(log_parent_visits / child_visits)
+}
+
+/// Returns the UCB1 value of `child`, or infinity if `child` has not been
+/// visited.
+fn child_value<'a, 'id, G: Game>(
+  log_parent_visits: f64,
+  explore_bias: f64,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  child: search_graph::view::EdgeRef<'id>,
+) -> f64 {
+  match child_score(log_parent_visits, explore_bias, graph, child) {
+    UcbSuccess::Select(_) => f64::INFINITY,
+    UcbSuccess::Value(_, v) => v,
   }
-  let parent = graph.edge_source(e);
-  // trace!("is_best_child: edge {} (from node {}) has {} siblings", e.get_id(), parent.get_id(), siblings.len());
-  let log_parent_visits = {
-    let mut parent_visits = 0;
-    for child_edge in graph.children(parent) {
-      parent_visits += graph.edge_data(child_edge).statistics.visits();
-    }
+}
+
+/// Returns the natural log of the total visits to the children of `parent`,
+/// counting virtual loss, or 0 if there are none.
+pub fn log_parent_visits<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  parent: search_graph::view::NodeRef<'id>,
+) -> f64 {
+  let parent_visits: u32 = graph
+    .children(parent)
+    .map(|child| graph.edge_data(child).visits_with_virtual_loss())
+    .sum();
+  if parent_visits == 0 {
+    // When we visit a vertex for the first time, it will have zero visits.
+    0.0
+  } else {
     f64::ln(parent_visits as f64)
-  };
-  let mut edge_ucb = None;
-  let mut best_ucb = ::std::f64::MIN;
-  let ucb_iter = EdgeUcbIter::new(
-    log_parent_visits,
-    explore_bias,
-    graph,
-    graph.children(parent),
-  );
-  // Scan through siblings to find the maximum UCB score. This is
-  // short-circuited using a lazy iterator to ameliorate the O(n) running
-  // time.
-  for ucb in ucb_iter {
-    match ucb {
-      Ok(UcbSuccess::Select(sibling)) if e == sibling => {
-        // trace!("is_best_child: edge {} is best child by fiat of score computation", e.get_id());
-        return true;
-      }
-      Ok(UcbSuccess::Select(_)) => {
-        if let Some(_) = edge_ucb {
-          // trace!("is_best_child: edge {} has a ucb of {}, but sibling edge {} is best child by fiat of score computation", e.get_id(), u, sibling.get_id());
-          return false;
-        }
-      }
-      Ok(UcbSuccess::Value(sibling, score)) => {
-        if sibling == e {
-          if best_ucb > score {
-            // trace!("is_best_child: found ucb {} for edge {}, but a sibling has a higher ucb of {}", score, e.get_id(), best_ucb);
-            return false;
-          }
-          edge_ucb = Some(score)
-        } else if let Some(u) = edge_ucb {
-          if score > u {
-            // trace!("is_best_child: found ucb {} for edge {}, but a sibling has a higher ucb of {}", score, e.get_id(), best_ucb);
-            return false;
-          }
-        }
-        if score > best_ucb {
-          best_ucb = score;
-        }
-      }
-      Err(e) => panic!("error {:?} computing ucb for best child search", e),
-    }
-  }
-  match edge_ucb {
-    Some(u) if u >= best_ucb => {
-      // Target edge has a UCB score which matches the maximum we found.
-      // trace!("is_best_child: edge {} has a ucb of {}, which matches the max sibling ucb of {}", e.get_id(), u, best_ucb);
-      true
-    }
-    _ => {
-      // ThudEdges are not best children by default.
-      // trace!("is_best_child: edge {} has a ucb of {:?}, which does not match the max sibling ucb of {}", e.get_id(), edge_ucb, best_ucb);
-      false
-    }
   }
 }
 
-/// Returns the child edge of `parent` that is best according to the UCB1
-/// criterion.
+/// Returns the child edge of `parent` for which `child_value` is greatest.
+/// Ties are broken uniformly at random, by reservoir sampling.
+///
+/// This is the common part of rollout for selectors that score each child
+/// independently, such as UCB1, [PUCT](../puct/index.html),
+/// [RAVE](../rave/index.html) and the [variance-aware
+/// bounds](../variance/index.html).
 ///
 /// This function will panic if `parent` has no children.
-pub fn find_best_child<'a, 'id, G, R>(
-  graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+pub fn find_best_child_by<'a, 'id, G, R, F>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
   parent: search_graph::view::NodeRef<'id>,
-  explore_bias: f64,
   rng: &mut R,
+  mut child_value: F,
 ) -> Result<search_graph::view::EdgeRef<'id>, UcbError>
 where
   G: Game,
   R: Rng,
+  F: FnMut(search_graph::view::EdgeRef<'id>) -> f64,
 {
-  let log_parent_visits = {
-    let mut parent_visits = 0;
-    for child in graph.children(parent) {
-      parent_visits += graph.edge_data(child).statistics.visits();
-    }
-    if parent_visits == 0 {
-      // When we visit a vertex for the first time, it will have zero visits.
-      0.0
-    } else {
-      // Otherwise, it should be positive.
-      f64::ln(parent_visits as f64)
-    }
-  };
-  let mut sampling_count = 0u32;
-  let mut ucb_iter = EdgeUcbIter::new(
-    log_parent_visits,
-    explore_bias,
-    graph,
-    graph.children(parent),
-  );
-  let mut best: search_graph::view::EdgeRef<'id>;
-  let mut best_ucb: f64;
-  match ucb_iter.next().expect("vertex has no children")? {
-    UcbSuccess::Select(e) => {
-      best = e;
-      best_ucb = f64::MAX;
-    }
-    UcbSuccess::Value(e, v) => {
-      best = e;
-      best_ucb = v;
-    }
-  }
-
-  for ucb in ucb_iter {
-    let (edge, value) = match ucb? {
-      UcbSuccess::Select(e) => (e, f64::MAX),
-      UcbSuccess::Value(e, v) => (e, v),
-    };
-    match value.partial_cmp(&best_ucb) {
+  let mut children = graph.children(parent);
+  let mut best = children.next().expect("vertex has no children");
+  let mut best_value = child_value(best);
+  let mut sampling_count = 1u32;
+  for child in children {
+    let value = child_value(child);
+    match value.partial_cmp(&best_value) {
       None => {
         error!("find_best_child: invalid floating-point comparison");
         return Err(UcbError::InvalidComputation);
       }
       Some(Ordering::Greater) => {
-        trace!("find_best_child_edge: new best action with score {}", value);
-        best = edge;
-        best_ucb = value;
+        trace!("find_best_child: new best action with score {}", value);
+        best = child;
+        best_value = value;
         sampling_count = 1;
       }
       Some(Ordering::Equal) => {
-        // We use reservoir sampling to break ties.
         trace!("find_best_child: found action with identical score; sampling to break tie");
         sampling_count += 1;
         if rng.gen_ratio(1, sampling_count) {
-          trace!("find_best_child: broke tie by selecting new action");
-          best = edge;
-        } else {
-          trace!("find_best_child: broke tie by keeping old action");
+          best = child;
         }
       }
       Some(Ordering::Less) => (),
     }
   }
+  if best_value.is_nan() {
+    return Err(UcbError::InvalidComputation);
+  }
   Ok(best)
 }
 
+/// Returns `true` iff `e` could be chosen by
+/// [find_best_child_by](fn.find_best_child_by.html) with the same
+/// `child_value` (i.e., no sibling of `e` has a greater value).
+///
+/// "Could be" reflects that more than one child of a vertex may be a best
+/// child (as when there are several children that have not yet been
+/// explored). When doing backpropagation on a full game state graph (not just
+/// a tree), we want to know all of the parent edges which could have rolled
+/// out to a given child.
+pub fn is_best_child_by<'a, 'id, G, F>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  e: search_graph::view::EdgeRef<'id>,
+  mut child_value: F,
+) -> bool
+where
+  G: Game,
+  F: FnMut(search_graph::view::EdgeRef<'id>) -> f64,
+{
+  let value = child_value(e);
+  graph
+    .children(graph.edge_source(e))
+    .all(|sibling| child_value(sibling) <= value)
+}
+
+/// Returns the parent edges of `node` for which `is_best` holds, for use by
+/// [backprop selectors](../backprop/trait.BackpropSelector.html) that follow
+/// the edges that their rollout policy would have selected.
+pub fn best_parents<'a, 'id, G, F>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+  mut is_best: F,
+) -> std::vec::IntoIter<search_graph::view::EdgeRef<'id>>
+where
+  G: Game,
+  F: FnMut(search_graph::view::EdgeRef<'id>) -> bool,
+{
+  // TODO: this requires an allocation. We have everything in place for
+  // backprop selectors to be allocation-free, except Rust doesn't yet support
+  // ATCs/HKTs. We need ATC support because this iterator type will have its
+  // lifetime constrained by the borrow of `graph` in the select method, but
+  // that lifetime isn't known statically.
+  let result: Vec<search_graph::view::EdgeRef<'id>> = graph
+    .parents(node)
+    .filter(|&parent_edge| is_best(parent_edge))
+    .collect();
+  result.into_iter()
+}
+
+/// Returns `true` iff `e` could be selected by the UCB policy during rollout
+/// from its parent vertex. Assumes we haven't yet altered the parent vertex
+/// statistics. (Callers should ensure that this is not called more than once
+/// for a given parent vertex.)
+///
+/// See [is_best_child_by](fn.is_best_child_by.html).
+pub fn is_best_child<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  e: search_graph::view::EdgeRef<'id>,
+  explore_bias: f64,
+) -> bool {
+  if graph.edge_data(e).statistics.visits() == 0 {
+    // Edge has been visited, but statistics aren't yet updated.
+    return true;
+  }
+  let log_parent_visits = log_parent_visits(graph, graph.edge_source(e));
+  is_best_child_by(graph, e, |child| {
+    child_value(log_parent_visits, explore_bias, graph, child)
+  })
+}
+
+/// Returns the child edge of `parent` that is best according to the UCB1
+/// criterion. Unvisited children are preferred, and ties are broken uniformly
+/// at random.
+///
+/// This function will panic if `parent` has no children.
+pub fn find_best_child<'a, 'id, G, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  parent: search_graph::view::NodeRef<'id>,
+  explore_bias: f64,
+  rng: &mut R,
+) -> Result<search_graph::view::EdgeRef<'id>, UcbError>
+where
+  G: Game,
+  R: Rng,
+{
+  let log_parent_visits = log_parent_visits(graph, parent);
+  find_best_child_by(graph, parent, rng, |child| {
+    child_value(log_parent_visits, explore_bias, graph, child)
+  })
+}
+
 /// [Rollout selector](../rollout/trait.RolloutSelector.html) that chooses a
 /// child with the highest the UCB1 score.
 ///
@@ -336,7 +357,7 @@ impl RolloutSelector for Rollout {
 
   fn select<'a, 'id, G: Game, R: Rng>(
     &self,
-    graph: &search_graph::view::View<'a, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
     parent: search_graph::view::NodeRef<'id>,
     rng: &mut R,
   ) -> Result<search_graph::view::EdgeRef<'id>, UcbError> {
@@ -360,24 +381,15 @@ impl<'a> From<&'a crate::SearchSettings> for BestParentBackprop {
 }
 
 impl<'id> BackpropSelector<'id> for BestParentBackprop {
-  // TODO: this requires an allocation. We have everything in place for this
-  // selector to be allocation-free, except Rust doesn't yet support
-  // ATCs/HKTs. We need ATC support because this iterator type will have its
-  // lifetime constrained by the borrow of `graph` in the select method, but
-  // that lifetime isn't known statically.
   type Items = std::vec::IntoIter<search_graph::view::EdgeRef<'id>>;
 
   fn select<G: Game, R: Rng>(
     &self,
-    graph: &search_graph::view::View<'_, 'id, G::State, VertexData, EdgeData<G>>,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
     node: search_graph::view::NodeRef<'id>,
     _payoff: &G::Payoff,
     _rng: &mut R,
   ) -> Self::Items {
-    let result: Vec<search_graph::view::EdgeRef<'id>> = graph
-      .parents(node)
-      .filter(|&parent_edge| is_best_child(graph, parent_edge, self.explore_bias))
-      .collect();
-    result.into_iter()
+    best_parents(graph, node, |e| is_best_child(graph, e, self.explore_bias))
   }
 }
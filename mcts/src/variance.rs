@@ -0,0 +1,459 @@
+//! Variance-aware upper confidence bounds: UCB1-Tuned and UCB-V.
+//!
+//! Plain [UCB1](../ucb/index.html) explores every child at a rate that depends
+//! only on its number of visits. The selectors here also use the variance of
+//! each child's score, which requires statistics that track it (see
+//! `Statistics::score_variance` and
+//! [VarianceStatistics](../statistics/two_player/struct.VarianceStatistics.html)).
+//! Children whose scores vary little are explored less, so that search spends
+//! fewer visits on moves that are consistently bad.
+//!
+//! Both bounds are applied to scores rescaled from the game's
+//! [ScoreRange](struct.ScoreRange.html) to `[0, 1]`, so that `explore_bias`
+//! has the same meaning as for normalised payoffs. If a child's statistics do
+//! not track variance, it is taken to be `1/4`, the largest possible variance
+//! of a score in `[0, 1]`.
+
+use crate::backprop::BackpropSelector;
+use crate::game::{Game, State, Statistics};
+use crate::graph::{EdgeData, VertexData};
+use crate::normalize::Normalization;
+use crate::rollout::RolloutSelector;
+use crate::ucb::{self, UcbError};
+use rand::Rng;
+use search_graph;
+
+use std::f64;
+use std::result::Result;
+
+/// Variance assumed for children whose statistics do not track it, after
+/// rescaling to `[0, 1]`.
+const DEFAULT_VARIANCE: f64 = 0.25;
+
+/// The range of scores that one visit can add for the player to move, from
+/// that of a lost game to that of a won game. Scores are rescaled from this
+/// range to `[0, 1]` before a [Bound](enum.Bound.html) is applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreRange {
+  /// The score of a lost game. See `Game::loss_score`.
+  pub loss: f64,
+  /// The score of a won game. See `Game::win_score`.
+  pub win: f64,
+}
+
+impl ScoreRange {
+  /// Returns the range of scores of the game `G` when simulated payoffs are
+  /// normalised by `normalization`.
+  pub fn of<G: Game>(normalization: Option<&Normalization>) -> Self {
+    ScoreRange {
+      loss: G::loss_score(normalization) as f64,
+      win: G::win_score(normalization) as f64,
+    }
+  }
+
+  /// Returns the value under `bound` of a child with `visits` visits, a mean
+  /// score of `mean_score` and score variance `variance` (or `None` if its
+  /// statistics do not track variance), after rescaling these from this range
+  /// to `[0, 1]`.
+  pub fn scaled_value(
+    &self,
+    bound: Bound,
+    log_parent_visits: f64,
+    explore_bias: f64,
+    visits: f64,
+    mean_score: f64,
+    variance: Option<f64>,
+  ) -> f64 {
+    let width = self.win - self.loss;
+    let mean_score = (mean_score - self.loss) / width;
+    let variance = variance.map_or(DEFAULT_VARIANCE, |v| v / (width * width));
+    bound.value(
+      log_parent_visits,
+      explore_bias,
+      visits,
+      mean_score,
+      variance,
+    )
+  }
+}
+
+/// An upper confidence bound that uses the variance of each child's score.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+  /// The UCB1-Tuned bound of Auer et al.:
+  ///
+  /// `mean + C * sqrt(ln(N) / n * min(1/4, variance + sqrt(2 ln(N) / n)))`
+  ///
+  /// The cap of `1/4` is the largest variance of a score in `[0, 1]`, which is
+  /// why scores are rescaled to that range first.
+  Tuned,
+  /// The UCB-V bound of Audibert et al.:
+  ///
+  /// `mean + sqrt(2 * variance * ln(N) / n) + C * 3 * ln(N) / n`
+  ///
+  /// where `C` plays the role of the range of scores.
+  V,
+}
+
+impl Bound {
+  /// Returns the value of this bound for a child with `visits` visits, a mean
+  /// score of `mean_score` for the player to move at its parent, and score
+  /// variance `variance`, whose parent has `ln(N) = log_parent_visits`. `C` is
+  /// `explore_bias`.
+  pub fn value(
+    &self,
+    log_parent_visits: f64,
+    explore_bias: f64,
+    visits: f64,
+    mean_score: f64,
+    variance: f64,
+  ) -> f64 {
+    match *self {
+      Bound::Tuned => {
+        let variance_bound = variance + f64::sqrt(2.0 * log_parent_visits / visits);
+        mean_score
+          + explore_bias
+            * f64::sqrt(log_parent_visits / visits * f64::min(DEFAULT_VARIANCE, variance_bound))
+      }
+      Bound::V => {
+        mean_score
+          + f64::sqrt(2.0 * variance * log_parent_visits / visits)
+          + explore_bias * 3.0 * log_parent_visits / visits
+      }
+    }
+  }
+}
+
+/// Returns the value of `child` under `bound`, with scores rescaled from
+/// `range`, or infinity if `child` has not been visited.
+///
+/// Any virtual loss on `child` is counted as visits that lost the game for the
+/// player to move at its parent.
+pub fn child_score<'a, 'id, G: Game>(
+  bound: Bound,
+  range: ScoreRange,
+  log_parent_visits: f64,
+  explore_bias: f64,
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  child: search_graph::view::EdgeRef<'id>,
+) -> f64 {
+  let data = graph.edge_data(child);
+  let visits = data.visits_with_virtual_loss() as f64;
+  if visits == 0.0 {
+    return f64::INFINITY;
+  }
+  let player = graph.node_state(graph.edge_source(child)).active_player();
+  let mean_score = data.score_with_virtual_loss(player) as f64 / visits;
+  let variance = data.statistics.score_variance(player).map(|v| v as f64);
+  range.scaled_value(
+    bound,
+    log_parent_visits,
+    explore_bias,
+    visits,
+    mean_score,
+    variance,
+  )
+}
+
+/// Returns the child edge of `parent` that is best according to `bound`, with
+/// scores rescaled from `range`.
+/// Unvisited children are preferred, and ties are broken uniformly at random.
+///
+/// This function will panic if `parent` has no children.
+pub fn find_best_child<'a, 'id, G, R>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  parent: search_graph::view::NodeRef<'id>,
+  bound: Bound,
+  range: ScoreRange,
+  explore_bias: f64,
+  rng: &mut R,
+) -> Result<search_graph::view::EdgeRef<'id>, UcbError>
+where
+  G: Game,
+  R: Rng,
+{
+  let log_parent_visits = ucb::log_parent_visits(graph, parent);
+  ucb::find_best_child_by(graph, parent, rng, |child| {
+    child_score(bound, range, log_parent_visits, explore_bias, graph, child)
+  })
+}
+
+/// Returns `true` iff `e` could be selected under `bound`, with scores
+/// rescaled from `range`, during rollout from its parent vertex (i.e., no
+/// sibling of `e` has a higher value).
+pub fn is_best_child<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  e: search_graph::view::EdgeRef<'id>,
+  bound: Bound,
+  range: ScoreRange,
+  explore_bias: f64,
+) -> bool {
+  let log_parent_visits = ucb::log_parent_visits(graph, graph.edge_source(e));
+  ucb::is_best_child_by(graph, e, |child| {
+    child_score(bound, range, log_parent_visits, explore_bias, graph, child)
+  })
+}
+
+/// [Rollout selector](../rollout/trait.RolloutSelector.html) that chooses a
+/// child with the highest [UCB1-Tuned](enum.Bound.html#variant.Tuned) value.
+pub struct TunedRollout {
+  explore_bias: f64,
+  normalization: Option<Normalization>,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for TunedRollout {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    TunedRollout {
+      explore_bias: settings.explore_bias,
+      normalization: settings.payoff_normalization,
+    }
+  }
+}
+
+impl RolloutSelector for TunedRollout {
+  type Error = UcbError;
+
+  fn select<'a, 'id, G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    parent: search_graph::view::NodeRef<'id>,
+    rng: &mut R,
+  ) -> Result<search_graph::view::EdgeRef<'id>, UcbError> {
+    let range = ScoreRange::of::<G>(self.normalization.as_ref());
+    find_best_child(graph, parent, Bound::Tuned, range, self.explore_bias, rng)
+  }
+}
+
+/// [Backprop selector](../backprop/trait.BackpropSelector.html) that traverses
+/// upward edges that would have been selected by [UCB1-Tuned
+/// rollout](struct.TunedRollout.html).
+pub struct TunedBackprop {
+  explore_bias: f64,
+  normalization: Option<Normalization>,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for TunedBackprop {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    TunedBackprop {
+      explore_bias: settings.explore_bias,
+      normalization: settings.payoff_normalization,
+    }
+  }
+}
+
+impl<'id> BackpropSelector<'id> for TunedBackprop {
+  type Items = std::vec::IntoIter<search_graph::view::EdgeRef<'id>>;
+
+  fn select<G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    node: search_graph::view::NodeRef<'id>,
+    _payoff: &G::Payoff,
+    _rng: &mut R,
+  ) -> Self::Items {
+    let range = ScoreRange::of::<G>(self.normalization.as_ref());
+    ucb::best_parents(graph, node, |e| {
+      is_best_child(graph, e, Bound::Tuned, range, self.explore_bias)
+    })
+  }
+}
+
+/// [Rollout selector](../rollout/trait.RolloutSelector.html) that chooses a
+/// child with the highest [UCB-V](enum.Bound.html#variant.V) value.
+pub struct UcbVRollout {
+  explore_bias: f64,
+  normalization: Option<Normalization>,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for UcbVRollout {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    UcbVRollout {
+      explore_bias: settings.explore_bias,
+      normalization: settings.payoff_normalization,
+    }
+  }
+}
+
+impl RolloutSelector for UcbVRollout {
+  type Error = UcbError;
+
+  fn select<'a, 'id, G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    parent: search_graph::view::NodeRef<'id>,
+    rng: &mut R,
+  ) -> Result<search_graph::view::EdgeRef<'id>, UcbError> {
+    let range = ScoreRange::of::<G>(self.normalization.as_ref());
+    find_best_child(graph, parent, Bound::V, range, self.explore_bias, rng)
+  }
+}
+
+/// [Backprop selector](../backprop/trait.BackpropSelector.html) that traverses
+/// upward edges that would have been selected by [UCB-V
+/// rollout](struct.UcbVRollout.html).
+pub struct UcbVBackprop {
+  explore_bias: f64,
+  normalization: Option<Normalization>,
+}
+
+impl<'a> From<&'a crate::SearchSettings> for UcbVBackprop {
+  fn from(settings: &'a crate::SearchSettings) -> Self {
+    UcbVBackprop {
+      explore_bias: settings.explore_bias,
+      normalization: settings.payoff_normalization,
+    }
+  }
+}
+
+impl<'id> BackpropSelector<'id> for UcbVBackprop {
+  type Items = std::vec::IntoIter<search_graph::view::EdgeRef<'id>>;
+
+  fn select<G: Game, R: Rng>(
+    &self,
+    graph: &search_graph::view::View<'_, 'id, G::State, VertexData<G>, EdgeData<G>>,
+    node: search_graph::view::NodeRef<'id>,
+    _payoff: &G::Payoff,
+    _rng: &mut R,
+  ) -> Self::Items {
+    let range = ScoreRange::of::<G>(self.normalization.as_ref());
+    ucb::best_parents(graph, node, |e| {
+      is_best_child(graph, e, Bound::V, range, self.explore_bias)
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Bound, ScoreRange, TunedBackprop, TunedRollout, UcbVBackprop, UcbVRollout};
+  use crate::backprop::BackpropSelector;
+  use crate::graph::{EdgeData, VertexData};
+  use crate::rollout::RolloutSelector;
+  use crate::statistics::two_player::Player;
+  use crate::{simulation, tictactoe, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  type Graph = search_graph::Graph<
+    tictactoe::State,
+    VertexData<tictactoe::ScoredGame>,
+    EdgeData<tictactoe::ScoredGame>,
+  >;
+
+  fn search<RS, B>(settings: SearchSettings) -> Graph
+  where
+    RS: RolloutSelector,
+    B: for<'id> BackpropSelector<'id>,
+  {
+    let mut graph = Graph::new();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings, tictactoe::State::default(), view);
+      for _ in 0..50 {
+        rollout = rollout
+          .rollout::<RS>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<B>()
+          .expand();
+      }
+    });
+    graph
+  }
+
+  #[test]
+  fn variance_selectors_search() {
+    for graph in [
+      search::<TunedRollout, TunedBackprop>(SearchSettings::default()),
+      search::<UcbVRollout, UcbVBackprop>(SearchSettings::default()),
+    ]
+    .iter()
+    {
+      let root = graph.find_node(&tictactoe::State::default()).unwrap();
+      let children = root.get_child_list();
+      assert_eq!(9, children.len());
+      for child in children.iter() {
+        assert!(child.get_data().statistics.visits() > 0);
+      }
+    }
+  }
+
+  #[test]
+  fn several_simulations_keep_scores_in_range() {
+    let settings = SearchSettings {
+      simulation_count: 4,
+      simulation_thread_limit: 2,
+      ..Default::default()
+    };
+    let range = ScoreRange::of::<tictactoe::ScoredGame>(None);
+    for graph in [
+      search::<TunedRollout, TunedBackprop>(settings),
+      search::<UcbVRollout, UcbVBackprop>(settings),
+    ]
+    .iter()
+    {
+      let root = graph.find_node(&tictactoe::State::default()).unwrap();
+      let mut root_visits = 0;
+      for child in root.get_child_list().iter() {
+        let statistics = &child.get_data().statistics;
+        let visits = statistics.visits();
+        root_visits += visits;
+        let mean_score = statistics.net_score(Player::One) as f64 / visits as f64;
+        assert!(range.loss <= mean_score && mean_score <= range.win);
+      }
+      // Each of the 49 rollouts below the root simulated 4 playouts, unless
+      // it reached the end of the game.
+      assert!(root_visits > 49);
+    }
+  }
+
+  #[test]
+  fn low_variance_narrows_tuned_bound() {
+    let log_n = f64::ln(100000.0);
+    let certain = Bound::Tuned.value(log_n, 1.0, 10000.0, 0.2, 0.0);
+    let uncertain = Bound::Tuned.value(log_n, 1.0, 10000.0, 0.2, 0.25);
+    assert!(certain < uncertain);
+    // The bound never exceeds UCB1 with half the exploration bias.
+    let ucb1 = crate::ucb::ucb_value(log_n, 0.5, 10000.0, 2000.0);
+    assert!(uncertain <= ucb1 + 1e-12);
+  }
+
+  #[test]
+  fn ucb_v_shrinks_with_variance_and_visits() {
+    let log_n = f64::ln(1000.0);
+    let certain = Bound::V.value(log_n, 1.0, 100.0, 0.2, 0.0);
+    let uncertain = Bound::V.value(log_n, 1.0, 100.0, 0.2, 0.25);
+    assert!(certain < uncertain);
+    assert!((certain - (0.2 + 3.0 * log_n / 100.0)).abs() < 1e-12);
+    assert!(Bound::V.value(log_n, 1.0, 400.0, 0.2, 0.25) < uncertain);
+  }
+
+  #[test]
+  fn variance_changes_selection_at_thud_scale() {
+    // Net scores of Thud without normalisation lie in [-32, 32].
+    let range = ScoreRange {
+      loss: -32.0,
+      win: 32.0,
+    };
+    let log_n = f64::ln(20000.0);
+    // A child with steady scores, and one with a slightly lower mean whose
+    // scores vary widely.
+    let steady = (4.0, 1.0);
+    let erratic = (3.8, 900.0);
+    let scaled = |(mean, variance)| {
+      range.scaled_value(Bound::Tuned, log_n, 1.0, 10000.0, mean, Some(variance))
+    };
+    assert!(scaled(erratic) > scaled(steady));
+    // Without rescaling, the cap of 1/4 on the variance term applies to both,
+    // so the variance makes no difference and the higher mean is chosen.
+    let unscaled = |(mean, variance)| Bound::Tuned.value(log_n, 1.0, 10000.0, mean, variance);
+    assert!(unscaled(steady) > unscaled(erratic));
+    // Statistics that do not track variance are given the largest variance in
+    // the rescaled range.
+    assert_eq!(
+      range.scaled_value(Bound::V, log_n, 1.0, 10000.0, 0.0, Some(1024.0)),
+      range.scaled_value(Bound::V, log_n, 1.0, 10000.0, 0.0, None)
+    );
+  }
+}
@@ -0,0 +1,143 @@
+//! Progressive widening of search graph vertices.
+//!
+//! Without widening, [expansion](../expand/fn.expand.html) adds an edge for
+//! every legal action at a vertex as soon as it is expanded. With progressive
+//! widening, a vertex starts out with only a few children, in the order given
+//! by [Game::order_actions](../game/trait.Game.html), and more are added as
+//! the vertex gathers visits. This bounds the number of game states that are
+//! cloned into the graph for vertices that are rarely visited, so that search
+//! can go deeper within the same budget.
+
+use crate::game::{Game, Statistics};
+use crate::graph::{EdgeData, VertexData};
+
+use std::cmp;
+
+/// Controls how many children a vertex may have given the number of times it
+/// has been visited.
+///
+/// A vertex with `n` visits may have up to `ceil(constant * (n + 1) ^
+/// exponent)` children, and always at least one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgressiveWidening {
+  /// Scales the number of children allowed at every visit count.
+  pub constant: f64,
+  /// Controls how quickly the number of children grows with the number of
+  /// visits. This should be between 0 and 1.
+  pub exponent: f64,
+}
+
+impl ProgressiveWidening {
+  /// Returns the maximum number of children of a vertex that has been visited
+  /// `visits` times.
+  pub fn child_limit(&self, visits: u32) -> usize {
+    let limit = f64::ceil(self.constant * f64::powf(visits as f64 + 1.0, self.exponent));
+    if limit.is_finite() && limit > 1.0 {
+      limit as usize
+    } else {
+      1
+    }
+  }
+}
+
+/// Returns the number of visits to `node`, which is the total number of
+/// visits to its children.
+pub fn visits<'a, 'id, G: Game>(
+  graph: &search_graph::view::View<'a, 'id, G::State, VertexData<G>, EdgeData<G>>,
+  node: search_graph::view::NodeRef<'id>,
+) -> u32 {
+  graph
+    .children(node)
+    .map(|child| graph.edge_data(child).statistics.visits())
+    .fold(0, u32::saturating_add)
+}
+
+/// Returns the number of children that `node` should have after it has been
+/// visited `visits` times, given that it has `action_count` legal actions.
+pub fn target_child_count(
+  widening: &ProgressiveWidening,
+  visits: u32,
+  action_count: usize,
+) -> usize {
+  cmp::min(widening.child_limit(visits), action_count)
+}
+
+#[cfg(test)]
+mod test {
+  use super::ProgressiveWidening;
+  use crate::graph::{EdgeData, VertexData};
+  use crate::{backprop, simulation, tictactoe, ucb, RolloutPhase, SearchSettings};
+  use rand::SeedableRng;
+  use rand_pcg;
+
+  #[test]
+  fn child_limit_grows_with_visits() {
+    let widening = ProgressiveWidening {
+      constant: 1.0,
+      exponent: 0.5,
+    };
+    assert_eq!(1, widening.child_limit(0));
+    assert_eq!(2, widening.child_limit(1));
+    assert_eq!(2, widening.child_limit(3));
+    assert_eq!(3, widening.child_limit(4));
+    assert_eq!(10, widening.child_limit(99));
+    let degenerate = ProgressiveWidening {
+      constant: 0.0,
+      exponent: 0.5,
+    };
+    assert_eq!(1, degenerate.child_limit(1000));
+  }
+
+  #[test]
+  fn search_widens_root_gradually() {
+    let settings = SearchSettings {
+      simulation_count: 1,
+      simulation_thread_limit: 1,
+      explore_bias: 1.0,
+      search_thread_count: 1,
+      virtual_loss: 1,
+      rave_schedule: None,
+      progressive_widening: Some(ProgressiveWidening {
+        constant: 1.0,
+        exponent: 0.5,
+      }),
+      playout: Default::default(),
+      max_playout_depth: None,
+      solver: false,
+      payoff_normalization: None,
+      cycle_policy: Default::default(),
+    };
+    let mut graph = search_graph::Graph::<
+      tictactoe::State,
+      VertexData<tictactoe::ScoredGame>,
+      EdgeData<tictactoe::ScoredGame>,
+    >::new();
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    search_graph::view::of_graph(&mut graph, |view| {
+      let mut rollout =
+        RolloutPhase::initialize(&mut rng, settings, tictactoe::State::default(), view);
+      for _ in 0..16 {
+        rollout = rollout
+          .rollout::<ucb::Rollout>()
+          .unwrap()
+          .score::<simulation::RandomSimulator>()
+          .unwrap()
+          .backprop::<backprop::FirstParentSelector>()
+          .expand();
+      }
+    });
+
+    let root = graph.find_node(&tictactoe::State::default()).unwrap();
+    let children = root.get_child_list();
+    let visits: u32 = children
+      .iter()
+      .map(|c| c.get_data().statistics.visits())
+      .sum();
+    // The first iteration expands the root, and every later one visits one of
+    // its children.
+    assert_eq!(15, visits);
+    assert_eq!(4, children.len());
+    let full_expansion = 9 + 9 * 8;
+    assert!(graph.vertex_count() < full_expansion);
+  }
+}
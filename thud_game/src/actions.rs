@@ -3,10 +3,11 @@ use crate::end;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::cmp::{Eq, PartialEq};
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 use std::{error, fmt};
 
-#[derive(Clone, Copy, Hash)]
+#[derive(Clone, Copy)]
 pub enum Action {
   Move(Coordinate, Coordinate),
   Hurl(Coordinate, Coordinate),
@@ -88,11 +89,9 @@ impl PartialEq<Action> for Action {
         Action::Shove(a_start, a_end, a_capture_count, a_captures),
         Action::Shove(b_start, b_end, b_capture_count, b_captures),
       ) if a_start == b_start && a_end == b_end && a_capture_count == b_capture_count => {
-        let mut a_captures_sorted = a_captures.clone();
-        a_captures_sorted.sort();
-        let mut b_captures_sorted = b_captures.clone();
-        b_captures_sorted.sort();
-        a_captures_sorted == b_captures_sorted
+        let count = a_capture_count as usize;
+        sorted_captures(a_capture_count, a_captures)[..count]
+          == sorted_captures(b_capture_count, b_captures)[..count]
       }
       (Action::ProposeEnd, Action::ProposeEnd) => true,
       (Action::HandleEndProposal(d1), Action::HandleEndProposal(d2)) => d1 == d2,
@@ -103,6 +102,45 @@ impl PartialEq<Action> for Action {
 
 impl Eq for Action {}
 
+/// Returns the first `capture_count` entries of `captured`, sorted. Only these
+/// entries are meaningful, and their order is not, so this is what equality
+/// and hashing of shoves look at.
+fn sorted_captures(capture_count: u8, captured: [Coordinate; 7]) -> [Coordinate; 7] {
+  let mut sorted = captured;
+  sorted[..capture_count as usize].sort();
+  sorted
+}
+
+// Hashes the same parts of an action that `eq` compares, so that equal actions
+// hash equally.
+impl Hash for Action {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    match *self {
+      Action::Move(start, end) => {
+        0u8.hash(state);
+        start.hash(state);
+        end.hash(state);
+      }
+      Action::Hurl(start, end) => {
+        1u8.hash(state);
+        start.hash(state);
+        end.hash(state);
+      }
+      Action::Shove(start, end, capture_count, captured) => {
+        2u8.hash(state);
+        start.hash(state);
+        end.hash(state);
+        sorted_captures(capture_count, captured)[..capture_count as usize].hash(state);
+      }
+      Action::ProposeEnd => 3u8.hash(state),
+      Action::HandleEndProposal(d) => {
+        4u8.hash(state);
+        d.hash(state);
+      }
+    }
+  }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ActionParseError {
   /// Basic tokenization of move failed.
@@ -632,4 +670,29 @@ ______________d
     println!("{:?}", actions);
     assert!(!actions.contains(&move_literal!((9, 7), (10, 8))));
   }
+
+  #[test]
+  fn shoves_compare_and_hash_by_set_of_captures() {
+    use crate::coordinate::Coordinate;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash_of(a: &Action) -> u64 {
+      let mut hasher = DefaultHasher::new();
+      a.hash(&mut hasher);
+      hasher.finish()
+    }
+
+    let c = Coordinate::new_unchecked;
+    let (start, end, pad) = (c(7, 7), c(7, 9), c(7, 7));
+    let captured = [c(6, 9), c(8, 9), pad, pad, pad, pad, pad];
+    // Same captures in another order, with different unused entries.
+    let reordered = [c(8, 9), c(6, 9), c(0, 5), c(3, 4), pad, pad, pad];
+    let a = Action::Shove(start, end, 2, captured);
+    let b = Action::Shove(start, end, 2, reordered);
+    let d = Action::Shove(start, end, 1, captured);
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+    assert_ne!(a, d);
+  }
 }
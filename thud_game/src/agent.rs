@@ -49,6 +49,13 @@ impl From<io::Error> for ReaderAgentError {
 /// Implemented for agents that can play Thud.
 pub trait Agent: Send {
   fn propose_action(&mut self, state: &crate::state::State) -> Result;
+
+  /// Returns measurements of the search behind the most recent call to
+  /// `propose_action`, if this agent searches.
+  #[cfg(feature = "ai-mcts")]
+  fn search_report(&self) -> Option<&mcts::report::SearchReport> {
+    None
+  }
 }
 
 /// [Agent](trait.Agent.html) that reads moves from stdin.
@@ -1,2 +1,4 @@
 #[cfg(feature = "ai-mcts")]
 pub mod mcts;
+#[cfg(feature = "ai-mcts")]
+pub mod playout;
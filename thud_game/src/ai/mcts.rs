@@ -1,13 +1,45 @@
+use super::playout::MaterialEvaluator;
 use crate::actions::Action;
+use crate::coordinate::Coordinate;
+use crate::end;
 use crate::Role;
+use log::info;
+use mcts::budget::SearchBudget;
+use mcts::evict::GraphLimit;
+use mcts::graph::copy_payoff;
+use mcts::game::Statistics;
+use mcts::observe::{Observation, SearchMonitor, SearchObserver, SearchProgress};
+use mcts::persist::Persist;
+use mcts::report::{SearchRecorder, SearchReport};
 use mcts::{statistics, SearchSettings};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
 use search_graph;
-use std::{cmp, mem};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::{cmp, error, io, mem, thread};
 
+/// Thud scored by the points of each side, so that an agent searching it plays
+/// to maximise its margin of points over the other side.
 #[derive(Clone, Debug)]
 pub struct Game {}
 
+/// Thud scored by which side won, with the margin of points only breaking ties
+/// between actions that win equally often. An agent searching it plays to win
+/// rather than to maximise its points.
+#[derive(Clone, Debug)]
+pub struct OutcomeGame {}
+
+/// A formulation of Thud for MCTS, which determines what the [MCTS
+/// agent](struct.GameAgent.html) optimises for.
+pub trait ThudGame:
+  mcts::game::Game<Action = Action, PlayerId = Role, State = crate::state::State> + Send + 'static
+{
+  /// Returns the payoff of a game that ends with `dwarf` points for the dwarfs
+  /// and `troll` points for the trolls.
+  fn payoff_of_points(dwarf: u32, troll: u32) -> Self::Payoff;
+}
+
 impl statistics::two_player::PlayerMapping for Role {
   fn player_one() -> Self {
     Role::Dwarf
@@ -26,38 +58,239 @@ impl statistics::two_player::PlayerMapping for Role {
 impl mcts::game::State for crate::state::State {
   type Action = Action;
   type PlayerId = Role;
+  type Undo = crate::state::Undo;
 
   fn active_player(&self) -> &Role {
     &self.active_role()
   }
 
-  fn actions<'s>(&'s self) -> Box<dyn Iterator<Item = Action> + 's> {
-    Box::new(self.actions())
+  fn fill_actions(&self, actions: &mut Vec<Action>) {
+    self.fill_actions(actions);
   }
 
-  fn do_action(&mut self, action: &Action) {
-    self.do_action(action);
+  fn do_action(&mut self, action: &Action) -> crate::state::Undo {
+    self.do_action(action)
+  }
+
+  fn undo_action(&mut self, action: &Action, undo: crate::state::Undo) {
+    self.undo_action(action, undo);
   }
 }
 
+/// The points of either side at the start of a game: 32 dwarfs, or 8 trolls
+/// at 4 points each.
+const MAX_POINTS: f32 = 32.0;
+
 impl mcts::game::Game for Game {
   type Action = Action;
   type PlayerId = Role;
   type Payoff = statistics::two_player::ScoredPayoff;
   type State = crate::state::State;
-  type Statistics = statistics::two_player::ScoredStatistics<Role>;
+  type Statistics = statistics::two_player::VarianceStatistics<Role>;
 
   fn payoff_of(state: &Self::State) -> Option<Self::Payoff> {
     if state.terminated() {
-      Some(statistics::two_player::ScoredPayoff {
-        visits: 1,
-        score_one: state.score(Role::Dwarf) as u32,
-        score_two: state.score(Role::Troll) as u32,
-      })
+      Some(Game::payoff_of_points(
+        state.score(Role::Dwarf) as u32,
+        state.score(Role::Troll) as u32,
+      ))
+    } else {
+      None
+    }
+  }
+
+  /// A payoff is optimal for the side to move if every piece of the opposing
+  /// side has been captured and none of its own pieces have.
+  fn is_optimal_payoff(state: &Self::State, payoff: &Self::Payoff) -> bool {
+    match *state.active_role() {
+      Role::Dwarf => payoff.score_two == 0 && payoff.score_one == state.score(Role::Dwarf) as u32,
+      Role::Troll => payoff.score_one == 0 && payoff.score_two == state.score(Role::Troll) as u32,
+    }
+  }
+
+  /// Normalises the margin of dwarf points over troll points.
+  fn normalize_payoff<R: Rng>(
+    payoff: &Self::Payoff,
+    normalization: &mcts::normalize::Normalization,
+    rng: &mut R,
+  ) -> Self::Payoff {
+    payoff.normalized(normalization, rng)
+  }
+
+  /// A repeated position scores no points for either side.
+  fn draw_payoff() -> Option<Self::Payoff> {
+    Some(Game::payoff_of_points(0, 0))
+  }
+
+  /// Declining a proposal to end the game hands play back to the side that
+  /// proposed it, in the position from which it did so.
+  fn repeats_position(_state: &Self::State, action: &Action) -> bool {
+    *action == Action::HandleEndProposal(end::Decision::Decline)
+  }
+
+  /// The worst net score is losing by every point on the board. Normalised
+  /// payoffs score a loss as -1.
+  fn loss_score(normalization: Option<&mcts::normalize::Normalization>) -> f32 {
+    match normalization {
+      Some(_) => -1.0,
+      None => -MAX_POINTS,
+    }
+  }
+
+  /// The best net score is winning by every point on the board. Normalised
+  /// payoffs score a win as 1.
+  fn win_score(normalization: Option<&mcts::normalize::Normalization>) -> f32 {
+    match normalization {
+      Some(_) => 1.0,
+      None => MAX_POINTS,
+    }
+  }
+
+  /// Weights each action by the value of the pieces it captures, so that
+  /// captures are searched before quiet moves.
+  fn action_priors(_state: &Self::State, actions: &[Action]) -> Vec<f32> {
+    capture_priors(actions)
+  }
+}
+
+impl ThudGame for Game {
+  fn payoff_of_points(dwarf: u32, troll: u32) -> Self::Payoff {
+    statistics::two_player::ScoredPayoff {
+      visits: 1,
+      score_one: dwarf,
+      score_two: troll,
+    }
+  }
+}
+
+impl mcts::game::Game for OutcomeGame {
+  type Action = Action;
+  type PlayerId = Role;
+  type Payoff = statistics::outcome::OutcomePayoff;
+  type State = crate::state::State;
+  type Statistics = statistics::outcome::OutcomeStatistics<Role>;
+
+  fn payoff_of(state: &Self::State) -> Option<Self::Payoff> {
+    if state.terminated() {
+      Some(OutcomeGame::payoff_of_points(
+        state.score(Role::Dwarf) as u32,
+        state.score(Role::Troll) as u32,
+      ))
     } else {
       None
     }
   }
+
+  /// Any win is optimal for the side to move.
+  fn is_optimal_payoff(state: &Self::State, payoff: &Self::Payoff) -> bool {
+    match *state.active_role() {
+      Role::Dwarf => payoff.wins_one > payoff.wins_two,
+      Role::Troll => payoff.wins_two > payoff.wins_one,
+    }
+  }
+
+  /// A repeated position is a draw with no margin.
+  fn draw_payoff() -> Option<Self::Payoff> {
+    Some(OutcomeGame::payoff_of_points(0, 0))
+  }
+
+  /// Declining a proposal to end the game hands play back to the side that
+  /// proposed it, in the position from which it did so.
+  fn repeats_position(_state: &Self::State, action: &Action) -> bool {
+    *action == Action::HandleEndProposal(end::Decision::Decline)
+  }
+
+  /// Weights each action by the value of the pieces it captures, so that
+  /// captures are searched before quiet moves.
+  fn action_priors(_state: &Self::State, actions: &[Action]) -> Vec<f32> {
+    capture_priors(actions)
+  }
+}
+
+impl ThudGame for OutcomeGame {
+  fn payoff_of_points(dwarf: u32, troll: u32) -> Self::Payoff {
+    let outcome = match dwarf.cmp(&troll) {
+      cmp::Ordering::Greater => {
+        statistics::outcome::Outcome::Win(statistics::two_player::Player::One)
+      }
+      cmp::Ordering::Less => statistics::outcome::Outcome::Win(statistics::two_player::Player::Two),
+      cmp::Ordering::Equal => statistics::outcome::Outcome::Draw,
+    };
+    statistics::outcome::OutcomePayoff::with_margin(outcome, dwarf as i64 - troll as i64)
+  }
+}
+
+/// Returns a prior weight for each of `actions` given by the value of the
+/// pieces that it captures.
+fn capture_priors(actions: &[Action]) -> Vec<f32> {
+  actions
+    .iter()
+    .map(|action| match *action {
+      // A hurl captures a troll, which is worth 4 points.
+      Action::Hurl(_, _) => 1.0 + CAPTURE_PRIOR_WEIGHT * 4.0,
+      // A shove captures one dwarf, worth 1 point, for each adjacent dwarf.
+      Action::Shove(_, _, count, _) => 1.0 + CAPTURE_PRIOR_WEIGHT * count as f32,
+      Action::Move(_, _) | Action::ProposeEnd | Action::HandleEndProposal(_) => 1.0,
+    })
+    .collect()
+}
+
+/// Prior weight, relative to a move that captures nothing, that is added for
+/// each point of value captured by an action.
+const CAPTURE_PRIOR_WEIGHT: f32 = 2.0;
+
+/// Actions are saved in [search graphs](../../../mcts/persist/index.html) as a
+/// letter for the kind of action followed by the row and column of each
+/// coordinate. A shove also gives its number of captures and all seven of its
+/// capture coordinates, so that every shove has the same number of fields.
+/// Coordinates past the number of captures are ignored when actions are
+/// compared.
+impl Persist for Action {
+  fn persist(&self) -> String {
+    let coordinates = |cs: &[Coordinate]| -> String {
+      cs.iter()
+        .map(|c| format!(" {} {}", c.row(), c.col()))
+        .collect()
+    };
+    match *self {
+      Action::Move(start, end) => format!("M{}", coordinates(&[start, end])),
+      Action::Hurl(start, end) => format!("H{}", coordinates(&[start, end])),
+      Action::Shove(start, end, count, captured) => format!(
+        "S{} {}{}",
+        coordinates(&[start, end]),
+        count,
+        coordinates(&captured)
+      ),
+      Action::ProposeEnd => "E".into(),
+      Action::HandleEndProposal(end::Decision::Accept) => "A".into(),
+      Action::HandleEndProposal(end::Decision::Decline) => "D".into(),
+    }
+  }
+
+  fn restore(text: &str) -> Option<Self> {
+    let mut fields = text.split(' ');
+    let kind = fields.next()?;
+    let numbers = fields
+      .map(|f| f.parse::<u8>().ok())
+      .collect::<Option<Vec<u8>>>()?;
+    let coordinate = |i: usize| Coordinate::new(*numbers.get(i)?, *numbers.get(i + 1)?);
+    let action = match (kind, numbers.len()) {
+      ("M", 4) => Action::Move(coordinate(0)?, coordinate(2)?),
+      ("H", 4) => Action::Hurl(coordinate(0)?, coordinate(2)?),
+      ("S", 19) if (1..=7).contains(&numbers[4]) => {
+        let mut captured = [coordinate(5)?; 7];
+        for (i, c) in captured.iter_mut().enumerate() {
+          *c = coordinate(5 + 2 * i)?;
+        }
+        Action::Shove(coordinate(0)?, coordinate(2)?, numbers[4], captured)
+      }
+      ("E", 0) => Action::ProposeEnd,
+      ("A", 0) => Action::HandleEndProposal(end::Decision::Accept),
+      ("D", 0) => Action::HandleEndProposal(end::Decision::Decline),
+      _ => return None,
+    };
+    Some(action)
+  }
 }
 
 /// Controls how a game action is selected by the [MCTS
@@ -85,45 +318,838 @@ pub enum GraphCompact {
   Retain,
 }
 
-type SearchGraph =
-  search_graph::Graph<crate::state::State, mcts::graph::VertexData, mcts::graph::EdgeData<Game>>;
+/// Controls how the [MCTS agent](struct.Agent.html) organizes its search.
+#[derive(Debug, Clone, Copy)]
+pub enum SearchMode {
+  /// Run a single search on one search graph. If
+  /// `SearchSettings::search_thread_count` is greater than 1, that many
+  /// threads search the graph concurrently.
+  SharedTree,
+  /// Run the given number of independent searches from the same game state,
+  /// each on its own thread and with its own search graph and random number
+  /// stream. The statistics of each search's root actions are merged before an
+  /// action is selected.
+  ///
+  /// Each search is given the agent's full search budget.
+  RootEnsemble(u32),
+}
+
+/// Controls how the [MCTS agent](struct.Agent.html) selects which child to
+/// follow during rollout.
+#[derive(Debug, Clone, Copy)]
+pub enum RolloutPolicy {
+  /// Select the child with the best UCB1 score.
+  Ucb,
+  /// Select the child with the best PUCT score, which weights exploration by
+  /// the heuristic prior probability of each action (see
+  /// `Game::action_priors`).
+  Puct,
+  /// Select the child with the best UCB1 score, computed from a blend of its
+  /// ordinary and AMAF statistics according to
+  /// `SearchSettings::rave_schedule`.
+  Rave,
+  /// Select the child with the best UCB1-Tuned score, which narrows the
+  /// exploration term of children whose payoffs vary little.
+  Ucb1Tuned,
+  /// Select the child with the best UCB-V score, whose exploration term grows
+  /// with the variance of each child's payoffs.
+  UcbV,
+}
+
+/// Controls how the [MCTS agent](struct.Agent.html) chooses actions during
+/// simulated playouts.
+#[derive(Debug, Clone, Copy)]
+pub enum SimulationPolicy {
+  /// Choose each legal action with equal probability.
+  Uniform,
+  /// Usually choose the action that captures the most, but choose a random
+  /// action with probability `PlayoutSettings::epsilon` (see
+  /// [EpsilonGreedyPlayout](../playout/type.EpsilonGreedyPlayout.html)).
+  EpsilonGreedy,
+  /// Choose actions with probabilities weighted towards captures, according to
+  /// `PlayoutSettings::temperature` (see
+  /// [SoftmaxPlayout](../playout/type.SoftmaxPlayout.html)).
+  Softmax,
+}
+
+/// The policies used by the [MCTS agent](struct.Agent.html) during each
+/// iteration of search.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchPolicy {
+  /// Selects the children to follow during rollout.
+  pub rollout: RolloutPolicy,
+  /// Chooses actions during simulated playouts.
+  pub simulation: SimulationPolicy,
+}
+
+/// A [search observer](../../../mcts/observe/trait.SearchObserver.html) for
+/// the [MCTS agent](struct.GameAgent.html) that logs the best action so far
+/// every `interval` iterations of search, and never stops search.
+#[derive(Debug, Clone, Copy)]
+pub struct LoggingObserver {
+  interval: u32,
+}
+
+impl LoggingObserver {
+  /// Creates an observer that logs every `interval` iterations. Panics if
+  /// `interval` is 0.
+  pub fn new(interval: u32) -> Self {
+    assert!(interval > 0, "observer interval must be positive");
+    LoggingObserver { interval }
+  }
+}
+
+impl<G: ThudGame> SearchObserver<G> for LoggingObserver {
+  fn interval(&self) -> u32 {
+    self.interval
+  }
+
+  fn observe(&mut self, progress: &SearchProgress<G>) -> Observation {
+    match progress.best {
+      Some(best) => info!(
+        "search progress: {} iterations in {:.3}s; best action {:?} with {} visits",
+        progress.iterations,
+        progress.elapsed.as_secs_f64(),
+        best.action,
+        best.visits
+      ),
+      None => info!(
+        "search progress: {} iterations in {:.3}s",
+        progress.iterations,
+        progress.elapsed.as_secs_f64()
+      ),
+    }
+    Observation::Continue
+  }
+}
+
+type SearchGraph<G> =
+  search_graph::Graph<crate::state::State, mcts::graph::VertexData<G>, mcts::graph::EdgeData<G>>;
+
+type SearchView<'a, 'id, G> = search_graph::view::View<
+  'a,
+  'id,
+  crate::state::State,
+  mcts::graph::VertexData<G>,
+  mcts::graph::EdgeData<G>,
+>;
+
+/// The statistics of an action at the root of a search, and its proven payoff
+/// if the solver has found one.
+type RootActionStatistics<G> = (
+  Action,
+  <G as mcts::game::Game>::Statistics,
+  Option<<G as mcts::game::Game>::Payoff>,
+);
+
+type RootStatistics<G> = Vec<RootActionStatistics<G>>;
 
-pub struct Agent<R: Rng> {
+/// An agent that plays Thud with MCTS, searching the formulation of the game
+/// given by `G`.
+pub struct GameAgent<R: Rng, G: ThudGame> {
   settings: SearchSettings,
-  iterations: u32,
+  budget: SearchBudget,
   rng: R,
   action_select: ActionSelect,
   graph_compact: GraphCompact,
-  graph: SearchGraph,
+  graph_limit: Option<GraphLimit>,
+  search_mode: SearchMode,
+  policy: SearchPolicy,
+  graphs: Vec<SearchGraph<G>>,
+  root_statistics: Vec<mcts::ActionStatistics<G>>,
+  ensemble_statistics: Vec<Vec<mcts::ActionStatistics<G>>>,
+  forced_result: Option<G::Payoff>,
+  search_report: Option<SearchReport>,
+  observer: Option<Box<dyn SearchObserver<G>>>,
 }
 
-impl<R: Rng> Agent<R> {
+/// An MCTS agent that plays to maximise its margin of points.
+pub type Agent<R> = GameAgent<R, Game>;
+
+/// An MCTS agent that plays to win, whatever the margin of points.
+pub type OutcomeAgent<R> = GameAgent<R, OutcomeGame>;
+
+impl<R: Rng, G: ThudGame> GameAgent<R, G>
+where
+  G::Statistics: Send + Sync,
+{
+  /// Creates a new agent.
+  ///
+  /// All randomness in search, including that of simulation threads, is drawn
+  /// from `rng`. Agents that are created with identically seeded `rng`s and the
+  /// same settings propose the same actions, provided that `budget` does not
+  /// limit search time and `SearchSettings::search_thread_count` is 1.
+  ///
+  /// Search graphs are not bounded in size unless a limit is set with
+  /// [with_graph_limit](#method.with_graph_limit).
+  ///
+  /// Panics if `budget` is unbounded, if `search_mode` is
+  /// `SearchMode::RootEnsemble(0)`, or if `graph_compact` is
+  /// `GraphCompact::Retain` and `budget` limits the node count. The node count
+  /// limit applies to the whole search graph, so a retained graph that reached
+  /// it would leave every later search with a single iteration.
   pub fn new(
     settings: SearchSettings,
-    iterations: u32,
+    budget: SearchBudget,
     rng: R,
     action_select: ActionSelect,
     graph_compact: GraphCompact,
+    search_mode: SearchMode,
+    policy: SearchPolicy,
   ) -> Self {
-    Agent {
+    assert!(budget.is_bounded(), "search budget must be bounded");
+    if let GraphCompact::Retain = graph_compact {
+      assert!(
+        budget.node_count.is_none(),
+        "search budget cannot limit node count when the search graph is retained"
+      );
+    }
+    let graph_count = match search_mode {
+      SearchMode::SharedTree => 1,
+      SearchMode::RootEnsemble(n) => {
+        assert!(n > 0, "root ensemble must have at least one search");
+        n
+      }
+    };
+    GameAgent {
       settings,
-      iterations,
+      budget,
       rng,
       action_select,
       graph_compact,
-      graph: SearchGraph::new(),
+      graph_limit: None,
+      search_mode,
+      policy,
+      graphs: (0..graph_count).map(|_| SearchGraph::new()).collect(),
+      root_statistics: Vec::new(),
+      ensemble_statistics: Vec::new(),
+      forced_result: None,
+      search_report: None,
+      observer: None,
+    }
+  }
+
+  /// Bounds the size of search graphs by `graph_limit`, or removes the bound if
+  /// it is `None`.
+  ///
+  /// With a limit, search pauses whenever a search graph reaches it, evicts
+  /// the least-visited vertices of the graph down to half the limit (see
+  /// [mcts::evict](../../../mcts/evict/index.html)), and then resumes until
+  /// the search budget is exhausted. If the budget limits neither the number
+  /// of iterations nor search time, search instead stops at the limit.
+  pub fn with_graph_limit(mut self, graph_limit: Option<GraphLimit>) -> Self {
+    self.graph_limit = graph_limit;
+    self
+  }
+
+  /// Returns the options that each search by this agent is run with.
+  fn search_options(&self) -> SearchOptions {
+    SearchOptions {
+      settings: self.settings,
+      budget: self.budget,
+      graph_limit: self.graph_limit,
+      policy: self.policy,
+    }
+  }
+
+  /// Returns statistics for each action that was considered from the game
+  /// state passed to the most recent call to `propose_action`. When searching
+  /// with `SearchMode::RootEnsemble`, these are the merged statistics of all
+  /// searches.
+  pub fn root_statistics(&self) -> &[mcts::ActionStatistics<G>] {
+    &self.root_statistics
+  }
+
+  /// Returns the statistics of each individual search run by the most recent
+  /// call to `propose_action`, for comparison with each other and with
+  /// `root_statistics`. This is empty unless searching with
+  /// `SearchMode::RootEnsemble`.
+  pub fn ensemble_statistics(&self) -> &[Vec<mcts::ActionStatistics<G>>] {
+    &self.ensemble_statistics
+  }
+
+  /// Returns the proven payoff of the game state passed to the most recent
+  /// call to `propose_action`, if `SearchSettings::solver` is set and search
+  /// solved it. The proposed action is then one that forces this result.
+  pub fn forced_result(&self) -> Option<&G::Payoff> {
+    self.forced_result.as_ref()
+  }
+
+  /// Returns measurements of the search run by the most recent call to
+  /// `propose_action`, including one that failed. When searching with
+  /// `SearchMode::RootEnsemble`, these cover all searches, and the graph sizes
+  /// are summed over their graphs.
+  pub fn search_report(&self) -> Option<&SearchReport> {
+    self.search_report.as_ref()
+  }
+
+  /// Sets an observer to be shown the progress of each search run by
+  /// `propose_action`, replacing any earlier one. If the observer stops
+  /// search, the action is proposed from the statistics gathered so far.
+  ///
+  /// When searching with `SearchMode::RootEnsemble`, the observer is shown
+  /// the progress of the first search only, but stopping it stops all
+  /// searches.
+  pub fn set_observer(&mut self, observer: Option<Box<dyn SearchObserver<G>>>) {
+    self.observer = observer;
+  }
+
+  /// Writes the part of the search graph that can be reached from `state` to
+  /// `out`, in the format of [mcts::persist](../../../mcts/persist/index.html).
+  /// When searching with `SearchMode::RootEnsemble`, this is the graph of the
+  /// first search.
+  pub fn save_graph<W: io::Write>(&self, state: &crate::state::State, out: W) -> io::Result<()>
+  where
+    G::Payoff: Persist,
+    G::Statistics: Persist,
+  {
+    mcts::persist::save(&self.graphs[0], state, out)
+  }
+
+  /// Replaces the search graph with one that was written by `save_graph` from
+  /// `state`, so that search continues from the saved statistics. With
+  /// `GraphCompact::Clear`, the loaded graph is discarded before the next
+  /// search, so it is only useful with `GraphCompact::Prune` or
+  /// `GraphCompact::Retain`.
+  pub fn load_graph<B: io::BufRead>(
+    &mut self,
+    state: &crate::state::State,
+    input: B,
+  ) -> io::Result<()>
+  where
+    G::Payoff: Persist,
+    G::Statistics: Persist,
+  {
+    self.graphs[0] = mcts::persist::load::<G, _>(state, input)?;
+    Ok(())
+  }
+
+  fn propose_shared_tree_action(&mut self, state: &crate::state::State) -> crate::agent::Result {
+    // Borrow/copy stuff out of self because the closure passed to of_graph
+    // can't borrow self.
+    let options = self.search_options();
+    let (rng, graph, settings, action_select) = (
+      &mut self.rng,
+      &mut self.graphs[0],
+      self.settings,
+      self.action_select,
+    );
+    let recorder = SearchRecorder::start();
+    let result = search_bounded(
+      graph,
+      state,
+      &options,
+      &recorder,
+      self
+        .observer
+        .as_mut()
+        .map(|o| &mut **o as &mut dyn SearchObserver<G>),
+      &mut *rng,
+    );
+    let report = recorder.report(graph.vertex_count(), graph.edge_count());
+    info!("search report: {}", report);
+    self.search_report = Some(report);
+    result?;
+    let (action, root_statistics, forced_result) =
+      search_graph::view::of_graph(graph, |view| -> Result<_, Box<dyn error::Error + Send>> {
+        let root = view.find_node(state).unwrap();
+        let forced_result = view.node_data(root).solution();
+        let child_edge = match action_select {
+          ActionSelect::Ucb => {
+            match mcts::ucb::find_best_child(&view, root, settings.explore_bias, &mut *rng) {
+              Ok(child) => child,
+              Err(e) => return Err(Box::new(e)),
+            }
+          }
+          ActionSelect::VisitCount => find_most_visited_child(&view, root, &mut *rng),
+        };
+        // Because search graph de-duplication maps each set of equivalent game
+        // states to a single "canonical" game state, the state in the search graph
+        // that corresponds to `state` may not actually be the game state at `root`. As
+        // a result, actions on the root game state need to be mapped back into the
+        // set of actions on `state`.
+        let transposed_to_state = view.node_state(view.edge_target(child_edge));
+        let mut actual_to_state = state.clone();
+        for action in state.actions() {
+          let undo = actual_to_state.do_action(&action);
+          if actual_to_state == *transposed_to_state {
+            return Ok((
+              action,
+              collect_root_statistics(&view, root, state),
+              forced_result,
+            ));
+          }
+          actual_to_state.undo_action(&action, undo);
+        }
+        unreachable!()
+      })?;
+    self.root_statistics = action_statistics(&root_statistics, state, &self.settings);
+    self.ensemble_statistics.clear();
+    self.forced_result = forced_result;
+    if self.forced_result.is_some() {
+      if let Some(forced) = find_forced_action(&self.root_statistics, state) {
+        return Ok(forced.action);
+      }
+    }
+    Ok(action)
+  }
+
+  fn propose_ensemble_action(&mut self, state: &crate::state::State) -> crate::agent::Result {
+    let options = self.search_options();
+    let graphs = mem::take(&mut self.graphs);
+    let recorder = Arc::new(SearchRecorder::start());
+    // Only the first search is observed.
+    let mut observer = self.observer.take();
+    let searches: Vec<thread::JoinHandle<_>> = graphs
+      .into_iter()
+      .map(|mut graph| {
+        let mut seed = [0u8; 32];
+        self.rng.fill_bytes(&mut seed);
+        let state = state.clone();
+        let recorder = Arc::clone(&recorder);
+        let mut observer = observer.take();
+        thread::spawn(move || {
+          let mut rng = Pcg64::from_seed(seed);
+          let result = search_bounded(
+            &mut graph,
+            &state,
+            &options,
+            &recorder,
+            observer
+              .as_mut()
+              .map(|o| &mut **o as &mut dyn SearchObserver<G>),
+            &mut rng,
+          )
+          .map(|()| {
+            search_graph::view::of_graph(&mut graph, |view| {
+              let root = view.find_node(&state).unwrap();
+              (
+                collect_root_statistics(&view, root, &state),
+                view.node_data(root).solution(),
+              )
+            })
+          });
+          (graph, observer, result)
+        })
+      })
+      .collect();
+    let mut results = Vec::with_capacity(searches.len());
+    for search in searches.into_iter() {
+      let (graph, observer, result) = search.join().expect("ensemble search thread panicked");
+      self.graphs.push(graph);
+      self.observer = self.observer.take().or(observer);
+      results.push(result);
+    }
+    let report = recorder.report(
+      self.graphs.iter().map(|g| g.vertex_count()).sum(),
+      self.graphs.iter().map(|g| g.edge_count()).sum(),
+    );
+    info!("search report: {}", report);
+    self.search_report = Some(report);
+    let mut members = Vec::with_capacity(results.len());
+    let mut forced_result = None;
+    for result in results.into_iter() {
+      let (member, solution) = result?;
+      members.push(member);
+      forced_result = forced_result.or(solution);
+    }
+
+    let merged = merge_root_statistics::<G>(&members);
+    self.root_statistics = action_statistics(&merged, state, &self.settings);
+    self.ensemble_statistics = members
+      .iter()
+      .map(|m| action_statistics(m, state, &self.settings))
+      .collect();
+    self.forced_result = forced_result;
+    if self.forced_result.is_some() {
+      if let Some(forced) = find_forced_action(&self.root_statistics, state) {
+        return Ok(forced.action);
+      }
+    }
+    let best = match self.action_select {
+      ActionSelect::VisitCount => {
+        find_best_action(&self.root_statistics, |s| s.visits as f64, &mut self.rng)
+      }
+      ActionSelect::Ucb => {
+        for s in self.root_statistics.iter() {
+          if let Err(ref e) = s.ucb {
+            return Err(Box::new(e.clone()));
+          }
+        }
+        find_best_action(
+          &self.root_statistics,
+          |s| match s.ucb {
+            Ok(mcts::UcbValue::Value(v)) => v,
+            _ => f64::INFINITY,
+          },
+          &mut self.rng,
+        )
+      }
+    };
+    Ok(best.action)
+  }
+}
+
+/// The options of a [GameAgent](struct.GameAgent.html) that each of its
+/// searches is run with. They are copied out of the agent so that searches can
+/// run on other threads.
+#[derive(Clone, Copy)]
+struct SearchOptions {
+  settings: SearchSettings,
+  budget: SearchBudget,
+  graph_limit: Option<GraphLimit>,
+  policy: SearchPolicy,
+}
+
+/// Runs MCTS from `state` on `graph` until the budget of `options` is
+/// exhausted, evicting the least-visited parts of `graph` whenever it reaches
+/// the graph limit of `options` (see `GameAgent::with_graph_limit`). Progress
+/// is recorded with `recorder` and shown to `observer`, if it is given, which
+/// may cancel search.
+fn search_bounded<G, R>(
+  graph: &mut SearchGraph<G>,
+  state: &crate::state::State,
+  options: &SearchOptions,
+  recorder: &SearchRecorder,
+  observer: Option<&mut dyn SearchObserver<G>>,
+  rng: &mut R,
+) -> Result<(), Box<dyn error::Error + Send>>
+where
+  G: ThudGame,
+  G::Statistics: Send + Sync,
+  R: Rng,
+{
+  let (settings, budget, graph_limit, policy) = (
+    &options.settings,
+    &options.budget,
+    options.graph_limit,
+    options.policy,
+  );
+  let vertex_limit = graph_limit.map(|limit| limit.vertex_count::<G>());
+  let tracker = match vertex_limit {
+    Some(limit) => SearchBudget {
+      node_count: Some(budget.node_count.map_or(limit, |n| cmp::min(n, limit))),
+      ..*budget
+    },
+    None => *budget,
+  }
+  .start();
+  let observer = observer.map(|o| o as &mut dyn SearchObserver<G>);
+  let monitor = SearchMonitor::new(&tracker, recorder, observer);
+  let evicting = budget.iterations.is_some() || budget.duration.is_some();
+  loop {
+    let started = tracker.iterations_started();
+    search_graph::view::of_graph(graph, |view| {
+      search(view, state, settings, &monitor, policy, &mut *rng).map(|_| ())
+    })?;
+    if recorder.cancelled() {
+      return Ok(());
+    }
+    match vertex_limit {
+      Some(limit) if evicting && graph.vertex_count() >= limit => {
+        *graph = mcts::evict::evict(graph, state, limit / 2);
+      }
+      _ => return Ok(()),
+    }
+    if tracker.iterations_started() == started {
+      return Ok(());
+    }
+  }
+}
+
+/// Runs MCTS from `state` on `view` until `monitor` reports that its budget is
+/// exhausted or search is cancelled, reporting progress to `monitor` and using
+/// `policy` to select the children to follow during rollout and to choose
+/// actions during playouts. Playouts that are cut off by
+/// `SearchSettings::max_playout_depth` are scored by material.
+fn search<'a, 'id, G, R>(
+  view: SearchView<'a, 'id, G>,
+  state: &crate::state::State,
+  settings: &SearchSettings,
+  monitor: &SearchMonitor<G>,
+  policy: SearchPolicy,
+  rng: &mut R,
+) -> Result<SearchView<'a, 'id, G>, Box<dyn error::Error + Send>>
+where
+  G: ThudGame,
+  G::Statistics: Send + Sync,
+  R: Rng,
+{
+  let rollout_policy = policy.rollout;
+  match policy.simulation {
+    SimulationPolicy::Uniform => search_with_simulator::<
+      G,
+      _,
+      mcts::simulation::PlayoutSimulator<mcts::playout::Uniform, MaterialEvaluator>,
+    >(view, state, settings, monitor, rollout_policy, rng),
+    SimulationPolicy::EpsilonGreedy => search_with_simulator::<
+      G,
+      _,
+      mcts::simulation::PlayoutSimulator<super::playout::EpsilonGreedyPlayout, MaterialEvaluator>,
+    >(view, state, settings, monitor, rollout_policy, rng),
+    SimulationPolicy::Softmax => search_with_simulator::<
+      G,
+      _,
+      mcts::simulation::PlayoutSimulator<super::playout::SoftmaxPlayout, MaterialEvaluator>,
+    >(view, state, settings, monitor, rollout_policy, rng),
+  }
+}
+
+fn search_with_simulator<'a, 'id, G, R, S>(
+  view: SearchView<'a, 'id, G>,
+  state: &crate::state::State,
+  settings: &SearchSettings,
+  monitor: &SearchMonitor<G>,
+  rollout_policy: RolloutPolicy,
+  rng: &mut R,
+) -> Result<SearchView<'a, 'id, G>, Box<dyn error::Error + Send>>
+where
+  G: ThudGame,
+  G::Statistics: Send + Sync,
+  R: Rng,
+  S: mcts::simulation::Simulator<G> + Sync,
+  S::Error: Send + 'static,
+{
+  match rollout_policy {
+    RolloutPolicy::Ucb => {
+      search_with::<G, _, mcts::ucb::Rollout, S, mcts::ucb::BestParentBackprop>(
+        view, state, settings, monitor, rng,
+      )
+    }
+    RolloutPolicy::Puct => {
+      search_with::<G, _, mcts::puct::Rollout, S, mcts::puct::BestParentBackprop>(
+        view, state, settings, monitor, rng,
+      )
+    }
+    RolloutPolicy::Rave => {
+      search_with::<G, _, mcts::rave::Rollout, S, mcts::rave::BestParentBackprop>(
+        view, state, settings, monitor, rng,
+      )
+    }
+    RolloutPolicy::Ucb1Tuned => {
+      search_with::<G, _, mcts::variance::TunedRollout, S, mcts::variance::TunedBackprop>(
+        view, state, settings, monitor, rng,
+      )
+    }
+    RolloutPolicy::UcbV => {
+      search_with::<G, _, mcts::variance::UcbVRollout, S, mcts::variance::UcbVBackprop>(
+        view, state, settings, monitor, rng,
+      )
+    }
+  }
+}
+
+fn search_with<'a, 'id, G, R, RS, S, B>(
+  view: SearchView<'a, 'id, G>,
+  state: &crate::state::State,
+  settings: &SearchSettings,
+  monitor: &SearchMonitor<G>,
+  rng: &mut R,
+) -> Result<SearchView<'a, 'id, G>, Box<dyn error::Error + Send>>
+where
+  G: ThudGame,
+  G::Statistics: Send + Sync,
+  R: Rng,
+  RS: mcts::rollout::RolloutSelector,
+  RS::Error: Send + 'static,
+  S: mcts::simulation::Simulator<G> + Sync,
+  S::Error: Send + 'static,
+  B: mcts::backprop::BackpropSelector<'id>,
+{
+  if settings.search_thread_count > 1 {
+    let mut view = view;
+    let result =
+      mcts::parallel::search_monitored::<G, _, RS, S, B>(&mut view, state, settings, monitor, rng);
+    if let Err(e) = result {
+      return Err(Box::new(e));
+    }
+    Ok(view)
+  } else {
+    // The simulator's threads are started once and reused by every iteration.
+    let simulator = S::from(settings);
+    let mut rollout = mcts::RolloutPhase::initialize(rng, *settings, state.clone(), view);
+    while monitor.start_iteration(rollout.graph().node_count()) {
+      if rollout.graph().node_data(rollout.root_node()).solved() {
+        // Further iterations would stop at the root.
+        break;
+      }
+      let node_count = rollout.graph().node_count();
+      let scoring = match rollout.rollout::<RS>() {
+        Ok(s) => s,
+        Err(e) => {
+          monitor.recorder().record_rollout_error(&e);
+          return Err(Box::new(e));
+        }
+      };
+      let rollout_depth = scoring.rollout_depth();
+      let backprop = match scoring.score_with(&simulator) {
+        Ok(b) => b,
+        Err(e) => {
+          monitor.recorder().record_simulation_error(&e);
+          return Err(Box::new(e));
+        }
+      };
+      let simulations = backprop.simulation_count();
+      rollout = backprop.backprop::<B>().expand();
+      monitor.complete_iteration(
+        rollout.graph(),
+        rollout.root_node(),
+        settings.explore_bias,
+        rollout_depth,
+        simulations,
+        rollout.graph().node_count() - node_count,
+      );
+    }
+    let (_, view) = rollout.recover_components();
+    Ok(view)
+  }
+}
+
+/// Returns the statistics of each action on `state`, gathered from the child
+/// edges of `root`.
+///
+/// Because of search graph de-duplication, the game state at `root` may only
+/// be equivalent to `state`, so its actions are mapped back onto the actions of
+/// `state`. This also lets the statistics of searches on different graphs be
+/// compared. Edges that lead to equivalent game states have their statistics
+/// summed.
+fn collect_root_statistics<'a, 'id, G: ThudGame>(
+  view: &SearchView<'a, 'id, G>,
+  root: search_graph::view::NodeRef<'id>,
+  state: &crate::state::State,
+) -> RootStatistics<G> {
+  let actions: HashMap<crate::state::State, Action> = state
+    .actions()
+    .map(|action| {
+      let mut child_state = state.clone();
+      child_state.do_action(&action);
+      (child_state, action)
+    })
+    .collect();
+  let mut statistics = Vec::new();
+  for child in view.children(root) {
+    let action = actions
+      .get(view.node_state(view.edge_target(child)))
+      .expect("no action on game state leads to search graph child");
+    let proven = view.node_data(view.edge_target(child)).solution();
+    add_statistics::<G>(&mut statistics, action, &view[child].statistics, proven);
+  }
+  statistics
+}
+
+/// Sums the statistics of each action across several searches.
+fn merge_root_statistics<G: ThudGame>(members: &[RootStatistics<G>]) -> RootStatistics<G> {
+  let mut merged = Vec::new();
+  for member in members.iter() {
+    for (action, stats, proven) in member.iter() {
+      add_statistics::<G>(
+        &mut merged,
+        action,
+        stats,
+        proven.as_ref().map(copy_payoff::<G>),
+      );
+    }
+  }
+  merged
+}
+
+fn add_statistics<G: ThudGame>(
+  statistics: &mut RootStatistics<G>,
+  action: &Action,
+  stats: &G::Statistics,
+  proven: Option<G::Payoff>,
+) {
+  match statistics.iter_mut().find(|(a, _, _)| a == action) {
+    Some((_, existing, existing_proven)) => {
+      existing.merge(stats);
+      if existing_proven.is_none() {
+        *existing_proven = proven;
+      }
+    }
+    None => statistics.push((*action, stats.clone(), proven)),
+  }
+}
+
+fn action_statistics<G: ThudGame>(
+  statistics: &[RootActionStatistics<G>],
+  state: &crate::state::State,
+  settings: &SearchSettings,
+) -> Vec<mcts::ActionStatistics<G>> {
+  let parent_visits = statistics
+    .iter()
+    .map(|(_, s, _)| s.visits())
+    .fold(0, u32::saturating_add);
+  statistics
+    .iter()
+    .map(|(action, stats, proven)| {
+      let mut action_statistics = mcts::ActionStatistics::new(
+        *action,
+        stats,
+        state.active_role(),
+        parent_visits,
+        settings.explore_bias,
+      );
+      action_statistics.proven = proven.as_ref().map(copy_payoff::<G>);
+      action_statistics
+    })
+    .collect()
+}
+
+/// Returns the element of `statistics` with the best proven payoff for the
+/// player to move at `state`, or `None` if no action has a proven payoff.
+fn find_forced_action<'s, G: ThudGame>(
+  statistics: &'s [mcts::ActionStatistics<G>],
+  state: &crate::state::State,
+) -> Option<&'s mcts::ActionStatistics<G>> {
+  let mut best = None;
+  let mut best_score = f32::NEG_INFINITY;
+  for s in statistics.iter() {
+    if let Some(ref proven) = s.proven {
+      let score = mcts::solver::payoff_score::<G>(proven, state.active_role());
+      if best.is_none() || score > best_score {
+        best = Some(s);
+        best_score = score;
+      }
+    }
+  }
+  best
+}
+
+/// Returns the element of `statistics` that maximizes `key`, breaking ties
+/// uniformly at random.
+fn find_best_action<'s, G, F, R>(
+  statistics: &'s [mcts::ActionStatistics<G>],
+  key: F,
+  rng: &mut R,
+) -> &'s mcts::ActionStatistics<G>
+where
+  G: ThudGame,
+  F: Fn(&mcts::ActionStatistics<G>) -> f64,
+  R: Rng,
+{
+  let mut best = &statistics[0];
+  let mut best_key = key(best);
+  let mut reservoir_count = 1u32;
+  for s in statistics[1..].iter() {
+    let k = key(s);
+    match k.partial_cmp(&best_key) {
+      None | Some(cmp::Ordering::Less) => continue,
+      Some(cmp::Ordering::Equal) => {
+        reservoir_count += 1;
+        if !rng.gen_bool(1.0f64 / (reservoir_count as f64)) {
+          continue;
+        }
+      }
+      Some(cmp::Ordering::Greater) => reservoir_count = 1,
     }
+    best = s;
+    best_key = k;
   }
+  best
 }
 
-fn find_most_visited_child<'a, 'id, R: Rng>(
-  view: &search_graph::view::View<
-    'a,
-    'id,
-    crate::state::State,
-    mcts::graph::VertexData,
-    mcts::graph::EdgeData<Game>,
-  >,
+fn find_most_visited_child<'a, 'id, G: ThudGame, R: Rng>(
+  view: &SearchView<'a, 'id, G>,
   root: search_graph::view::NodeRef<'id>,
   mut rng: R,
 ) -> search_graph::view::EdgeRef<'id> {
@@ -149,72 +1175,479 @@ fn find_most_visited_child<'a, 'id, R: Rng>(
   best_child
 }
 
-impl<R: Rng + Send> crate::agent::Agent for Agent<R> {
+impl<R: Rng + Send, G: ThudGame> crate::agent::Agent for GameAgent<R, G>
+where
+  G::Statistics: Send + Sync,
+{
+  fn search_report(&self) -> Option<&SearchReport> {
+    self.search_report.as_ref()
+  }
+
   fn propose_action(&mut self, state: &crate::state::State) -> crate::agent::Result {
-    match self.graph_compact {
-      GraphCompact::Prune => {
-        if let Some(node) = self.graph.find_node_mut(state) {
-          search_graph::view::of_node(node, |view, node| {
-            view.retain_reachable_from(Some(node).into_iter());
-          });
-        } else {
-          mem::swap(&mut self.graph, &mut SearchGraph::new());
+    for graph in self.graphs.iter_mut() {
+      match self.graph_compact {
+        GraphCompact::Prune => {
+          if let Some(node) = graph.find_node_mut(state) {
+            search_graph::view::of_node(node, |view, node| {
+              view.retain_reachable_from(Some(node).into_iter());
+            });
+          } else {
+            mem::swap(graph, &mut SearchGraph::new());
+          }
         }
+        GraphCompact::Clear => mem::swap(graph, &mut SearchGraph::new()),
+        GraphCompact::Retain => (),
       }
-      GraphCompact::Clear => mem::swap(&mut self.graph, &mut SearchGraph::new()),
-      GraphCompact::Retain => (),
     }
 
-    // Borrow/copy stuff out of self because the closure passed to of_graph
-    // can't borrow self.
-    let (rng, graph, settings, iterations, action_select) = (
-      &mut self.rng,
-      &mut self.graph,
-      self.settings.clone(),
-      self.iterations,
-      self.action_select,
+    match self.search_mode {
+      SearchMode::SharedTree => self.propose_shared_tree_action(state),
+      SearchMode::RootEnsemble(_) => self.propose_ensemble_action(state),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    ActionSelect, Agent, Game, GraphCompact, OutcomeAgent, RolloutPolicy, SearchMode, SearchPolicy,
+    SimulationPolicy,
+  };
+  use crate::agent::Agent as _;
+  use crate::board::{self, Cells};
+  use crate::state::State;
+  use mcts::budget::SearchBudget;
+  use mcts::evict::GraphLimit;
+  use mcts::observe::{Observation, SearchObserver, SearchProgress};
+  use mcts::SearchSettings;
+  use rand::SeedableRng;
+  use std::sync::{Arc, Mutex};
+
+  /// The policies that most tests search with.
+  const UCB_UNIFORM: SearchPolicy = SearchPolicy {
+    rollout: RolloutPolicy::Ucb,
+    simulation: SimulationPolicy::Uniform,
+  };
+
+  /// Returns an agent that searches a single tree, seeded with `seed`, and
+  /// prunes its search graph to the chosen action after each search.
+  fn agent(
+    settings: SearchSettings,
+    budget: SearchBudget,
+    seed: u8,
+    policy: SearchPolicy,
+  ) -> Agent<rand_pcg::Pcg64> {
+    Agent::new(
+      settings,
+      budget,
+      rand_pcg::Pcg64::from_seed([seed; 32]),
+      ActionSelect::VisitCount,
+      GraphCompact::Prune,
+      SearchMode::SharedTree,
+      policy,
+    )
+  }
+
+  #[test]
+  fn captures_have_higher_priors() {
+    use crate::actions::Action;
+    use mcts::game::Game as _;
+    let actions = [
+      Action::Move(coordinate_literal!(7, 7), coordinate_literal!(7, 8)),
+      Action::Hurl(coordinate_literal!(7, 7), coordinate_literal!(7, 9)),
+      Action::Shove(
+        coordinate_literal!(7, 7),
+        coordinate_literal!(7, 8),
+        2,
+        [coordinate_literal!(7, 7); 7],
+      ),
+      Action::ProposeEnd,
+    ];
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    let priors = super::Game::action_priors(&state, &actions);
+    assert_eq!(priors[0], priors[3]);
+    assert!(priors[1] > priors[2]);
+    assert!(priors[2] > priors[0]);
+  }
+
+  #[test]
+  fn root_ensemble_merges_statistics() {
+    let settings = SearchSettings::default();
+    let mut agent = Agent::new(
+      settings,
+      SearchBudget::iterations(20),
+      rand_pcg::Pcg64::from_seed([1; 32]),
+      ActionSelect::VisitCount,
+      GraphCompact::Prune,
+      SearchMode::RootEnsemble(3),
+      UCB_UNIFORM,
     );
-    search_graph::view::of_graph(graph, |view| -> crate::agent::Result {
-      let mut rollout = mcts::RolloutPhase::initialize(rng, settings, state.clone(), view);
-      for _ in 0..iterations {
-        let scoring = match rollout.rollout::<mcts::ucb::Rollout>() {
-          Ok(s) => s,
-          Err(e) => return Err(Box::new(e)),
-        };
-        let backprop = match scoring.score::<mcts::simulation::RandomSimulator>() {
-          Ok(b) => b,
-          Err(e) => return Err(Box::new(e)),
-        };
-        rollout = backprop
-          .backprop::<mcts::ucb::BestParentBackprop>()
-          .expand();
-      }
-      let (rng, view) = rollout.recover_components();
-
-      let root = view.find_node(state).unwrap();
-      let child_edge = match action_select {
-        ActionSelect::Ucb => {
-          match mcts::ucb::find_best_child(&view, root, settings.explore_bias, rng) {
-            Ok(child) => child,
-            Err(e) => return Err(Box::new(e)),
-          }
-        }
-        ActionSelect::VisitCount => find_most_visited_child(&view, root, rng),
-      };
-      // Because search graph de-duplication maps each set of equivalent game
-      // states to a single "canonical" game state, the state in the search graph
-      // that corresponds to `state` may not actually be the game state at `root`. As
-      // a result, actions on the root game state need to be mapped back into the
-      // set of actions on `state`.
-      let transposed_to_state = view.node_state(view.edge_target(child_edge));
-      for action in state.actions() {
-        let mut actual_to_state = state.clone();
-        actual_to_state.do_action(&action);
-        if actual_to_state == *transposed_to_state {
-          return Ok(action);
-        }
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    let action = agent.propose_action(&state).unwrap();
+    assert!(state.actions().any(|a| a == action));
+    assert_eq!(3, agent.ensemble_statistics().len());
+    for merged in agent.root_statistics().iter() {
+      assert_eq!(
+        1,
+        agent
+          .root_statistics()
+          .iter()
+          .filter(|s| s.action == merged.action)
+          .count()
+      );
+      let member_visits: u32 = agent
+        .ensemble_statistics()
+        .iter()
+        .filter_map(|m| m.iter().find(|s| s.action == merged.action))
+        .map(|s| s.payoff.visits)
+        .sum();
+      assert_eq!(member_visits, merged.payoff.visits);
+    }
+    let merged_visits: u32 = agent
+      .root_statistics()
+      .iter()
+      .map(|s| s.payoff.visits)
+      .sum();
+    assert!(merged_visits > 0);
+  }
+
+  #[test]
+  fn puct_agent_proposes_legal_action() {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    let policy = SearchPolicy {
+      rollout: RolloutPolicy::Puct,
+      simulation: SimulationPolicy::EpsilonGreedy,
+    };
+    let mut agent = agent(settings, SearchBudget::iterations(10), 2, policy);
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    let action = agent.propose_action(&state).unwrap();
+    assert!(state.actions().any(|a| a == action));
+    assert!(agent.ensemble_statistics().is_empty());
+    assert!(!agent.root_statistics().is_empty());
+  }
+
+  #[test]
+  fn bounded_graph_agent_searches_past_limit() {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    let mut agent = Agent::new(
+      settings,
+      SearchBudget::iterations(200),
+      rand_pcg::Pcg64::from_seed([4; 32]),
+      ActionSelect::VisitCount,
+      GraphCompact::Retain,
+      SearchMode::SharedTree,
+      UCB_UNIFORM,
+    )
+    .with_graph_limit(Some(GraphLimit::Vertices(1000)));
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    let action = agent.propose_action(&state).unwrap();
+    assert!(state.actions().any(|a| a == action));
+    assert!(agent.graphs[0].vertex_count() < 1000);
+    let visits: u32 = agent.root_statistics().iter().map(|s| s.visits).sum();
+    assert!(visits >= 199);
+  }
+
+  #[test]
+  fn agent_reports_search() {
+    let settings = SearchSettings {
+      simulation_count: 2,
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    let mut agent = agent(settings, SearchBudget::iterations(30), 5, UCB_UNIFORM);
+    assert!(agent.search_report().is_none());
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    agent.propose_action(&state).unwrap();
+    let report = agent.search_report().unwrap();
+    assert_eq!(30, report.iterations);
+    assert_eq!(agent.graphs[0].vertex_count(), report.node_count);
+    assert_eq!(agent.graphs[0].edge_count(), report.edge_count);
+    // Only the root was in the graph before search.
+    assert_eq!(report.node_count, report.nodes_added + 1);
+    assert_eq!(60, report.simulations);
+    assert!(report.max_rollout_depth >= 1);
+    assert_eq!(None, report.failure);
+  }
+
+  /// Records the iterations at which it is notified, and stops search after
+  /// `stop_after` notifications.
+  struct CountingObserver {
+    interval: u32,
+    stop_after: usize,
+    seen: Arc<Mutex<Vec<u32>>>,
+  }
+
+  impl SearchObserver<Game> for CountingObserver {
+    fn interval(&self) -> u32 {
+      self.interval
+    }
+
+    fn observe(&mut self, progress: &SearchProgress<Game>) -> Observation {
+      assert!(progress.best.is_some());
+      let mut seen = self.seen.lock().unwrap();
+      seen.push(progress.iterations);
+      if seen.len() >= self.stop_after {
+        Observation::Stop
+      } else {
+        Observation::Continue
       }
-      unreachable!()
-    })
+    }
+  }
+
+  fn observed_agent(search_mode: SearchMode, iterations: u32) -> Agent<rand_pcg::Pcg64> {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    Agent::new(
+      settings,
+      SearchBudget::iterations(iterations),
+      rand_pcg::Pcg64::from_seed([6; 32]),
+      ActionSelect::VisitCount,
+      GraphCompact::Clear,
+      search_mode,
+      UCB_UNIFORM,
+    )
+  }
+
+  #[test]
+  fn observer_sees_search_progress() {
+    let mut agent = observed_agent(SearchMode::SharedTree, 45);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    agent.set_observer(Some(Box::new(CountingObserver {
+      interval: 10,
+      stop_after: usize::MAX,
+      seen: Arc::clone(&seen),
+    })));
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    agent.propose_action(&state).unwrap();
+    assert_eq!(vec![10, 20, 30, 40], *seen.lock().unwrap());
+    assert!(!agent.search_report().unwrap().cancelled);
+    // The observer is kept for the next search.
+    agent.propose_action(&state).unwrap();
+    assert_eq!(8, seen.lock().unwrap().len());
+  }
+
+  #[test]
+  fn observer_stops_search() {
+    for &mode in [SearchMode::SharedTree, SearchMode::RootEnsemble(2)].iter() {
+      let mut agent = observed_agent(mode, 1000);
+      let seen = Arc::new(Mutex::new(Vec::new()));
+      agent.set_observer(Some(Box::new(CountingObserver {
+        interval: 5,
+        stop_after: 2,
+        seen: Arc::clone(&seen),
+      })));
+      let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+      agent.propose_action(&state).unwrap();
+      assert_eq!(vec![5, 10], *seen.lock().unwrap());
+      let report = agent.search_report().unwrap();
+      assert!(report.cancelled);
+      assert!(report.iterations < 1000);
+      let visits: u32 = agent.root_statistics().iter().map(|s| s.visits).sum();
+      assert!(visits > 0);
+    }
+  }
+
+  /// A dwarf that can capture the last troll by hurling itself at it.
+  fn forced_win_state() -> State {
+    State::new(
+      board::decode_board(
+        r#"
+....._____.....
+...._______....
+..._________...
+..___________..
+._____________.
+_______________
+_______________
+_____Td________
+_______________
+_______________
+._____________.
+..___________..
+..._________...
+...._______....
+....._____.....
+"#,
+      ),
+      &board::TRANSPOSITIONAL_EQUIVALENCE,
+    )
+  }
+
+  #[test]
+  fn solver_agent_plays_forced_win() {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      solver: true,
+      ..Default::default()
+    };
+    let mut agent = agent(settings, SearchBudget::iterations(200), 3, UCB_UNIFORM);
+    let state = forced_win_state();
+    let action = agent.propose_action(&state).unwrap();
+    assert!(action.is_hurl());
+    let forced = agent.forced_result().unwrap();
+    assert_eq!((1, 0), (forced.score_one, forced.score_two));
+    let proven = agent
+      .root_statistics()
+      .iter()
+      .find(|s| s.action == action)
+      .unwrap();
+    assert_eq!(Some(*forced), proven.proven);
+  }
+
+  #[test]
+  fn seeded_agents_play_the_same_game() {
+    let settings = SearchSettings {
+      simulation_count: 4,
+      simulation_thread_limit: 4,
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    let play = |seed: u8| {
+      let mut agent = agent(settings, SearchBudget::iterations(20), seed, UCB_UNIFORM);
+      let mut state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+      let mut actions = Vec::new();
+      for _ in 0..6 {
+        let action = agent.propose_action(&state).unwrap();
+        state.do_action(&action);
+        actions.push(action);
+      }
+      actions
+    };
+    assert_eq!(play(7), play(7));
+  }
+
+  #[test]
+  fn variance_agents_propose_legal_actions() {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    for &rollout in [RolloutPolicy::Ucb1Tuned, RolloutPolicy::UcbV].iter() {
+      let policy = SearchPolicy {
+        rollout,
+        simulation: SimulationPolicy::Uniform,
+      };
+      let mut agent = agent(settings, SearchBudget::iterations(10), 4, policy);
+      let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+      let action = agent.propose_action(&state).unwrap();
+      assert!(state.actions().any(|a| a == action));
+    }
+  }
+
+  #[test]
+  fn normalized_payoffs_score_at_most_one_per_visit() {
+    // Each visit is one playout, however many are simulated per rollout, so
+    // that a normalised win adds 1 to the score of one visit.
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      simulation_count: 2,
+      payoff_normalization: Some(mcts::normalize::Normalization::WinDrawLoss),
+      ..Default::default()
+    };
+    let mut agent = agent(settings, SearchBudget::iterations(20), 5, UCB_UNIFORM);
+    let state = State::new(Cells::default(), &board::TRANSPOSITIONAL_EQUIVALENCE);
+    let action = agent.propose_action(&state).unwrap();
+    assert!(state.actions().any(|a| a == action));
+    for stats in agent.root_statistics() {
+      assert!(stats.payoff.score_one + stats.payoff.score_two <= stats.payoff.visits);
+    }
+  }
+
+  #[test]
+  fn outcome_agent_plays_forced_win() {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      solver: true,
+      ..Default::default()
+    };
+    let mut agent = OutcomeAgent::new(
+      settings,
+      SearchBudget::iterations(200),
+      rand_pcg::Pcg64::from_seed([6; 32]),
+      ActionSelect::VisitCount,
+      GraphCompact::Prune,
+      SearchMode::SharedTree,
+      UCB_UNIFORM,
+    );
+    let state = forced_win_state();
+    let action = agent.propose_action(&state).unwrap();
+    assert!(action.is_hurl());
+    let result = agent.forced_result().unwrap();
+    assert_eq!((1, 0, 1), (result.wins_one, result.wins_two, result.margin));
+  }
+
+  #[test]
+  fn actions_persist() {
+    use crate::actions::Action;
+    use crate::end::Decision;
+    use mcts::persist::Persist;
+    let actions = [
+      Action::Move(coordinate_literal!(7, 7), coordinate_literal!(7, 8)),
+      Action::Hurl(coordinate_literal!(7, 7), coordinate_literal!(7, 9)),
+      Action::Shove(
+        coordinate_literal!(7, 7),
+        coordinate_literal!(7, 8),
+        2,
+        [
+          coordinate_literal!(6, 8),
+          coordinate_literal!(8, 8),
+          coordinate_literal!(7, 7),
+          coordinate_literal!(7, 7),
+          coordinate_literal!(7, 7),
+          coordinate_literal!(7, 7),
+          coordinate_literal!(7, 7),
+        ],
+      ),
+      Action::ProposeEnd,
+      Action::HandleEndProposal(Decision::Accept),
+      Action::HandleEndProposal(Decision::Decline),
+    ];
+    for action in actions.iter() {
+      assert_eq!(Some(*action), Action::restore(&action.persist()));
+    }
+    assert_eq!(None, Action::restore("M 7 7 7"));
+    assert_eq!(None, Action::restore("M 0 0 7 8"));
+  }
+
+  #[test]
+  fn saved_graph_reloads_into_agent() {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      ..Default::default()
+    };
+    let new_agent = || {
+      Agent::new(
+        settings,
+        SearchBudget::iterations(50),
+        rand_pcg::Pcg64::from_seed([7; 32]),
+        ActionSelect::VisitCount,
+        GraphCompact::Retain,
+        SearchMode::SharedTree,
+        UCB_UNIFORM,
+      )
+    };
+    let state = forced_win_state();
+    let mut agent = new_agent();
+    agent.propose_action(&state).unwrap();
+    let mut saved = Vec::new();
+    agent.save_graph(&state, &mut saved).unwrap();
+
+    let mut loaded = new_agent();
+    loaded.load_graph(&state, &saved[..]).unwrap();
+    let mut resaved = Vec::new();
+    loaded.save_graph(&state, &mut resaved).unwrap();
+    assert_eq!(saved, resaved);
+
+    loaded.propose_action(&state).unwrap();
+    let visits: u32 = loaded.root_statistics().iter().map(|s| s.visits).sum();
+    assert!(visits > 50);
   }
 }
@@ -0,0 +1,169 @@
+//! Heuristic playout policies for MCTS simulations of Thud.
+//!
+//! Uniformly random playouts of Thud mostly shuffle pieces around and rarely
+//! capture anything, so their payoffs say little about the state that they
+//! start from. The policies here instead favor capturing moves and avoid
+//! moving dwarfs to where trolls can reach them. Playouts that are cut off
+//! before the game ends are scored by the material left on the board.
+
+use super::mcts::ThudGame;
+use crate::actions::Action;
+use crate::coordinate::Coordinate;
+use crate::state::State;
+use crate::Role;
+
+/// Score for each point of value captured by an action.
+const CAPTURE_SCORE: f64 = 4.0;
+
+/// Score penalty for each troll that could capture a dwarf after it moves.
+const EXPOSURE_PENALTY: f64 = 1.0;
+
+/// Scores actions by the value of the pieces that they capture, less a penalty
+/// for moving a dwarf within reach of trolls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CaptureScore;
+
+impl<G: ThudGame> mcts::playout::ActionScore<G> for CaptureScore {
+  fn score(state: &State, action: &Action) -> f64 {
+    match *action {
+      // A hurl captures a troll, which is worth 4 points.
+      Action::Hurl(_, end) => CAPTURE_SCORE * 4.0 - EXPOSURE_PENALTY * exposure(state, end) as f64,
+      // A shove captures one dwarf, worth 1 point, for each adjacent dwarf.
+      Action::Shove(_, _, count, _) => CAPTURE_SCORE * count as f64,
+      Action::Move(_, end) if *state.active_role() == Role::Dwarf => {
+        -EXPOSURE_PENALTY * exposure(state, end) as f64
+      }
+      Action::Move(_, _) | Action::ProposeEnd | Action::HandleEndProposal(_) => 0.0,
+    }
+  }
+}
+
+/// Returns the number of trolls, other than one at `position`, that are close
+/// enough to capture a dwarf at `position` on their next move. A troll can do
+/// so if it is within two spaces, because it may step next to the dwarf and
+/// capture it.
+fn exposure(state: &State, position: Coordinate) -> usize {
+  state
+    .board()
+    .occupied_iter(Role::Troll)
+    .filter(|&troll| troll != position && distance(troll, position) <= 2)
+    .count()
+}
+
+/// Returns the number of king moves between `a` and `b` on an open board.
+fn distance(a: Coordinate, b: Coordinate) -> u8 {
+  let rows = if a.row() > b.row() {
+    a.row() - b.row()
+  } else {
+    b.row() - a.row()
+  };
+  let cols = if a.col() > b.col() {
+    a.col() - b.col()
+  } else {
+    b.col() - a.col()
+  };
+  std::cmp::max(rows, cols)
+}
+
+/// Scores a playout that was cut off by the material that each side has left
+/// on the board, as given by `State::score`. This is the same as the payoff of
+/// a game that ends in that state.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialEvaluator;
+
+impl<G: ThudGame> mcts::simulation::Evaluator<G> for MaterialEvaluator {
+  fn evaluate(state: &State) -> Option<G::Payoff> {
+    Some(G::payoff_of_points(
+      state.score(Role::Dwarf) as u32,
+      state.score(Role::Troll) as u32,
+    ))
+  }
+}
+
+/// Chooses a random action with probability `PlayoutSettings::epsilon`, and
+/// otherwise the action with the best [CaptureScore](struct.CaptureScore.html).
+pub type EpsilonGreedyPlayout = mcts::playout::EpsilonGreedy<CaptureScore>;
+
+/// Chooses actions according to the softmax of their
+/// [CaptureScore](struct.CaptureScore.html).
+pub type SoftmaxPlayout = mcts::playout::Softmax<CaptureScore>;
+
+#[cfg(test)]
+mod test {
+  use super::{CaptureScore, MaterialEvaluator};
+  use crate::actions::Action;
+  use crate::ai::mcts::Game;
+  use crate::board;
+  use crate::state::State;
+  use mcts::playout::{ActionScore, PlayoutPolicy, PlayoutSettings};
+  use mcts::SearchSettings;
+  use rand::SeedableRng;
+
+  /// A troll that can shove one of two dwarfs, which can hurl at it.
+  fn capture_state() -> State {
+    State::new(
+      board::decode_board(
+        r#"
+....._____.....
+...._______....
+..._________...
+..___________..
+._____________.
+_______________
+_______________
+_____T_dd______
+_______________
+_______________
+._____________.
+..___________..
+..._________...
+...._______....
+....._____.....
+"#,
+      ),
+      &board::TRANSPOSITIONAL_EQUIVALENCE,
+    )
+  }
+
+  fn settings() -> SearchSettings {
+    SearchSettings {
+      playout: PlayoutSettings {
+        epsilon: 0.0,
+        temperature: 1.0,
+      },
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn greedy_playout_prefers_captures() {
+    let policy = super::EpsilonGreedyPlayout::from(&settings());
+    let mut rng = rand_pcg::Pcg64::from_seed([0; 32]);
+    let mut actions = Vec::new();
+    let mut state = capture_state();
+    let action = PlayoutPolicy::<Game>::choose(&policy, &state, &mut actions, &mut rng).unwrap();
+    assert!(action.is_hurl());
+    state.toggle_active_role();
+    let action = PlayoutPolicy::<Game>::choose(&policy, &state, &mut actions, &mut rng).unwrap();
+    assert!(action.is_shove());
+  }
+
+  #[test]
+  fn material_evaluation() {
+    use mcts::simulation::Evaluator;
+    let payoff = <MaterialEvaluator as Evaluator<Game>>::evaluate(&capture_state()).unwrap();
+    assert_eq!(1, payoff.visits);
+    assert_eq!(2, payoff.score_one);
+    assert_eq!(4, payoff.score_two);
+  }
+
+  #[test]
+  fn dwarf_moves_near_trolls_are_penalized() {
+    let state = capture_state();
+    let exposed = Action::Move(coordinate_literal!(7, 7), coordinate_literal!(6, 7));
+    let safe = Action::Move(coordinate_literal!(7, 8), coordinate_literal!(7, 12));
+    let score = <CaptureScore as ActionScore<Game>>::score;
+    assert!(score(&state, &exposed) < score(&state, &safe));
+    assert_eq!(0.0, score(&state, &safe));
+  }
+}
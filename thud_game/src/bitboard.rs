@@ -0,0 +1,509 @@
+//! A bitboard representation of the Thud board, for fast move generation.
+//!
+//! A [BitBoard](struct.BitBoard.html) keeps a set of [Bits](struct.Bits.html)
+//! for each kind of token, with one bit for each of the 165 cells of the
+//! board, indexed by `Coordinate::index`. Each ray from a coordinate in a
+//! direction is precomputed as a mask, so finding how far a piece can travel
+//! is a few word operations instead of a walk along the ray.
+//!
+//! Coordinates are indexed row by row, so the index of each cell along a ray
+//! either always increases or always decreases. The nearest occupied cell
+//! along a ray is therefore its lowest or highest set bit.
+
+use crate::actions::Action;
+use crate::board::Token;
+use crate::coordinate::{Coordinate, Direction};
+use crate::Role;
+
+use lazy_static::lazy_static;
+
+use std::ops::{BitAnd, BitOr};
+
+const CELL_COUNT: usize = 165;
+const DIRECTION_COUNT: usize = 8;
+
+/// A set of cells on the board.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Bits {
+  words: [u64; 3],
+}
+
+impl Bits {
+  /// Returns the empty set.
+  pub fn empty() -> Self {
+    Bits { words: [0; 3] }
+  }
+
+  /// Returns the set of cells whose index is less than that of `c`.
+  pub fn below(c: Coordinate) -> Self {
+    let index = c.index();
+    let mut bits = Bits::empty();
+    for (i, word) in bits.words.iter_mut().enumerate() {
+      let start = i * 64;
+      if index >= start + 64 {
+        *word = !0;
+      } else if index > start {
+        *word = (1u64 << (index - start)) - 1;
+      }
+    }
+    bits
+  }
+
+  /// Returns the set of cells whose index is greater than that of `c`.
+  pub fn above(c: Coordinate) -> Self {
+    let mut bits = Bits::below(c);
+    bits.toggle(c);
+    for word in bits.words.iter_mut() {
+      *word = !*word;
+    }
+    bits.words[2] &= (1u64 << (CELL_COUNT - 128)) - 1;
+    bits
+  }
+
+  /// Returns `true` iff `c` is in this set.
+  pub fn contains(&self, c: Coordinate) -> bool {
+    let index = c.index();
+    self.words[index / 64] & (1u64 << (index % 64)) != 0
+  }
+
+  /// Adds `c` to this set if it is absent, and removes it otherwise.
+  pub fn toggle(&mut self, c: Coordinate) {
+    let index = c.index();
+    self.words[index / 64] ^= 1u64 << (index % 64);
+  }
+
+  /// Returns `true` iff this set has no cells.
+  pub fn is_empty(&self) -> bool {
+    self.words == [0; 3]
+  }
+
+  /// Returns the number of cells in this set.
+  pub fn count(&self) -> u32 {
+    self.words.iter().map(|w| w.count_ones()).sum()
+  }
+
+  /// Returns the cells that are in this set but not in `other`.
+  pub fn and_not(self, other: Bits) -> Bits {
+    Bits {
+      words: [
+        self.words[0] & !other.words[0],
+        self.words[1] & !other.words[1],
+        self.words[2] & !other.words[2],
+      ],
+    }
+  }
+
+  /// Returns the cell in this set with the least index.
+  pub fn first(&self) -> Option<Coordinate> {
+    self.iter().next()
+  }
+
+  /// Returns the cell in this set with the greatest index.
+  pub fn last(&self) -> Option<Coordinate> {
+    self.iter().next_back()
+  }
+
+  /// Returns an iterator over the cells in this set, in order of index.
+  pub fn iter(&self) -> BitsIter {
+    BitsIter { words: self.words }
+  }
+}
+
+impl BitAnd for Bits {
+  type Output = Bits;
+
+  fn bitand(self, other: Bits) -> Bits {
+    Bits {
+      words: [
+        self.words[0] & other.words[0],
+        self.words[1] & other.words[1],
+        self.words[2] & other.words[2],
+      ],
+    }
+  }
+}
+
+impl BitOr for Bits {
+  type Output = Bits;
+
+  fn bitor(self, other: Bits) -> Bits {
+    Bits {
+      words: [
+        self.words[0] | other.words[0],
+        self.words[1] | other.words[1],
+        self.words[2] | other.words[2],
+      ],
+    }
+  }
+}
+
+/// Iterates over the cells in a set of [Bits](struct.Bits.html), from either
+/// end.
+pub struct BitsIter {
+  words: [u64; 3],
+}
+
+impl Iterator for BitsIter {
+  type Item = Coordinate;
+
+  fn next(&mut self) -> Option<Coordinate> {
+    for (i, word) in self.words.iter_mut().enumerate() {
+      if *word != 0 {
+        let bit = word.trailing_zeros() as usize;
+        *word &= *word - 1;
+        return Some(Coordinate::from_index(i * 64 + bit));
+      }
+    }
+    None
+  }
+}
+
+impl DoubleEndedIterator for BitsIter {
+  fn next_back(&mut self) -> Option<Coordinate> {
+    for (i, word) in self.words.iter_mut().enumerate().rev() {
+      if *word != 0 {
+        let bit = 63 - word.leading_zeros() as usize;
+        *word ^= 1u64 << bit;
+        return Some(Coordinate::from_index(i * 64 + bit));
+      }
+    }
+    None
+  }
+}
+
+fn direction_index(d: Direction) -> usize {
+  match d {
+    Direction::Up => 0,
+    Direction::Down => 1,
+    Direction::Left => 2,
+    Direction::Right => 3,
+    Direction::UpLeft => 4,
+    Direction::UpRight => 5,
+    Direction::DownLeft => 6,
+    Direction::DownRight => 7,
+  }
+}
+
+/// Precomputed masks of the cells around each coordinate.
+struct Masks {
+  /// The cells along the ray from each coordinate in each direction, not
+  /// including the coordinate itself.
+  rays: Vec<[Bits; DIRECTION_COUNT]>,
+  /// The cells adjacent to each coordinate.
+  neighbors: Vec<Bits>,
+  /// Whether the index of each cell along a ray in each direction is greater
+  /// than the last.
+  ascending: [bool; DIRECTION_COUNT],
+}
+
+lazy_static! {
+  static ref MASKS: Masks = {
+    let mut rays = vec![[Bits::empty(); DIRECTION_COUNT]; CELL_COUNT];
+    let mut neighbors = vec![Bits::empty(); CELL_COUNT];
+    let mut ascending = [false; DIRECTION_COUNT];
+    for &start in Coordinate::all() {
+      for &d in Direction::all() {
+        let mut here = start.to_direction(d);
+        if let Some(next) = here {
+          neighbors[start.index()].toggle(next);
+          ascending[direction_index(d)] = next.index() > start.index();
+        }
+        while let Some(c) = here {
+          rays[start.index()][direction_index(d)].toggle(c);
+          here = c.to_direction(d);
+        }
+      }
+    }
+    Masks {
+      rays,
+      neighbors,
+      ascending,
+    }
+  };
+}
+
+/// The cells along a ray from `start` in direction `d` that come before the
+/// first cell in `blockers`, and that first cell, if any.
+struct Run {
+  cells: Bits,
+  blocker: Option<Coordinate>,
+  ascending: bool,
+}
+
+impl Run {
+  fn new(start: Coordinate, d: Direction, blockers: Bits) -> Self {
+    let ray = MASKS.rays[start.index()][direction_index(d)];
+    let ascending = MASKS.ascending[direction_index(d)];
+    let blocked = ray & blockers;
+    let (cells, blocker) = if ascending {
+      match blocked.first() {
+        Some(b) => (ray & Bits::below(b), Some(b)),
+        None => (ray, None),
+      }
+    } else {
+      match blocked.last() {
+        Some(b) => (ray & Bits::above(b), Some(b)),
+        None => (ray, None),
+      }
+    };
+    Run {
+      cells,
+      blocker,
+      ascending,
+    }
+  }
+
+  /// Returns the cells of the run, nearest first.
+  fn iter(&self) -> RunIter {
+    RunIter {
+      cells: self.cells.iter(),
+      ascending: self.ascending,
+    }
+  }
+}
+
+struct RunIter {
+  cells: BitsIter,
+  ascending: bool,
+}
+
+impl Iterator for RunIter {
+  type Item = Coordinate;
+
+  fn next(&mut self) -> Option<Coordinate> {
+    if self.ascending {
+      self.cells.next()
+    } else {
+      self.cells.next_back()
+    }
+  }
+}
+
+/// The cells occupied by each kind of token.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct BitBoard {
+  stones: Bits,
+  dwarfs: Bits,
+  trolls: Bits,
+}
+
+impl BitBoard {
+  /// Returns an empty board.
+  pub fn empty() -> Self {
+    BitBoard::default()
+  }
+
+  /// Updates the board for `t` being added to or removed from `c`.
+  pub fn toggle(&mut self, c: Coordinate, t: Token) {
+    match t {
+      Token::Stone => self.stones.toggle(c),
+      Token::Dwarf => self.dwarfs.toggle(c),
+      Token::Troll => self.trolls.toggle(c),
+    }
+  }
+
+  /// Returns the cells occupied by pieces that have the role `r`.
+  pub fn role(&self, r: Role) -> Bits {
+    match r {
+      Role::Dwarf => self.dwarfs,
+      Role::Troll => self.trolls,
+    }
+  }
+
+  /// Returns the cells occupied by any token.
+  pub fn occupied(&self) -> Bits {
+    self.stones | self.dwarfs | self.trolls
+  }
+
+  /// Returns `true` iff the role `r` has any moves, hurls or shoves.
+  ///
+  /// Every piece that can travel at all can move to an adjacent empty cell,
+  /// and a dwarf next to a troll can always hurl itself onto it, so only
+  /// adjacent cells need to be checked.
+  pub fn has_role_actions(&self, r: Role) -> bool {
+    let blockers = match r {
+      Role::Dwarf => self.stones | self.dwarfs,
+      Role::Troll => self.occupied(),
+    };
+    self
+      .role(r)
+      .iter()
+      .any(|c| !MASKS.neighbors[c.index()].and_not(blockers).is_empty())
+  }
+
+  /// Appends to `actions` the same actions as
+  /// [Cells::role_actions](../board/struct.Cells.html#method.role_actions),
+  /// in the same order.
+  pub fn append_role_actions(&self, r: Role, allow_end_proposal: bool, actions: &mut Vec<Action>) {
+    let occupied = self.occupied();
+    let pieces = self.role(r);
+    for start in pieces.iter() {
+      for &d in Direction::all() {
+        let run = Run::new(start, d, occupied);
+        match r {
+          Role::Dwarf => actions.extend(run.iter().map(|end| Action::Move(start, end))),
+          Role::Troll => actions.extend(run.iter().take(1).map(|end| Action::Move(start, end))),
+        }
+      }
+    }
+    for start in pieces.iter() {
+      for &d in Direction::all() {
+        match r {
+          Role::Dwarf => self.append_hurl(start, d, occupied, actions),
+          Role::Troll => self.append_shoves(start, d, occupied, actions),
+        }
+      }
+    }
+    if allow_end_proposal {
+      actions.push(Action::ProposeEnd);
+    }
+  }
+
+  /// Appends the hurl by the dwarf at `start` in direction `d`, if there is
+  /// one. A dwarf may be hurled as far as the length of the line of dwarfs
+  /// that ends with it, onto a troll, over empty cells.
+  fn append_hurl(
+    &self,
+    start: Coordinate,
+    d: Direction,
+    occupied: Bits,
+    actions: &mut Vec<Action>,
+  ) {
+    let forward = Run::new(start, d, occupied);
+    match forward.blocker {
+      Some(end) if self.trolls.contains(end) => {
+        let ray = MASKS.rays[start.index()][direction_index(d.reverse())];
+        let line = Run::new(start, d.reverse(), ray.and_not(self.dwarfs));
+        if line.cells.count() >= forward.cells.count() {
+          actions.push(Action::Hurl(start, end));
+        }
+      }
+      _ => (),
+    }
+  }
+
+  /// Appends the shoves by the troll at `start` in direction `d`. A troll may
+  /// be shoved as far as the length of the line of trolls that ends with it,
+  /// over empty cells, onto any cell next to a dwarf.
+  fn append_shoves(
+    &self,
+    start: Coordinate,
+    d: Direction,
+    occupied: Bits,
+    actions: &mut Vec<Action>,
+  ) {
+    let forward = Run::new(start, d, occupied);
+    let ray = MASKS.rays[start.index()][direction_index(d.reverse())];
+    let line = Run::new(start, d.reverse(), ray.and_not(self.trolls));
+    let length = line.cells.count() as usize + 1;
+    for end in forward.iter().take(length) {
+      if (MASKS.neighbors[end.index()] & self.dwarfs).is_empty() {
+        continue;
+      }
+      let mut captured = [coordinate_literal!(7, 7); 7];
+      let mut i = 0u8;
+      for &adjacent in Direction::all() {
+        if let Some(adjacent) = end.to_direction(adjacent) {
+          if self.dwarfs.contains(adjacent) {
+            captured[i as usize] = adjacent;
+            i += 1;
+          }
+        }
+      }
+      actions.push(Action::Shove(start, end, i, captured));
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Bits;
+  use crate::actions::Action;
+  use crate::board::{decode_board, scripted_game, Cells};
+  use crate::coordinate::Coordinate;
+  use crate::Role;
+
+  /// Checks the bitboard's actions against those found by walking rays.
+  fn assert_same_actions(cells: &Cells) {
+    let mut actions = Vec::new();
+    for &r in [Role::Dwarf, Role::Troll].iter() {
+      for &allow_end_proposal in [false, true].iter() {
+        actions.clear();
+        cells
+          .bits()
+          .append_role_actions(r, allow_end_proposal, &mut actions);
+        let expected: Vec<Action> = cells.role_actions(r, allow_end_proposal).collect();
+        assert_eq!(expected, actions, "on board {:?}", cells);
+      }
+      assert_eq!(
+        cells.role_actions(r, false).next().is_some(),
+        cells.bits().has_role_actions(r),
+        "on board {:?}",
+        cells
+      );
+    }
+  }
+
+  #[test]
+  fn bits_iterate_in_order_of_index() {
+    let mut bits = Bits::empty();
+    for &index in [0, 63, 64, 100, 128, 164].iter() {
+      bits.toggle(Coordinate::from_index(index));
+    }
+    let indices: Vec<usize> = bits.iter().map(|c| c.index()).collect();
+    assert_eq!(vec![0, 63, 64, 100, 128, 164], indices);
+    let reversed: Vec<usize> = bits.iter().rev().map(|c| c.index()).collect();
+    assert_eq!(vec![164, 128, 100, 64, 63, 0], reversed);
+    let middle = Coordinate::from_index(64);
+    assert_eq!(2, (bits & Bits::below(middle)).count());
+    assert_eq!(3, (bits & Bits::above(middle)).count());
+    assert_eq!(165, (Bits::below(middle) | Bits::above(middle)).count() + 1);
+  }
+
+  #[test]
+  fn long_hurls_and_shoves_match_ray_walks() {
+    let cells = decode_board(
+      r#"
+.....d____.....
+....d______....
+..._________...
+.._____TT____..
+.____________d.
+_ddd__T________
+_______________
+_______O_d_____
+______TT_______
+__________d____
+.________d____.
+..d__________..
+...____T____...
+....d______....
+.....____d.....
+"#,
+    );
+    assert_same_actions(&cells);
+    let mut actions = Vec::new();
+    cells
+      .bits()
+      .append_role_actions(Role::Dwarf, false, &mut actions);
+    assert!(actions.contains(&Action::Hurl(
+      coordinate_literal!(5, 3),
+      coordinate_literal!(5, 6)
+    )));
+    actions.clear();
+    cells
+      .bits()
+      .append_role_actions(Role::Troll, false, &mut actions);
+    assert!(actions.iter().any(|a| match *a {
+      Action::Shove(start, end, _, _) => start == coordinate_literal!(8, 7) && end.col() == 9,
+      _ => false,
+    }));
+  }
+
+  #[test]
+  fn actions_match_ray_walks_through_a_game() {
+    for (cells, _) in scripted_game(300) {
+      assert_same_actions(&cells);
+    }
+  }
+}
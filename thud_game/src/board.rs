@@ -1,5 +1,7 @@
 use crate::actions::Action;
-use crate::coordinate::{Convolution, Coordinate, Direction};
+use crate::bitboard::BitBoard;
+use crate::coordinate::{Coordinate, Direction};
+use crate::zobrist::BoardKeys;
 use crate::Role;
 
 use r4::iterate;
@@ -7,11 +9,10 @@ use r4::iterate;
 #[cfg(test)] use quickcheck::{Arbitrary, Gen};
 
 use std::clone::Clone;
-use std::collections::hash_map::DefaultHasher;
 use std::default::Default;
 use std::fmt;
-use std::hash::{Hash, Hasher};
-use std::ops::{Index, IndexMut};
+use std::hash::Hash;
+use std::ops::Index;
 
 pub static SIMPLE_EQUIVALENCE: SimpleEquivalence = SimpleEquivalence{};
 pub static TRANSPOSITIONAL_EQUIVALENCE: TranspositionalEquivalence = TranspositionalEquivalence{};
@@ -119,8 +120,16 @@ impl Iterator for Ray {
 /// trolls. The dwarfs move first.
 ///
 /// This gives us 165 spaces, each of which may contain a piece.
+///
+/// The board keeps its [Zobrist keys](../zobrist/index.html) and a
+/// [bitboard](../bitboard/index.html) up to date as cells change, so it can be
+/// hashed without scanning it and can generate actions quickly. Cells are
+/// changed through [set](#method.set) rather than by indexing, so that these
+/// stay in step with them.
 pub struct Cells {
   cells: [Content; 165],
+  keys: BoardKeys,
+  bits: BitBoard,
 }
 
 impl Cells {
@@ -128,7 +137,33 @@ impl Cells {
   pub fn new() -> Self {
     Cells {
       cells: [Content::Empty; 165],
+      keys: BoardKeys::empty(),
+      bits: BitBoard::empty(),
+    }
+  }
+
+  /// Sets the content of the cell at `c`.
+  pub fn set(&mut self, c: Coordinate, content: Content) {
+    let cell = &mut self.cells[c.index()];
+    if let Content::Occupied(t) = *cell {
+      self.keys.toggle(c, t);
+      self.bits.toggle(c, t);
+    }
+    if let Content::Occupied(t) = content {
+      self.keys.toggle(c, t);
+      self.bits.toggle(c, t);
     }
+    *cell = content;
+  }
+
+  /// Returns the Zobrist keys of this board.
+  pub fn keys(&self) -> &BoardKeys {
+    &self.keys
+  }
+
+  /// Returns the bitboard of this board.
+  pub fn bits(&self) -> &BitBoard {
+    &self.bits
   }
 
   /// Returns an iterator over possible move actions that may be taken by a
@@ -227,6 +262,20 @@ impl Cells {
     move_actions.chain(hurl_actions).chain(shove_actions).chain(end_proposal_actions)
   }
 
+  /// Appends to `actions` the same actions as `role_actions`, in the same
+  /// order. This uses the bitboard, and does not allocate unless `actions`
+  /// must grow, so search can reuse one buffer for many positions.
+  pub fn append_role_actions(&self, r: Role, allow_end_proposal: bool, actions: &mut Vec<Action>) {
+    self
+      .bits
+      .append_role_actions(r, allow_end_proposal, actions)
+  }
+
+  /// Returns `true` iff the role `r` can move, hurl or shove.
+  pub fn has_role_actions(&self, r: Role) -> bool {
+    self.bits.has_role_actions(r)
+  }
+
   /// Returns an iterator over all actions that may be performed by the piece
   /// that is currently at `position`. If there is no piece there, then the
   /// iterator will be empty.
@@ -255,18 +304,45 @@ impl Cells {
       &Action::ProposeEnd => (),
       &Action::HandleEndProposal(_) => (),
       &Action::Move(start, end) => {
-        self[end] = self[start];
-        self[start] = Content::Empty;
+        self.set(end, self[start]);
+        self.set(start, Content::Empty);
       }
       &Action::Hurl(start, end) => {
-        self[end] = self[start];
-        self[start] = Content::Empty;
+        self.set(end, self[start]);
+        self.set(start, Content::Empty);
       }
       &Action::Shove(start, end, len, ref captured) => {
-        self[end] = self[start];
-        self[start] = Content::Empty;
+        self.set(end, self[start]);
+        self.set(start, Content::Empty);
         for i in 0..len {
-          self[captured[i as usize]] = Content::Empty;
+          self.set(captured[i as usize], Content::Empty);
+        }
+      }
+    }
+  }
+
+  /// Reverses `a`, which must be the last action that was done on this board.
+  /// Every action records where its piece started, and a capture can only take
+  /// pieces of one kind, so no other information is needed.
+  pub fn undo_action(&mut self, a: &Action) {
+    match *a {
+      Action::ProposeEnd => (),
+      Action::HandleEndProposal(_) => (),
+      Action::Move(start, end) => {
+        self.set(start, self[end]);
+        self.set(end, Content::Empty);
+      }
+      Action::Hurl(start, end) => {
+        // A hurl always captures the troll that was at its end.
+        self.set(start, self[end]);
+        self.set(end, Content::Occupied(Token::Troll));
+      }
+      Action::Shove(start, end, len, ref captured) => {
+        // A shove always captures dwarfs.
+        self.set(start, self[end]);
+        self.set(end, Content::Empty);
+        for i in 0..len {
+          self.set(captured[i as usize], Content::Occupied(Token::Dwarf));
         }
       }
     }
@@ -318,12 +394,14 @@ d_____________d
 impl Clone for Cells {
   fn clone(&self) -> Self {
     let mut other = Cells::new();
-    other.cells.clone_from_slice(&self.cells);
+    other.clone_from(self);
     other
   }
 
   fn clone_from(&mut self, source: &Cells) {
     self.cells.clone_from_slice(&source.cells);
+    self.keys = source.keys;
+    self.bits = source.bits;
   }
 }
 
@@ -335,12 +413,6 @@ impl Index<Coordinate> for Cells {
   }
 }
 
-impl IndexMut<Coordinate> for Cells {
-  fn index_mut(&mut self, c: Coordinate) -> &mut Content {
-    &mut self.cells[c.index()]
-  }
-}
-
 pub trait CellEquivalence: fmt::Debug + Send + Sync {
   fn hash_board(&self, board: &Cells) -> u64;
   fn boards_equal(&self, b1: &Cells, b2: &Cells) -> bool;
@@ -351,15 +423,7 @@ pub struct SimpleEquivalence;
 
 impl CellEquivalence for SimpleEquivalence {
   fn hash_board(&self, board: &Cells) -> u64 {
-    let mut state = std::collections::hash_map::DefaultHasher::new();
-    for row in 0u8..15u8 {
-      for col in 0u8..15u8 {
-        if let Some(c) = Coordinate::new(row, col) {
-          board[c].hash(&mut state)
-        }
-      }
-    }
-    state.finish()
+    board.keys().key()
   }
 
   fn boards_equal(&self, b1: &Cells, b2: &Cells) -> bool {
@@ -381,37 +445,7 @@ pub struct TranspositionalEquivalence;
 
 impl CellEquivalence for TranspositionalEquivalence {
   fn hash_board(&self, board: &Cells) -> u64 {
-    let mut state = std::collections::hash_map::DefaultHasher::new();
-    let mut hashers = [
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-      DefaultHasher::new(),
-    ];
-    for c in Coordinate::all() {
-      for (i, v) in Convolution::all().iter().enumerate() {
-        board[v.convolve(*c)].hash(&mut hashers[i]);
-      }
-    }
-    let mut hash_values = [
-      hashers[0].finish(),
-      hashers[1].finish(),
-      hashers[2].finish(),
-      hashers[3].finish(),
-      hashers[4].finish(),
-      hashers[5].finish(),
-      hashers[6].finish(),
-      hashers[7].finish(),
-    ];
-    hash_values.sort();
-    for v in hash_values.into_iter() {
-      state.write_u64(*v);
-    }
-    state.finish()
+    board.keys().canonical_key()
   }
 
   fn boards_equal(&self, b1: &Cells, b2: &Cells) -> bool {
@@ -461,13 +495,14 @@ pub fn decode_board(encoded: &str) -> Cells {
     for col in 0u8..15u8 {
       let value = chars.next().unwrap();
       if let Some(c) = Coordinate::new(row, col) {
-        board[c] = match value {
+        let content = match value {
           'T' => Content::Occupied(Token::Troll),
           'd' => Content::Occupied(Token::Dwarf),
           'O' => Content::Occupied(Token::Stone),
           '_' => Content::Empty,
           x @ _ => panic!("Unrecognized character '{}' for coordinate {:?}", x, c),
-        }
+        };
+        board.set(c, content);
       } else {
         assert!(
           value == '.',
@@ -511,6 +546,31 @@ impl fmt::Debug for Cells {
   }
 }
 
+/// Plays a fixed game of up to `plies` moves from the starting board, dwarves
+/// first, and returns each position along with the action played from it. The
+/// game captures whenever it can, so that tests walking it see hurls and
+/// shoves, and otherwise picks a move that varies from ply to ply.
+#[cfg(test)]
+pub(crate) fn scripted_game(plies: usize) -> Vec<(Cells, Action)> {
+  let mut cells = Cells::default();
+  let mut role = Role::Dwarf;
+  let mut game = Vec::new();
+  for ply in 0..plies {
+    let actions: Vec<Action> = cells.role_actions(role, false).collect();
+    if actions.is_empty() {
+      break;
+    }
+    let action = match actions.iter().find(|a| a.is_hurl() || a.is_shove()) {
+      Some(a) => *a,
+      None => actions[(ply * 7) % actions.len()],
+    };
+    game.push((cells.clone(), action));
+    cells.do_action(&action);
+    role = role.toggle();
+  }
+  game
+}
+
 // #[cfg(test)]
 // impl Arbitrary for Cells {
 //   fn arbitrary<G: Gen>(g: &mut G) -> Self {
@@ -537,7 +597,7 @@ impl fmt::Debug for Cells {
 
 #[cfg(test)]
 mod test {
-  use super::{decode_board, Cells, Content, Token};
+  use super::{decode_board, format_board, scripted_game, Cells, Content, Token};
   use crate::actions::Action;
   use crate::coordinate::{Coordinate, Direction};
   use crate::util;
@@ -573,6 +633,25 @@ d_____________d
     }
   }
 
+  #[test]
+  fn keys_follow_actions() {
+    let mut cells = Cells::default();
+    let mut history = Vec::new();
+    for (position, action) in scripted_game(300) {
+      assert_eq!(position.keys(), cells.keys());
+      history.push((action, *cells.keys()));
+      cells.do_action(&action);
+      // Rebuilding the board from scratch gives the same keys.
+      assert_eq!(decode_board(&format_board(&cells)).keys(), cells.keys());
+    }
+    assert!(!history.is_empty());
+    while let Some((action, keys)) = history.pop() {
+      cells.undo_action(&action);
+      assert_eq!(&keys, cells.keys());
+    }
+    assert_eq!(Cells::default().keys(), cells.keys());
+  }
+
   // #[quickcheck]
   fn dwarf_can_move(cells: Cells) -> bool {
     for start in Coordinate::all().iter() {
@@ -7,10 +7,12 @@
 pub mod agent;
 #[macro_use] pub mod coordinate;
 #[macro_use] pub mod actions;
+pub mod bitboard;
 pub mod board;
 pub mod end;
 pub mod state;
 pub mod util;
+pub mod zobrist;
 
 #[cfg(any(feature = "ai", feature = "ai-mcts"))] pub mod ai;
 
@@ -7,6 +7,15 @@ use r4::iterate;
 
 use std::hash::{Hash, Hasher};
 
+/// What [State::undo_action](struct.State.html#method.undo_action) needs to
+/// reverse an action: whether an end to the game had been proposed, and how
+/// the proposal was handled, before the action.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Undo {
+  proposed_terminate: bool,
+  terminate_decision: Option<end::Decision>,
+}
+
 #[derive(Debug)]
 pub struct State {
   board: Cells,
@@ -50,6 +59,23 @@ impl State {
     handle_end_proposal.chain(regular_moves)
   }
 
+  /// Replaces the contents of `actions` with the actions of the active role,
+  /// in the same order as `actions()`, without allocating unless `actions`
+  /// must grow.
+  pub fn fill_actions(&self, actions: &mut Vec<Action>) {
+    actions.clear();
+    if self.proposed_terminate && self.terminate_decision.is_none() {
+      actions.push(Action::HandleEndProposal(end::Decision::Accept));
+      actions.push(Action::HandleEndProposal(end::Decision::Decline));
+    } else {
+      self.board.append_role_actions(
+        *self.active_role(),
+        self.terminate_decision.is_none(),
+        actions,
+      );
+    }
+  }
+
   pub fn position_actions<'s>(&'s self, position: Coordinate) -> impl Iterator<Item = Action> + 's {
     self.board.position_actions(position)
   }
@@ -84,7 +110,12 @@ impl State {
   //   self.terminate_decision = convolved.terminate_decision;
   // }
 
-  pub fn do_action(&mut self, a: &Action) {
+  /// Does `a`, and returns what `undo_action` needs to reverse it.
+  pub fn do_action(&mut self, a: &Action) -> Undo {
+    let undo = Undo {
+      proposed_terminate: self.proposed_terminate,
+      terminate_decision: self.terminate_decision,
+    };
     match a {
       &Action::ProposeEnd => self.proposed_terminate = true,
       &Action::HandleEndProposal(d) => self.terminate_decision = Some(d),
@@ -95,12 +126,22 @@ impl State {
       }
     }
     self.toggle_active_role();
+    undo
+  }
+
+  /// Reverses `a`, which must be the last action that was done on this state,
+  /// given what `do_action` returned for it.
+  pub fn undo_action(&mut self, a: &Action, undo: Undo) {
+    self.toggle_active_role();
+    self.board.undo_action(a);
+    self.proposed_terminate = undo.proposed_terminate;
+    self.terminate_decision = undo.terminate_decision;
   }
 
   pub fn terminated(&self) -> bool {
     self.terminate_decision == Some(end::Decision::Accept)
-      || self.board.role_actions(Role::Dwarf, false).count() == 0
-      || self.board.role_actions(Role::Troll, false).count() == 0
+      || !self.board.has_role_actions(Role::Dwarf)
+      || !self.board.has_role_actions(Role::Troll)
   }
 
   pub fn board(&self) -> &Cells {
@@ -334,6 +375,63 @@ mod test {
     assert!(available_actions.contains(&Action::ProposeEnd));
   }
 
+  #[test]
+  fn undo_restores_state() {
+    let mut state = new_simple_state();
+    let mut history = Vec::new();
+    let mut captures = 0;
+    for (_, action) in board::scripted_game(300) {
+      // The scripted game captures whenever possible, so captures are undone
+      // too.
+      if action.is_hurl() || action.is_shove() {
+        captures += 1;
+      }
+      let before = state.clone();
+      let undo = state.do_action(&action);
+      history.push((action, undo, before));
+    }
+    assert!(captures > 0);
+    while let Some((action, undo, before)) = history.pop() {
+      state.undo_action(&action, undo);
+      assert!(state == before);
+      assert_eq!(before.score(Role::Dwarf), state.score(Role::Dwarf));
+      assert_eq!(before.score(Role::Troll), state.score(Role::Troll));
+    }
+    assert!(state == new_simple_state());
+  }
+
+  #[test]
+  fn undo_restores_end_proposal() {
+    let mut state = new_simple_state();
+    state.do_action(&Action::ProposeEnd);
+    state.do_action(&Action::HandleEndProposal(end::Decision::Decline));
+    let before = state.clone();
+    let action = state.actions().next().unwrap();
+    let undo = state.do_action(&action);
+    assert!(!state.opponent_proposed_end());
+    state.undo_action(&action, undo);
+    assert!(state == before);
+    assert!(state.opponent_proposed_end());
+  }
+
+  #[test]
+  fn fill_actions_matches_actions() {
+    let mut state = new_simple_state();
+    let mut buffer = vec![Action::ProposeEnd];
+    for (_, action) in board::scripted_game(300) {
+      state.fill_actions(&mut buffer);
+      assert_eq!(state.actions().collect::<Vec<Action>>(), buffer);
+      state.do_action(&action);
+    }
+    state.fill_actions(&mut buffer);
+    assert_eq!(state.actions().collect::<Vec<Action>>(), buffer);
+    let mut state = new_simple_state();
+    state.do_action(&Action::ProposeEnd);
+    state.fill_actions(&mut buffer);
+    assert_eq!(state.actions().collect::<Vec<Action>>(), buffer);
+    assert_eq!(2, buffer.len());
+  }
+
   #[test]
   fn compute_score() {
     let state = new_simple_state();
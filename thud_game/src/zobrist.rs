@@ -0,0 +1,141 @@
+//! Zobrist hashing of Thud boards.
+//!
+//! Each pairing of a coordinate and a token has a fixed pseudorandom key, and
+//! the key of a board is the XOR of the keys of its occupied cells. Moving a
+//! token changes the key of a board by XORing in the keys of the cells that
+//! changed, so a board's key can be kept up to date without re-scanning the
+//! board.
+//!
+//! [BoardKeys](struct.BoardKeys.html) keeps one such key for each of the 8
+//! [Convolution](../coordinate/struct.Convolution.html)s: the key of the board
+//! as seen through that convolution. Boards that are reflections or rotations
+//! of each other have the same set of keys, so the least of them is a hash that
+//! does not depend on the symmetry of the board.
+
+use crate::board::Token;
+use crate::coordinate::{Convolution, Coordinate};
+
+use lazy_static::lazy_static;
+
+const TOKEN_COUNT: usize = 3;
+const CELL_COUNT: usize = 165;
+
+/// Returns the SplitMix64 output for `seed`. Consecutive seeds give
+/// well-mixed, independent-looking keys.
+fn split_mix(seed: u64) -> u64 {
+  let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
+
+fn token_index(t: Token) -> usize {
+  match t {
+    Token::Stone => 0,
+    Token::Dwarf => 1,
+    Token::Troll => 2,
+  }
+}
+
+lazy_static! {
+  /// The key of each coordinate and token, for the board as seen through each
+  /// convolution. A token at `c` appears at `v.inverse(c)` when seen through
+  /// the convolution `v`, so it contributes the key of that coordinate.
+  static ref KEYS: Vec<[[u64; TOKEN_COUNT]; CELL_COUNT]> = {
+    let mut base = [[0u64; TOKEN_COUNT]; CELL_COUNT];
+    for (index, keys) in base.iter_mut().enumerate() {
+      for (token, key) in keys.iter_mut().enumerate() {
+        *key = split_mix((index * TOKEN_COUNT + token) as u64);
+      }
+    }
+    Convolution::all()
+      .iter()
+      .map(|v| {
+        let mut keys = [[0u64; TOKEN_COUNT]; CELL_COUNT];
+        for c in Coordinate::all() {
+          keys[c.index()] = base[v.inverse(*c).index()];
+        }
+        keys
+      })
+      .collect()
+  };
+}
+
+/// The Zobrist keys of a board, as seen through each of the 8 convolutions.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BoardKeys {
+  keys: [u64; 8],
+}
+
+impl BoardKeys {
+  /// Returns the keys of an empty board.
+  pub fn empty() -> Self {
+    BoardKeys { keys: [0; 8] }
+  }
+
+  /// Updates the keys for `t` being added to or removed from `c`.
+  pub fn toggle(&mut self, c: Coordinate, t: Token) {
+    let token = token_index(t);
+    for (key, keys) in self.keys.iter_mut().zip(KEYS.iter()) {
+      *key ^= keys[c.index()][token];
+    }
+  }
+
+  /// Returns the key of the board as it is.
+  pub fn key(&self) -> u64 {
+    self.keys[0]
+  }
+
+  /// Returns the least key of the board under any convolution. This is the
+  /// same for boards that are reflections or rotations of each other.
+  pub fn canonical_key(&self) -> u64 {
+    *self.keys.iter().min().unwrap()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::BoardKeys;
+  use crate::board::{Content, Token};
+  use crate::coordinate::{Convolution, Coordinate};
+
+  #[test]
+  fn toggling_twice_restores_keys() {
+    let mut keys = BoardKeys::empty();
+    keys.toggle(coordinate_literal!(3, 4), Token::Dwarf);
+    assert_ne!(BoardKeys::empty(), keys);
+    keys.toggle(coordinate_literal!(7, 7), Token::Stone);
+    keys.toggle(coordinate_literal!(3, 4), Token::Dwarf);
+    keys.toggle(coordinate_literal!(7, 7), Token::Stone);
+    assert_eq!(BoardKeys::empty(), keys);
+  }
+
+  #[test]
+  fn convolved_boards_have_same_canonical_key() {
+    let board = crate::board::Cells::default();
+    let mut keys = BoardKeys::empty();
+    let placed = [
+      (coordinate_literal!(2, 5), Token::Dwarf),
+      (coordinate_literal!(6, 7), Token::Troll),
+      (coordinate_literal!(7, 7), Token::Stone),
+    ];
+    for &(c, t) in placed.iter() {
+      keys.toggle(c, t);
+    }
+    for v in Convolution::all() {
+      let mut convolved = BoardKeys::empty();
+      for &(c, t) in placed.iter() {
+        convolved.toggle(v.convolve(c), t);
+      }
+      assert_eq!(keys.canonical_key(), convolved.canonical_key());
+    }
+    // The starting board is symmetric, so each convolution sees it the same.
+    let mut start = BoardKeys::empty();
+    for c in Coordinate::all() {
+      if let Content::Occupied(t) = board[*c] {
+        start.toggle(*c, t);
+      }
+    }
+    assert!(start.keys.iter().all(|&key| key == start.key()));
+  }
+}
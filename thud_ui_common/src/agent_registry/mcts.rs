@@ -1,15 +1,56 @@
 use crate::agent_registry::{AgentBuilder, Error};
 use clap::{App, Arg, ArgMatches};
+use mcts::budget::SearchBudget;
+use mcts::evict::GraphLimit;
+use rand::{RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+use std::time::Duration;
 use thud_game;
 
+/// Default RAVE equivalence parameter, used when the RAVE rollout policy is
+/// selected without an explicit value.
+const DEFAULT_RAVE_EQUIVALENCE: f64 = 1000.0;
+
+/// Default progressive widening exponent, used when a widening constant is
+/// given without an explicit exponent.
+const DEFAULT_WIDENING_EXPONENT: f64 = 0.5;
+
+/// Default maximum score margin for linear payoff normalisation, which is the
+/// total value of either side's pieces on the standard board.
+const DEFAULT_LINEAR_MAX_MARGIN: f64 = 32.0;
+
+/// Default score margin scale for sigmoid payoff normalisation.
+const DEFAULT_SIGMOID_SCALE: f64 = 4.0;
+
 pub struct MctsAgentBuilder {
   name: String,
   iteration_count_flag: String,
+  move_time_flag: String,
+  node_count_flag: String,
   simulation_count_flag: String,
   simulation_thread_limit_flag: String,
+  search_thread_count_flag: String,
+  virtual_loss_flag: String,
+  root_ensemble_flag: String,
   exploration_bias_flag: String,
   compact_graph_flag: String,
+  graph_vertex_limit_flag: String,
+  graph_memory_flag: String,
   action_selection_flag: String,
+  rollout_policy_flag: String,
+  rave_equivalence_flag: String,
+  widening_constant_flag: String,
+  widening_exponent_flag: String,
+  playout_policy_flag: String,
+  playout_epsilon_flag: String,
+  playout_temperature_flag: String,
+  max_playout_depth_flag: String,
+  solver_flag: String,
+  payoff_normalization_flag: String,
+  normalization_scale_flag: String,
+  cycle_policy_flag: String,
+  objective_flag: String,
+  progress_interval_flag: String,
   rng_seed_flag: String,
 }
 
@@ -19,11 +60,32 @@ impl MctsAgentBuilder {
     MctsAgentBuilder {
       name: name.clone(),
       iteration_count_flag: format!("{}_iterations", name),
+      move_time_flag: format!("{}_move_time_ms", name),
+      node_count_flag: format!("{}_max_nodes", name),
       simulation_count_flag: format!("{}_simulations", name),
       simulation_thread_limit_flag: format!("{}_simulation_threads", name),
+      search_thread_count_flag: format!("{}_search_threads", name),
+      virtual_loss_flag: format!("{}_virtual_loss", name),
+      root_ensemble_flag: format!("{}_root_ensemble", name),
       exploration_bias_flag: format!("{}_explore_bias", name),
       compact_graph_flag: format!("{}_compact_search_graph", name),
+      graph_vertex_limit_flag: format!("{}_graph_vertex_limit", name),
+      graph_memory_flag: format!("{}_graph_memory_mb", name),
       action_selection_flag: format!("{}_action_selection", name),
+      rollout_policy_flag: format!("{}_rollout_policy", name),
+      rave_equivalence_flag: format!("{}_rave_equivalence", name),
+      widening_constant_flag: format!("{}_widening_constant", name),
+      widening_exponent_flag: format!("{}_widening_exponent", name),
+      playout_policy_flag: format!("{}_playout_policy", name),
+      playout_epsilon_flag: format!("{}_playout_epsilon", name),
+      playout_temperature_flag: format!("{}_playout_temperature", name),
+      max_playout_depth_flag: format!("{}_max_playout_depth", name),
+      solver_flag: format!("{}_solver", name),
+      payoff_normalization_flag: format!("{}_payoff_normalization", name),
+      normalization_scale_flag: format!("{}_normalization_scale", name),
+      cycle_policy_flag: format!("{}_cycle_policy", name),
+      objective_flag: format!("{}_objective", name),
+      progress_interval_flag: format!("{}_progress_interval", name),
       rng_seed_flag: format!("{}_rng_seed", name),
     }
   }
@@ -41,7 +103,18 @@ impl AgentBuilder for MctsAgentBuilder {
     app.arg(Arg::with_name(&self.iteration_count_flag)
             .long(&self.iteration_count_flag)
             .value_name("COUNT")
-            .help("Number of iterations of MCTS search for the agent"))
+            .required(false)
+            .help("Maximum number of iterations of MCTS search for the agent per move"))
+      .arg(Arg::with_name(&self.move_time_flag)
+           .long(&self.move_time_flag)
+           .value_name("MILLISECONDS")
+           .required(false)
+           .help("Maximum wall-clock time of MCTS search for the agent per move"))
+      .arg(Arg::with_name(&self.node_count_flag)
+           .long(&self.node_count_flag)
+           .value_name("NODES")
+           .required(false)
+           .help("Search graph size at which the agent stops MCTS search"))
       .arg(Arg::with_name(&self.simulation_count_flag)
            .long(&self.simulation_count_flag)
            .value_name("COUNT")
@@ -50,6 +123,21 @@ impl AgentBuilder for MctsAgentBuilder {
            .long(&self.simulation_thread_limit_flag)
            .value_name("THREADS")
            .help("Maximum number of threads to run MCTS simulations in"))
+      .arg(Arg::with_name(&self.search_thread_count_flag)
+           .long(&self.search_thread_count_flag)
+           .value_name("THREADS")
+           .required(false)
+           .help("Number of threads to run tree-parallel MCTS search in (default 1)"))
+      .arg(Arg::with_name(&self.virtual_loss_flag)
+           .long(&self.virtual_loss_flag)
+           .value_name("VISITS")
+           .required(false)
+           .help("Virtual loss applied to rollout paths during tree-parallel MCTS search (default 1)"))
+      .arg(Arg::with_name(&self.root_ensemble_flag)
+           .long(&self.root_ensemble_flag)
+           .value_name("SEARCHES")
+           .required(false)
+           .help("Number of independent MCTS searches to run on separate threads and merge (default: a single search)"))
       .arg(Arg::with_name(&self.exploration_bias_flag)
            .long(&self.exploration_bias_flag)
            .value_name("BIAS")
@@ -57,16 +145,96 @@ impl AgentBuilder for MctsAgentBuilder {
       .arg(Arg::with_name(&self.compact_graph_flag)
            .long(&self.compact_graph_flag)
            .value_name("PRUNE|CLEAR|RETAIN")
-           .help("Search graph compaction for the agent to use between rounds of MCTS"))
+           .help("Search graph compaction for the agent to use between rounds of MCTS (RETAIN cannot be combined with a node limit)"))
+      .arg(Arg::with_name(&self.graph_vertex_limit_flag)
+           .long(&self.graph_vertex_limit_flag)
+           .value_name("VERTICES")
+           .required(false)
+           .help("Search graph size at which the agent evicts its least-visited vertices during MCTS search"))
+      .arg(Arg::with_name(&self.graph_memory_flag)
+           .long(&self.graph_memory_flag)
+           .value_name("MEGABYTES")
+           .required(false)
+           .help("Estimated search graph memory use at which the agent evicts its least-visited vertices during MCTS search"))
       .arg(Arg::with_name(&self.action_selection_flag)
            .long(&self.action_selection_flag)
            .value_name("UCB|VISIT_COUNT")
            .help("Action selection criterion for the agent to use when selecting the action to take after MCTS statistics are gathered"))
+      .arg(Arg::with_name(&self.rollout_policy_flag)
+           .long(&self.rollout_policy_flag)
+           .value_name("UCB|PUCT|RAVE|UCB1_TUNED|UCB_V")
+           .required(false)
+           .help("Policy for the agent to use when selecting children to follow during MCTS rollout (default UCB)"))
+      .arg(Arg::with_name(&self.rave_equivalence_flag)
+           .long(&self.rave_equivalence_flag)
+           .value_name("VISITS")
+           .required(false)
+           .help("Number of visits at which AMAF and ordinary statistics are weighted equally during RAVE rollout (default 1000)"))
+      .arg(Arg::with_name(&self.widening_constant_flag)
+           .long(&self.widening_constant_flag)
+           .value_name("CONSTANT")
+           .required(false)
+           .help("Enables progressive widening, allowing a vertex with N visits up to CONSTANT * (N + 1) ^ EXPONENT children (default: add all children at once)"))
+      .arg(Arg::with_name(&self.widening_exponent_flag)
+           .long(&self.widening_exponent_flag)
+           .value_name("EXPONENT")
+           .required(false)
+           .help("Exponent for progressive widening, between 0 and 1 (default 0.5)"))
+      .arg(Arg::with_name(&self.playout_policy_flag)
+           .long(&self.playout_policy_flag)
+           .value_name("UNIFORM|GREEDY|SOFTMAX")
+           .required(false)
+           .help("Policy for the agent to use when choosing actions during simulated playouts (default UNIFORM)"))
+      .arg(Arg::with_name(&self.playout_epsilon_flag)
+           .long(&self.playout_epsilon_flag)
+           .value_name("PROBABILITY")
+           .required(false)
+           .help("Probability of choosing a random action during GREEDY playouts (default 0.1)"))
+      .arg(Arg::with_name(&self.playout_temperature_flag)
+           .long(&self.playout_temperature_flag)
+           .value_name("TEMPERATURE")
+           .required(false)
+           .help("Temperature of SOFTMAX playouts, with higher temperatures giving more random playouts (default 1.0)"))
+      .arg(Arg::with_name(&self.max_playout_depth_flag)
+           .long(&self.max_playout_depth_flag)
+           .value_name("ACTIONS")
+           .required(false)
+           .help("Maximum number of actions in a simulated playout, after which the position is scored by material (default: play until the game ends)"))
+      .arg(Arg::with_name(&self.solver_flag)
+           .long(&self.solver_flag)
+           .takes_value(false)
+           .required(false)
+           .help("Prove wins, losses and exact scores during search, and play forced results when they are found"))
+      .arg(Arg::with_name(&self.payoff_normalization_flag)
+           .long(&self.payoff_normalization_flag)
+           .value_name("LINEAR|WIN_DRAW_LOSS|SIGMOID")
+           .required(false)
+           .help("Normalisation of the score margin of simulated payoffs before they are recorded, so that the exploration bias does not depend on the material on the board (default: raw scores)"))
+      .arg(Arg::with_name(&self.normalization_scale_flag)
+           .long(&self.normalization_scale_flag)
+           .value_name("MARGIN")
+           .required(false)
+           .help("Score margin that is normalised to a win for LINEAR normalisation (default 32), or the scale of margins for SIGMOID normalisation (default 4)"))
+      .arg(Arg::with_name(&self.cycle_policy_flag)
+           .long(&self.cycle_policy_flag)
+           .value_name("ERROR|DRAW|BACKOFF")
+           .required(false)
+           .help("How MCTS rollout handles a move back to a position already on its path: fail the search, score the position as a draw, or follow another move (default DRAW)"))
+      .arg(Arg::with_name(&self.objective_flag)
+           .long(&self.objective_flag)
+           .value_name("POINTS|WIN")
+           .required(false)
+           .help("Whether the agent plays to maximise its margin of points or to win, using the margin of points only to break ties (default POINTS)"))
+      .arg(Arg::with_name(&self.progress_interval_flag)
+           .long(&self.progress_interval_flag)
+           .value_name("ITERATIONS")
+           .required(false)
+           .help("Number of MCTS iterations between log messages that show the agent's best action so far (default: no progress messages)"))
       .arg(Arg::with_name(&self.rng_seed_flag)
            .long(&self.rng_seed_flag)
            .value_name("SEED")
            .required(false)
-           .help("Hex-valued RNG seed for the agent to use during MCTS"))
+           .help("Hex-valued RNG seed for the agent to use during MCTS (default: seeded by the operating system). With the same seed, an agent that has one search thread and no move time limit proposes the same actions"))
   }
 
   fn build(&self, matches: &ArgMatches) -> crate::agent_registry::Result {
@@ -130,17 +298,273 @@ impl AgentBuilder for MctsAgentBuilder {
         })
       }
     };
+    let search_thread_count = match matches
+      .value_of(&self.search_thread_count_flag)
+      .map(|s| s.parse::<u32>())
+    {
+      Some(Ok(c)) if c > 0 => c,
+      None => 1,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.search_thread_count_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.search_thread_count_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let virtual_loss = match matches
+      .value_of(&self.virtual_loss_flag)
+      .map(|s| s.parse::<u32>())
+    {
+      Some(Ok(c)) => c,
+      None => 1,
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.virtual_loss_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let rollout_policy = match matches.value_of(&self.rollout_policy_flag) {
+      Some(s) if s.to_lowercase() == "ucb" => thud_game::ai::mcts::RolloutPolicy::Ucb,
+      Some(s) if s.to_lowercase() == "puct" => thud_game::ai::mcts::RolloutPolicy::Puct,
+      Some(s) if s.to_lowercase() == "rave" => thud_game::ai::mcts::RolloutPolicy::Rave,
+      Some(s) if s.to_lowercase() == "ucb1_tuned" => thud_game::ai::mcts::RolloutPolicy::Ucb1Tuned,
+      Some(s) if s.to_lowercase() == "ucb_v" => thud_game::ai::mcts::RolloutPolicy::UcbV,
+      Some(_) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.rollout_policy_flag.clone(),
+          error: None,
+        })
+      }
+      None => thud_game::ai::mcts::RolloutPolicy::Ucb,
+    };
+    let rave_schedule = match matches
+      .value_of(&self.rave_equivalence_flag)
+      .map(|s| s.parse::<f64>())
+    {
+      Some(Ok(k)) if k > 0.0 => Some(mcts::rave::RaveSchedule::Equivalence(k)),
+      None => match rollout_policy {
+        thud_game::ai::mcts::RolloutPolicy::Rave => Some(mcts::rave::RaveSchedule::Equivalence(
+          DEFAULT_RAVE_EQUIVALENCE,
+        )),
+        _ => None,
+      },
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.rave_equivalence_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.rave_equivalence_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let widening_exponent = match matches
+      .value_of(&self.widening_exponent_flag)
+      .map(|s| s.parse::<f64>())
+    {
+      Some(Ok(e)) if (0.0..=1.0).contains(&e) => e,
+      None => DEFAULT_WIDENING_EXPONENT,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.widening_exponent_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.widening_exponent_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let progressive_widening = match matches
+      .value_of(&self.widening_constant_flag)
+      .map(|s| s.parse::<f64>())
+    {
+      Some(Ok(c)) if c > 0.0 => Some(mcts::widening::ProgressiveWidening {
+        constant: c,
+        exponent: widening_exponent,
+      }),
+      None => None,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.widening_constant_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.widening_constant_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let simulation_policy = match matches.value_of(&self.playout_policy_flag) {
+      Some(s) if s.to_lowercase() == "uniform" => thud_game::ai::mcts::SimulationPolicy::Uniform,
+      Some(s) if s.to_lowercase() == "greedy" => {
+        thud_game::ai::mcts::SimulationPolicy::EpsilonGreedy
+      }
+      Some(s) if s.to_lowercase() == "softmax" => thud_game::ai::mcts::SimulationPolicy::Softmax,
+      Some(_) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.playout_policy_flag.clone(),
+          error: None,
+        })
+      }
+      None => thud_game::ai::mcts::SimulationPolicy::Uniform,
+    };
+    let mut playout = mcts::playout::PlayoutSettings::default();
+    match matches
+      .value_of(&self.playout_epsilon_flag)
+      .map(|s| s.parse::<f64>())
+    {
+      Some(Ok(e)) if (0.0..=1.0).contains(&e) => playout.epsilon = e,
+      None => (),
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.playout_epsilon_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.playout_epsilon_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    }
+    match matches
+      .value_of(&self.playout_temperature_flag)
+      .map(|s| s.parse::<f64>())
+    {
+      Some(Ok(t)) if t > 0.0 => playout.temperature = t,
+      None => (),
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.playout_temperature_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.playout_temperature_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    }
+    let max_playout_depth = match matches
+      .value_of(&self.max_playout_depth_flag)
+      .map(|s| s.parse::<u32>())
+    {
+      Some(Ok(d)) => Some(d),
+      None => None,
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.max_playout_depth_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let normalization_scale = match matches
+      .value_of(&self.normalization_scale_flag)
+      .map(|s| s.parse::<f64>())
+    {
+      Some(Ok(m)) if m > 0.0 => Some(m),
+      None => None,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.normalization_scale_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.normalization_scale_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let payoff_normalization = match matches.value_of(&self.payoff_normalization_flag) {
+      Some(s) if s.to_lowercase() == "linear" => Some(mcts::normalize::Normalization::Linear {
+        max_margin: normalization_scale.unwrap_or(DEFAULT_LINEAR_MAX_MARGIN),
+      }),
+      Some(s) if s.to_lowercase() == "win_draw_loss" => {
+        Some(mcts::normalize::Normalization::WinDrawLoss)
+      }
+      Some(s) if s.to_lowercase() == "sigmoid" => Some(mcts::normalize::Normalization::Sigmoid {
+        scale: normalization_scale.unwrap_or(DEFAULT_SIGMOID_SCALE),
+      }),
+      Some(_) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.payoff_normalization_flag.clone(),
+          error: None,
+        })
+      }
+      None => None,
+    };
+    let cycle_policy = match matches.value_of(&self.cycle_policy_flag) {
+      Some(s) if s.to_lowercase() == "error" => mcts::rollout::CyclePolicy::Error,
+      Some(s) if s.to_lowercase() == "draw" => mcts::rollout::CyclePolicy::Draw,
+      Some(s) if s.to_lowercase() == "backoff" => mcts::rollout::CyclePolicy::Backoff,
+      Some(_) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.cycle_policy_flag.clone(),
+          error: None,
+        })
+      }
+      None => mcts::rollout::CyclePolicy::Draw,
+    };
     let settings = mcts::SearchSettings {
       simulation_count,
       simulation_thread_limit,
       explore_bias,
+      search_thread_count,
+      virtual_loss,
+      rave_schedule,
+      progressive_widening,
+      playout,
+      max_playout_depth,
+      solver: matches.is_present(&self.solver_flag),
+      payoff_normalization,
+      cycle_policy,
     };
     let iterations = match matches
       .value_of(&self.iteration_count_flag)
       .map(|s| s.parse::<u32>())
     {
-      Some(Ok(c)) if c > 0 => c,
-      Some(Ok(_)) | None => {
+      Some(Ok(c)) if c > 0 => Some(c),
+      None => None,
+      Some(Ok(_)) => {
         return Err(Error::InvalidAgentParameter {
           agent: self.name().into(),
           parameter: self.iteration_count_flag.clone(),
@@ -155,15 +579,74 @@ impl AgentBuilder for MctsAgentBuilder {
         })
       }
     };
-    let rng = match matches.value_of(&self.rng_seed_flag) {
-      Some(_) => {
+    let duration = match matches
+      .value_of(&self.move_time_flag)
+      .map(|s| s.parse::<u64>())
+    {
+      Some(Ok(t)) if t > 0 => Some(Duration::from_millis(t)),
+      None => None,
+      Some(Ok(_)) => {
         return Err(Error::InvalidAgentParameter {
           agent: self.name().into(),
-          parameter: self.rng_seed_flag.clone(),
+          parameter: self.move_time_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.move_time_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let node_count = match matches
+      .value_of(&self.node_count_flag)
+      .map(|s| s.parse::<usize>())
+    {
+      Some(Ok(c)) if c > 0 => Some(c),
+      None => None,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.node_count_flag.clone(),
           error: None,
         })
       }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.node_count_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let budget = SearchBudget {
+      iterations,
+      duration,
+      node_count,
+    };
+    if !budget.is_bounded() {
+      // At least one of the search budget flags must be given.
+      return Err(Error::InvalidAgentParameter {
+        agent: self.name().into(),
+        parameter: self.iteration_count_flag.clone(),
+        error: None,
+      });
+    }
+    let rng: Box<dyn RngCore + Send> = match matches
+      .value_of(&self.rng_seed_flag)
+      .map(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16))
+    {
+      Some(Ok(seed)) => Box::new(Pcg64::seed_from_u64(seed)),
       None => Box::new(rand::rngs::OsRng),
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.rng_seed_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
     };
     let action_select = match matches.value_of(&self.action_selection_flag) {
       Some(s) if s.to_lowercase() == "visit_count" => thud_game::ai::mcts::ActionSelect::VisitCount,
@@ -177,6 +660,27 @@ impl AgentBuilder for MctsAgentBuilder {
       }
       None => thud_game::ai::mcts::ActionSelect::VisitCount,
     };
+    let search_mode = match matches
+      .value_of(&self.root_ensemble_flag)
+      .map(|s| s.parse::<u32>())
+    {
+      Some(Ok(c)) if c > 0 => thud_game::ai::mcts::SearchMode::RootEnsemble(c),
+      None => thud_game::ai::mcts::SearchMode::SharedTree,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.root_ensemble_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.root_ensemble_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
     let graph_compact = match matches.value_of(&self.compact_graph_flag) {
       Some(s) if s.to_lowercase() == "prune" => thud_game::ai::mcts::GraphCompact::Prune,
       Some(s) if s.to_lowercase() == "clear" => thud_game::ai::mcts::GraphCompact::Clear,
@@ -190,43 +694,647 @@ impl AgentBuilder for MctsAgentBuilder {
       }
       None => thud_game::ai::mcts::GraphCompact::Prune,
     };
-    Ok(Box::new(thud_game::ai::mcts::Agent::new(
-      settings,
-      iterations,
-      rng,
-      action_select,
-      graph_compact,
-    )))
-  }
-}
-
-#[cfg(test)]
-mod test {
-  use super::MctsAgentBuilder;
-  use crate::agent_registry::AgentBuilder;
-  use clap::App;
-
-  #[test]
-  fn build_agent() {
-    let builder = MctsAgentBuilder::new("mcts");
-    let app = builder.register_args(App::new("test"));
-    let matches = app
-      .get_matches_from_safe(&[
-        "bin",
-        "--mcts_simulations",
-        "5",
-        "--mcts_simulation_threads",
-        "2",
-        "--mcts_iterations",
-        "31",
-        "--mcts_explore_bias",
-        "0.64",
-        "--mcts_compact_search_graph",
-        "PRUNE",
-        "--mcts_action_selection",
-        "VISIT_COUNT",
+    if let (thud_game::ai::mcts::GraphCompact::Retain, Some(_)) = (graph_compact, node_count) {
+      // The node limit applies to the whole graph, which a retained graph
+      // would keep at the limit from one move to the next.
+      return Err(Error::InvalidAgentParameter {
+        agent: self.name().into(),
+        parameter: self.compact_graph_flag.clone(),
+        error: None,
+      });
+    }
+    let graph_vertex_limit = match matches
+      .value_of(&self.graph_vertex_limit_flag)
+      .map(|s| s.parse::<usize>())
+    {
+      Some(Ok(c)) if c > 0 => Some(GraphLimit::Vertices(c)),
+      None => None,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.graph_vertex_limit_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.graph_vertex_limit_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let graph_memory_limit = match matches
+      .value_of(&self.graph_memory_flag)
+      .map(|s| s.parse::<usize>())
+    {
+      Some(Ok(m)) if m > 0 => Some(GraphLimit::Bytes(m.saturating_mul(1 << 20))),
+      None => None,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.graph_memory_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.graph_memory_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let graph_limit = match (graph_vertex_limit, graph_memory_limit) {
+      (Some(_), Some(_)) => {
+        // Only one kind of search graph limit may be given.
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.graph_memory_flag.clone(),
+          error: None,
+        });
+      }
+      (limit, None) | (None, limit) => limit,
+    };
+    let progress_interval = match matches
+      .value_of(&self.progress_interval_flag)
+      .map(|s| s.parse::<u32>())
+    {
+      Some(Ok(i)) if i > 0 => Some(i),
+      None => None,
+      Some(Ok(_)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.progress_interval_flag.clone(),
+          error: None,
+        })
+      }
+      Some(Err(e)) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.progress_interval_flag.clone(),
+          error: Some(Box::new(e)),
+        })
+      }
+    };
+    let observer = progress_interval.map(thud_game::ai::mcts::LoggingObserver::new);
+    let policy = thud_game::ai::mcts::SearchPolicy {
+      rollout: rollout_policy,
+      simulation: simulation_policy,
+    };
+    match matches.value_of(&self.objective_flag) {
+      Some(s) if s.to_lowercase() == "points" => (),
+      Some(s) if s.to_lowercase() == "win" => {
+        let mut agent = thud_game::ai::mcts::OutcomeAgent::new(
+          settings,
+          budget,
+          rng,
+          action_select,
+          graph_compact,
+          search_mode,
+          policy,
+        )
+        .with_graph_limit(graph_limit);
+        if let Some(observer) = observer {
+          agent.set_observer(Some(Box::new(observer)));
+        }
+        return Ok(Box::new(agent));
+      }
+      Some(_) => {
+        return Err(Error::InvalidAgentParameter {
+          agent: self.name().into(),
+          parameter: self.objective_flag.clone(),
+          error: None,
+        })
+      }
+      None => (),
+    }
+    let mut agent = thud_game::ai::mcts::Agent::new(
+      settings,
+      budget,
+      rng,
+      action_select,
+      graph_compact,
+      search_mode,
+      policy,
+    )
+    .with_graph_limit(graph_limit);
+    if let Some(observer) = observer {
+      agent.set_observer(Some(Box::new(observer)));
+    }
+    Ok(Box::new(agent))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::MctsAgentBuilder;
+  use crate::agent_registry::AgentBuilder;
+  use clap::App;
+
+  #[test]
+  fn build_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let app = builder.register_args(App::new("test"));
+    let matches = app
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_compact_search_graph",
+        "PRUNE",
+        "--mcts_action_selection",
+        "VISIT_COUNT",
+        "--mcts_rollout_policy",
+        "PUCT",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+  }
+
+  #[test]
+  fn build_root_ensemble_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let app = builder.register_args(App::new("test"));
+    let matches = app
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_root_ensemble",
+        "4",
       ])
       .unwrap();
     let _agent = builder.build(&matches).unwrap();
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_root_ensemble",
+        "0",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_agent_with_search_budget() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let args = |budget: &[&'static str]| {
+      let mut args = vec![
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_explore_bias",
+        "0.64",
+      ];
+      args.extend_from_slice(budget);
+      builder
+        .register_args(App::new("test"))
+        .get_matches_from_safe(&args)
+        .unwrap()
+    };
+    assert!(builder
+      .build(&args(&["--mcts_move_time_ms", "500"]))
+      .is_ok());
+    assert!(builder
+      .build(&args(&["--mcts_max_nodes", "100000"]))
+      .is_ok());
+    assert!(builder
+      .build(&args(&[
+        "--mcts_iterations",
+        "31",
+        "--mcts_move_time_ms",
+        "500"
+      ]))
+      .is_ok());
+    assert!(builder.build(&args(&[])).is_err());
+    assert!(builder.build(&args(&["--mcts_move_time_ms", "0"])).is_err());
+    assert!(builder
+      .build(&args(&[
+        "--mcts_max_nodes",
+        "100000",
+        "--mcts_compact_search_graph",
+        "RETAIN"
+      ]))
+      .is_err());
+    assert!(builder
+      .build(&args(&[
+        "--mcts_iterations",
+        "31",
+        "--mcts_compact_search_graph",
+        "RETAIN"
+      ]))
+      .is_ok());
+  }
+
+  #[test]
+  fn build_rave_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_rollout_policy",
+        "RAVE",
+        "--mcts_rave_equivalence",
+        "500",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+  }
+
+  #[test]
+  fn build_progressive_widening_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_widening_constant",
+        "2",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_widening_constant",
+        "2",
+        "--mcts_widening_exponent",
+        "1.5",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_playout_policy_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_playout_policy",
+        "SOFTMAX",
+        "--mcts_playout_temperature",
+        "0.5",
+        "--mcts_max_playout_depth",
+        "40",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_playout_policy",
+        "GREEDY",
+        "--mcts_playout_epsilon",
+        "2",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_solver_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_solver",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+  }
+
+  #[test]
+  fn build_normalized_agents() {
+    let builder = MctsAgentBuilder::new("mcts");
+    for normalization in ["LINEAR", "win_draw_loss", "SIGMOID"].iter() {
+      let matches = builder
+        .register_args(App::new("test"))
+        .get_matches_from_safe(&[
+          "bin",
+          "--mcts_simulations",
+          "5",
+          "--mcts_simulation_threads",
+          "2",
+          "--mcts_iterations",
+          "31",
+          "--mcts_explore_bias",
+          "0.64",
+          "--mcts_payoff_normalization",
+          normalization,
+          "--mcts_normalization_scale",
+          "12",
+        ])
+        .unwrap();
+      let _agent = builder.build(&matches).unwrap();
+    }
+
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_payoff_normalization",
+        "SIGMOID",
+        "--mcts_normalization_scale",
+        "0",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_outcome_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_objective",
+        "WIN",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_objective",
+        "DRAW",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_cycle_policy_agents() {
+    let builder = MctsAgentBuilder::new("mcts");
+    for policy in ["ERROR", "draw", "BACKOFF"].iter() {
+      let matches = builder
+        .register_args(App::new("test"))
+        .get_matches_from_safe(&[
+          "bin",
+          "--mcts_simulations",
+          "5",
+          "--mcts_simulation_threads",
+          "2",
+          "--mcts_iterations",
+          "31",
+          "--mcts_explore_bias",
+          "0.64",
+          "--mcts_cycle_policy",
+          policy,
+        ])
+        .unwrap();
+      let _agent = builder.build(&matches).unwrap();
+    }
+
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_cycle_policy",
+        "IGNORE",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_seeded_agent() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_rng_seed",
+        "0xc0ffee",
+      ])
+      .unwrap();
+    let _agent = builder.build(&matches).unwrap();
+
+    let matches = builder
+      .register_args(App::new("test"))
+      .get_matches_from_safe(&[
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+        "--mcts_rng_seed",
+        "coffee",
+      ])
+      .unwrap();
+    assert!(builder.build(&matches).is_err());
+  }
+
+  #[test]
+  fn build_variance_rollout_agents() {
+    let builder = MctsAgentBuilder::new("mcts");
+    for policy in ["UCB1_TUNED", "UCB_V"].iter() {
+      let matches = builder
+        .register_args(App::new("test"))
+        .get_matches_from_safe(&[
+          "bin",
+          "--mcts_simulations",
+          "5",
+          "--mcts_simulation_threads",
+          "2",
+          "--mcts_iterations",
+          "31",
+          "--mcts_explore_bias",
+          "0.64",
+          "--mcts_rollout_policy",
+          policy,
+        ])
+        .unwrap();
+      let _agent = builder.build(&matches).unwrap();
+    }
+  }
+
+  #[test]
+  fn build_graph_limit_agents() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let build = |limit: &[&str]| {
+      let mut args = vec![
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+      ];
+      args.extend_from_slice(limit);
+      let matches = builder
+        .register_args(App::new("test"))
+        .get_matches_from_safe(&args)
+        .unwrap();
+      builder.build(&matches)
+    };
+    assert!(build(&["--mcts_graph_vertex_limit", "100000"]).is_ok());
+    assert!(build(&["--mcts_graph_memory_mb", "512"]).is_ok());
+    assert!(build(&["--mcts_graph_vertex_limit", "0"]).is_err());
+    assert!(build(&["--mcts_graph_memory_mb", "lots"]).is_err());
+    assert!(build(&[
+      "--mcts_graph_vertex_limit",
+      "100000",
+      "--mcts_graph_memory_mb",
+      "512"
+    ])
+    .is_err());
+  }
+
+  #[test]
+  fn build_progress_interval_agents() {
+    let builder = MctsAgentBuilder::new("mcts");
+    let build = |extra: &[&str]| {
+      let mut args = vec![
+        "bin",
+        "--mcts_simulations",
+        "5",
+        "--mcts_simulation_threads",
+        "2",
+        "--mcts_iterations",
+        "31",
+        "--mcts_explore_bias",
+        "0.64",
+      ];
+      args.extend_from_slice(extra);
+      let matches = builder
+        .register_args(App::new("test"))
+        .get_matches_from_safe(&args)
+        .unwrap();
+      builder.build(&matches)
+    };
+    assert!(build(&["--mcts_progress_interval", "1000"]).is_ok());
+    assert!(build(&["--mcts_progress_interval", "10", "--mcts_objective", "WIN"]).is_ok());
+    assert!(build(&["--mcts_progress_interval", "0"]).is_err());
+    assert!(build(&["--mcts_progress_interval", "often"]).is_err());
   }
 }
@@ -136,3 +136,79 @@ where
     .collect();
   app.args(&populated_flags)
 }
+
+#[cfg(test)]
+mod test {
+  use super::InitialBoard;
+  use mcts::budget::SearchBudget;
+  use mcts::SearchSettings;
+  use rand::SeedableRng;
+  use thud_game::actions::Action;
+  use thud_game::agent::Agent as _;
+  use thud_game::ai::mcts::{
+    ActionSelect, Agent, GraphCompact, RolloutPolicy, SearchMode, SearchPolicy, SimulationPolicy,
+  };
+  use thud_game::board;
+  use thud_game::end::Decision;
+  use thud_game::state::State;
+
+  fn solver_agent(iterations: u32) -> Agent<rand_pcg::Pcg64> {
+    let settings = SearchSettings {
+      max_playout_depth: Some(20),
+      solver: true,
+      ..Default::default()
+    };
+    Agent::new(
+      settings,
+      SearchBudget::iterations(iterations),
+      rand_pcg::Pcg64::from_seed([1; 32]),
+      ActionSelect::VisitCount,
+      GraphCompact::Prune,
+      SearchMode::SharedTree,
+      SearchPolicy {
+        rollout: RolloutPolicy::Ucb,
+        simulation: SimulationPolicy::Uniform,
+      },
+    )
+  }
+
+  #[test]
+  fn solver_proves_dwarf_endgame() {
+    // The dwarves move first and can hurl onto the only troll.
+    let state = State::new(
+      InitialBoard::DwarfEndgame.cells(),
+      &board::TRANSPOSITIONAL_EQUIVALENCE,
+    );
+    let mut agent = solver_agent(2000);
+    let action = agent.propose_action(&state).unwrap();
+    assert!(action.is_hurl());
+    let forced = agent.forced_result().unwrap();
+    assert_eq!((3, 0), (forced.score_one, forced.score_two));
+    let proven = agent
+      .root_statistics()
+      .iter()
+      .find(|s| s.action == action)
+      .unwrap();
+    assert_eq!(Some(*forced), proven.proven);
+    // Search stops as soon as the root is solved.
+    assert!(agent.search_report().unwrap().iterations < 2000);
+  }
+
+  #[test]
+  fn solver_proves_declined_end_in_troll_endgame() {
+    // Declining an end proposal only hands play back to the side that made
+    // it, so the solver counts it as a draw. The trolls are ahead on points,
+    // so accepting is proven best.
+    let mut state = State::new(
+      InitialBoard::TrollEndgame.cells(),
+      &board::TRANSPOSITIONAL_EQUIVALENCE,
+    );
+    state.do_action(&Action::ProposeEnd);
+    let mut agent = solver_agent(300);
+    let action = agent.propose_action(&state).unwrap();
+    assert_eq!(Action::HandleEndProposal(Decision::Accept), action);
+    let forced = agent.forced_result().unwrap();
+    assert_eq!((7, 12), (forced.score_one, forced.score_two));
+    assert!(agent.search_report().unwrap().iterations < 300);
+  }
+}
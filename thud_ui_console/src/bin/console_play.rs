@@ -69,6 +69,9 @@ fn main() {
     println!("state: {:?}", state);
     let action = agent1.propose_action(&state).unwrap();
     println!("agent 1 proposes action: {:?}", action);
+    if let Some(report) = agent1.search_report() {
+      println!("agent 1 search: {}", report);
+    }
     state.do_action(&action);
     println!("state: {:?}", state);
     if state.terminated() {
@@ -76,6 +79,9 @@ fn main() {
     }
     let action = agent2.propose_action(&state).unwrap();
     println!("agent 2 proposes action: {:?}", action);
+    if let Some(report) = agent2.search_report() {
+      println!("agent 2 search: {}", report);
+    }
     state.do_action(&action);
   }
   println!("state has terminated: {:?}", state);